@@ -0,0 +1,417 @@
+use std::{cell::RefCell, convert::TryInto, fmt::Display};
+
+use utfx::{U16CStr, U16CString};
+
+use crate::key;
+use crate::sec::Security;
+use crate::util::{cmp_ignore_case, eq_ignore_case};
+use crate::value::{self, Data};
+use crate::Hive;
+
+use super::Backend;
+
+/// A registry that lives entirely in memory.
+///
+/// Every hive starts out empty. Key and value names are matched case-insensitively, as they are by
+/// Windows, and subkeys are enumerated in sorted order.
+#[derive(Debug, Default)]
+pub struct MemoryRegistry {
+    hives: RefCell<Vec<(Hive, Node)>>,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    name: U16CString,
+    subkeys: Vec<Node>,
+    values: Vec<(U16CString, Data)>,
+}
+
+/// An open key in a [`MemoryRegistry`](struct.MemoryRegistry.html).
+#[derive(Debug, Clone)]
+pub struct MemoryKey {
+    hive: Hive,
+    path: Vec<U16CString>,
+}
+
+impl Display for MemoryKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", &self.hive)?;
+        for segment in &self.path {
+            f.write_str(r"\")?;
+            f.write_str(&segment.to_string_lossy())?;
+        }
+        Ok(())
+    }
+}
+
+impl MemoryKey {
+    #[inline]
+    pub fn hive(&self) -> Hive {
+        self.hive
+    }
+
+    fn join(&self, path: &U16CStr) -> MemoryKey {
+        let mut full = self.path.clone();
+        full.extend(split_path(path));
+        MemoryKey {
+            hive: self.hive,
+            path: full,
+        }
+    }
+}
+
+impl Node {
+    fn new(name: U16CString) -> Node {
+        Node {
+            name,
+            subkeys: vec![],
+            values: vec![],
+        }
+    }
+
+    fn subkey_index(&self, name: &U16CStr) -> Result<usize, usize> {
+        self.subkeys
+            .binary_search_by(|x| cmp_ignore_case(x.name.as_slice(), name.as_slice()))
+    }
+
+    fn value_index(&self, name: &U16CStr) -> Option<usize> {
+        self.values
+            .iter()
+            .position(|(x, _)| eq_ignore_case(x.as_slice(), name.as_slice()))
+    }
+}
+
+#[inline]
+fn split_path(path: &U16CStr) -> impl Iterator<Item = U16CString> + '_ {
+    path.as_slice()
+        .split(|x| *x == u16::from(b'\\'))
+        .filter(|x| !x.is_empty())
+        .map(|x| unsafe { U16CString::from_vec_unchecked(x.to_vec()) })
+}
+
+#[inline]
+fn key_not_found(key: &MemoryKey) -> key::Error {
+    key::Error::NotFound(key.to_string(), std::io::ErrorKind::NotFound.into())
+}
+
+#[inline]
+fn value_not_found(name: &U16CStr) -> value::Error {
+    value::Error::NotFound(name.to_string_lossy(), std::io::ErrorKind::NotFound.into())
+}
+
+impl MemoryRegistry {
+    #[inline]
+    pub fn new() -> MemoryRegistry {
+        MemoryRegistry::default()
+    }
+
+    /// Runs `f` against the node for `key`, creating any missing keys along the way if `create` is set.
+    fn with_node<F, R>(&self, key: &MemoryKey, create: bool, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut Node) -> R,
+    {
+        let mut hives = self.hives.borrow_mut();
+        let index = match hives.iter().position(|(hive, _)| *hive == key.hive) {
+            Some(i) => i,
+            None => {
+                hives.push((key.hive, Node::new(U16CString::default())));
+                hives.len() - 1
+            }
+        };
+
+        let mut node = &mut hives[index].1;
+        for segment in &key.path {
+            let i = match node.subkey_index(segment) {
+                Ok(i) => i,
+                Err(i) if create => {
+                    node.subkeys.insert(i, Node::new(segment.clone()));
+                    i
+                }
+                Err(_) => return None,
+            };
+            node = &mut node.subkeys[i];
+        }
+
+        Some(f(node))
+    }
+}
+
+impl Backend for MemoryRegistry {
+    type Key = MemoryKey;
+
+    fn open_hive(&self, hive: Hive, _sec: Security) -> Result<MemoryKey, key::Error> {
+        Ok(MemoryKey { hive, path: vec![] })
+    }
+
+    fn open_key<P>(
+        &self,
+        base: &MemoryKey,
+        path: P,
+        _sec: Security,
+    ) -> Result<MemoryKey, key::Error>
+    where
+        P: TryInto<U16CString>,
+        P::Error: Into<key::Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        let key = base.join(&path);
+        self.with_node(&key, false, |_| ())
+            .ok_or_else(|| key_not_found(&key))?;
+        Ok(key)
+    }
+
+    fn create_key<P>(
+        &self,
+        base: &MemoryKey,
+        path: P,
+        _sec: Security,
+    ) -> Result<MemoryKey, key::Error>
+    where
+        P: TryInto<U16CString>,
+        P::Error: Into<key::Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        self.with_node(base, false, |_| ())
+            .ok_or_else(|| key_not_found(base))?;
+        let key = base.join(&path);
+        self.with_node(&key, true, |_| ());
+        Ok(key)
+    }
+
+    fn delete_key<P>(&self, base: &MemoryKey, path: P, is_recursive: bool) -> Result<(), key::Error>
+    where
+        P: TryInto<U16CString>,
+        P::Error: Into<key::Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        let key = base.join(&path);
+
+        // Like `RegDeleteTreeW`, a recursive delete of the key itself only empties it.
+        if path.is_empty() && is_recursive {
+            return self
+                .with_node(&key, false, |node| {
+                    node.subkeys.clear();
+                    node.values.clear();
+                })
+                .ok_or_else(|| key_not_found(&key));
+        }
+
+        let (name, parent_path) = match key.path.split_last() {
+            Some(v) => v,
+            None => {
+                return Err(key::Error::PermissionDenied(
+                    key.to_string(),
+                    std::io::ErrorKind::PermissionDenied.into(),
+                ))
+            }
+        };
+        let parent = MemoryKey {
+            hive: key.hive,
+            path: parent_path.to_vec(),
+        };
+
+        self.with_node(&parent, false, |node| match node.subkey_index(name) {
+            Ok(i) if !is_recursive && !node.subkeys[i].subkeys.is_empty() => {
+                Err(key::Error::PermissionDenied(
+                    key.to_string(),
+                    std::io::ErrorKind::PermissionDenied.into(),
+                ))
+            }
+            Ok(i) => {
+                node.subkeys.remove(i);
+                Ok(())
+            }
+            Err(_) => Err(key_not_found(&key)),
+        })
+        .unwrap_or_else(|| Err(key_not_found(&key)))
+    }
+
+    fn query_value<S>(&self, key: &MemoryKey, value_name: S) -> Result<Data, value::Error>
+    where
+        S: TryInto<U16CString>,
+        S::Error: Into<value::Error>,
+    {
+        let value_name = value_name.try_into().map_err(Into::into)?;
+        self.with_node(key, false, |node| {
+            node.value_index(&value_name)
+                .map(|i| node.values[i].1.clone())
+        })
+        .flatten()
+        .ok_or_else(|| value_not_found(&value_name))
+    }
+
+    fn set_value<S>(&self, key: &MemoryKey, value_name: S, data: &Data) -> Result<(), value::Error>
+    where
+        S: TryInto<U16CString>,
+        S::Error: Into<value::Error>,
+    {
+        let value_name = value_name.try_into().map_err(Into::into)?;
+        self.with_node(key, false, |node| match node.value_index(&value_name) {
+            Some(i) => node.values[i].1 = data.clone(),
+            None => node.values.push((value_name.clone(), data.clone())),
+        })
+        .ok_or_else(|| value_not_found(&value_name))
+    }
+
+    fn delete_value<S>(&self, key: &MemoryKey, value_name: S) -> Result<(), value::Error>
+    where
+        S: TryInto<U16CString>,
+        S::Error: Into<value::Error>,
+    {
+        let value_name = value_name.try_into().map_err(Into::into)?;
+        self.with_node(key, false, |node| {
+            node.value_index(&value_name).map(|i| node.values.remove(i))
+        })
+        .flatten()
+        .map(|_| ())
+        .ok_or_else(|| value_not_found(&value_name))
+    }
+
+    fn enum_key(&self, key: &MemoryKey, index: u32) -> Result<Option<U16CString>, key::Error> {
+        self.with_node(key, false, |node| {
+            node.subkeys.get(index as usize).map(|x| x.name.clone())
+        })
+        .ok_or_else(|| key_not_found(key))
+    }
+
+    fn enum_value(
+        &self,
+        key: &MemoryKey,
+        index: u32,
+    ) -> Result<Option<(U16CString, Data)>, value::Error> {
+        self.with_node(key, false, |node| node.values.get(index as usize).cloned())
+            .ok_or_else(|| {
+                value::Error::NotFound(key.to_string(), std::io::ErrorKind::NotFound.into())
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    fn names<B: Backend>(backend: &B, key: &B::Key) -> Vec<String> {
+        backend
+            .keys(key)
+            .map(|x| x.unwrap().to_string_lossy())
+            .collect()
+    }
+
+    #[test]
+    fn create_and_open() {
+        let reg = MemoryRegistry::new();
+        let root = reg.open_hive(Hive::CurrentUser, Security::Read).unwrap();
+        let key = reg
+            .create_key(&root, r"Software\Test\Nested", Security::AllAccess)
+            .unwrap();
+        assert_eq!(key.to_string(), r"HKEY_CURRENT_USER\Software\Test\Nested");
+
+        let opened = reg
+            .open_key(&root, r"SOFTWARE\test", Security::Read)
+            .unwrap();
+        assert_eq!(names(&reg, &opened), vec!["Nested"]);
+
+        assert!(matches!(
+            reg.open_key(&root, r"Software\Missing", Security::Read),
+            Err(key::Error::NotFound(..))
+        ));
+
+        let hklm = reg.open_hive(Hive::LocalMachine, Security::Read).unwrap();
+        assert!(reg.open_key(&hklm, "Software", Security::Read).is_err());
+    }
+
+    #[test]
+    fn keys_are_sorted() {
+        let reg = MemoryRegistry::new();
+        let root = reg.open_hive(Hive::LocalMachine, Security::Read).unwrap();
+        for name in &["b", "C", "a"] {
+            reg.create_key(&root, *name, Security::AllAccess).unwrap();
+        }
+        assert_eq!(names(&reg, &root), vec!["a", "b", "C"]);
+    }
+
+    #[test]
+    fn set_query_and_delete_values() {
+        let reg = MemoryRegistry::new();
+        let root = reg.open_hive(Hive::CurrentUser, Security::Read).unwrap();
+        let key = reg.create_key(&root, "Test", Security::AllAccess).unwrap();
+
+        reg.set_value(&key, "u32", &Data::U32(42)).unwrap();
+        reg.set_value(&key, "string", &Data::String("Meow".try_into().unwrap()))
+            .unwrap();
+        reg.set_value(&key, "U32", &Data::U32(7)).unwrap();
+
+        assert_eq!(reg.query_value(&key, "u32").unwrap(), Data::U32(7));
+        let values = reg
+            .values(&key)
+            .map(|x| x.map(|(name, data)| (name.to_string_lossy(), data)))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            values,
+            vec![
+                ("u32".to_string(), Data::U32(7)),
+                (
+                    "string".to_string(),
+                    Data::String("Meow".try_into().unwrap())
+                ),
+            ]
+        );
+
+        reg.delete_value(&key, "STRING").unwrap();
+        assert!(matches!(
+            reg.query_value(&key, "string"),
+            Err(value::Error::NotFound(..))
+        ));
+        assert!(reg.delete_value(&key, "string").is_err());
+    }
+
+    #[test]
+    fn enumeration_stops_after_error() {
+        let reg = MemoryRegistry::new();
+        let root = reg.open_hive(Hive::CurrentUser, Security::Read).unwrap();
+        let key = reg.create_key(&root, "Test", Security::AllAccess).unwrap();
+        reg.delete_key(&root, "Test", false).unwrap();
+
+        let keys = reg.keys(&key).take(3).collect::<Vec<_>>();
+        assert!(matches!(keys[..], [Err(key::Error::NotFound(..))]));
+        let values = reg.values(&key).take(3).collect::<Vec<_>>();
+        assert!(matches!(values[..], [Err(value::Error::NotFound(..))]));
+    }
+
+    #[test]
+    fn delete_keys() {
+        let reg = MemoryRegistry::new();
+        let root = reg.open_hive(Hive::CurrentUser, Security::Read).unwrap();
+        let key = reg
+            .create_key(&root, r"Test\A\B", Security::AllAccess)
+            .unwrap();
+
+        assert!(matches!(
+            reg.delete_key(&root, "Test", false),
+            Err(key::Error::PermissionDenied(..))
+        ));
+        reg.delete_key(&root, r"Test\A\B", false).unwrap();
+        assert!(reg.set_value(&key, "x", &Data::None).is_err());
+
+        reg.delete_key(&root, "Test", true).unwrap();
+        assert!(names(&reg, &root).is_empty());
+    }
+
+    #[test]
+    fn recursive_delete_of_self_empties_key() {
+        let reg = MemoryRegistry::new();
+        let root = reg.open_hive(Hive::CurrentUser, Security::Read).unwrap();
+        let key = reg
+            .create_key(&root, r"Test\A", Security::AllAccess)
+            .unwrap();
+        let test = reg.open_key(&root, "Test", Security::Read).unwrap();
+        reg.set_value(&test, "x", &Data::None).unwrap();
+
+        reg.delete_key(&test, "", true).unwrap();
+        assert!(names(&reg, &test).is_empty());
+        assert!(reg.values(&test).next().is_none());
+        assert!(reg.open_key(&key, "", Security::Read).is_err());
+    }
+}
@@ -0,0 +1,160 @@
+//! Pluggable storage for registry keys and values.
+//!
+//! Code that is written against the [`Backend`](trait.Backend.html) trait can run against the live
+//! Windows registry through [`Win32Registry`](struct.Win32Registry.html), or against a
+//! [`MemoryRegistry`](struct.MemoryRegistry.html) on any platform.
+
+use std::convert::TryInto;
+
+use utfx::U16CString;
+
+use crate::key;
+use crate::sec::Security;
+use crate::value::{self, Data};
+use crate::Hive;
+
+mod memory;
+#[cfg(windows)]
+mod win32;
+
+pub use memory::{MemoryKey, MemoryRegistry};
+#[cfg(windows)]
+pub use win32::Win32Registry;
+
+/// Operations on registry keys and values, independent of where they are stored.
+pub trait Backend {
+    /// An open handle to a key in this backend.
+    type Key;
+
+    /// Opens the root key of the given hive.
+    fn open_hive(&self, hive: Hive, sec: Security) -> Result<Self::Key, key::Error>;
+
+    fn open_key<P>(
+        &self,
+        base: &Self::Key,
+        path: P,
+        sec: Security,
+    ) -> Result<Self::Key, key::Error>
+    where
+        P: TryInto<U16CString>,
+        P::Error: Into<key::Error>;
+
+    fn create_key<P>(
+        &self,
+        base: &Self::Key,
+        path: P,
+        sec: Security,
+    ) -> Result<Self::Key, key::Error>
+    where
+        P: TryInto<U16CString>,
+        P::Error: Into<key::Error>;
+
+    fn delete_key<P>(
+        &self,
+        base: &Self::Key,
+        path: P,
+        is_recursive: bool,
+    ) -> Result<(), key::Error>
+    where
+        P: TryInto<U16CString>,
+        P::Error: Into<key::Error>;
+
+    fn query_value<S>(&self, key: &Self::Key, value_name: S) -> Result<Data, value::Error>
+    where
+        S: TryInto<U16CString>,
+        S::Error: Into<value::Error>;
+
+    fn set_value<S>(&self, key: &Self::Key, value_name: S, data: &Data) -> Result<(), value::Error>
+    where
+        S: TryInto<U16CString>,
+        S::Error: Into<value::Error>;
+
+    fn delete_value<S>(&self, key: &Self::Key, value_name: S) -> Result<(), value::Error>
+    where
+        S: TryInto<U16CString>,
+        S::Error: Into<value::Error>;
+
+    /// Returns the name of the subkey at `index`, or `None` once all subkeys have been enumerated.
+    fn enum_key(&self, key: &Self::Key, index: u32) -> Result<Option<U16CString>, key::Error>;
+
+    /// Returns the value at `index`, or `None` once all values have been enumerated.
+    fn enum_value(
+        &self,
+        key: &Self::Key,
+        index: u32,
+    ) -> Result<Option<(U16CString, Data)>, value::Error>;
+
+    /// Iterates over the names of all subkeys of `key`.
+    #[inline]
+    fn keys<'a>(&'a self, key: &'a Self::Key) -> Keys<'a, Self>
+    where
+        Self: Sized,
+    {
+        Keys {
+            backend: self,
+            key,
+            index: 0,
+            is_done: false,
+        }
+    }
+
+    /// Iterates over the names and data of all values of `key`.
+    #[inline]
+    fn values<'a>(&'a self, key: &'a Self::Key) -> Values<'a, Self>
+    where
+        Self: Sized,
+    {
+        Values {
+            backend: self,
+            key,
+            index: 0,
+            is_done: false,
+        }
+    }
+}
+
+/// An iterator over the subkey names of a key in a [`Backend`](trait.Backend.html).
+pub struct Keys<'a, B: Backend> {
+    backend: &'a B,
+    key: &'a B::Key,
+    index: u32,
+    is_done: bool,
+}
+
+impl<B: Backend> Iterator for Keys<'_, B> {
+    type Item = Result<U16CString, key::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.is_done {
+            return None;
+        }
+        let result = self.backend.enum_key(self.key, self.index).transpose()?;
+        self.index += 1;
+        // Enumeration stops at the first error, as the backend would most likely keep failing.
+        self.is_done = result.is_err();
+        Some(result)
+    }
+}
+
+/// An iterator over the values of a key in a [`Backend`](trait.Backend.html).
+pub struct Values<'a, B: Backend> {
+    backend: &'a B,
+    key: &'a B::Key,
+    index: u32,
+    is_done: bool,
+}
+
+impl<B: Backend> Iterator for Values<'_, B> {
+    type Item = Result<(U16CString, Data), value::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.is_done {
+            return None;
+        }
+        let result = self.backend.enum_value(self.key, self.index).transpose()?;
+        self.index += 1;
+        // Enumeration stops at the first error, as the backend would most likely keep failing.
+        self.is_done = result.is_err();
+        Some(result)
+    }
+}
@@ -0,0 +1,185 @@
+use std::{convert::TryInto, ptr::null_mut};
+
+use utfx::U16CString;
+use winapi::shared::winerror::ERROR_NO_MORE_ITEMS;
+use winapi::um::winreg::{RegEnumKeyExW, RegEnumValueW, RegQueryInfoKeyW};
+
+use crate::key::{self, RegKey};
+use crate::sec::Security;
+use crate::util::U16AlignedU8Vec;
+use crate::value::{self, Data};
+use crate::Hive;
+
+use super::Backend;
+
+/// The live Windows registry, accessed through [`RegKey`](../struct.RegKey.html) handles.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Win32Registry;
+
+impl Backend for Win32Registry {
+    type Key = RegKey;
+
+    #[inline]
+    fn open_hive(&self, hive: Hive, sec: Security) -> Result<RegKey, key::Error> {
+        hive.open("", sec)
+    }
+
+    #[inline]
+    fn open_key<P>(&self, base: &RegKey, path: P, sec: Security) -> Result<RegKey, key::Error>
+    where
+        P: TryInto<U16CString>,
+        P::Error: Into<key::Error>,
+    {
+        base.open(path, sec)
+    }
+
+    #[inline]
+    fn create_key<P>(&self, base: &RegKey, path: P, sec: Security) -> Result<RegKey, key::Error>
+    where
+        P: TryInto<U16CString>,
+        P::Error: Into<key::Error>,
+    {
+        base.create(path, sec)
+    }
+
+    #[inline]
+    fn delete_key<P>(&self, base: &RegKey, path: P, is_recursive: bool) -> Result<(), key::Error>
+    where
+        P: TryInto<U16CString>,
+        P::Error: Into<key::Error>,
+    {
+        base.delete(path, is_recursive)
+    }
+
+    #[inline]
+    fn query_value<S>(&self, key: &RegKey, value_name: S) -> Result<Data, value::Error>
+    where
+        S: TryInto<U16CString>,
+        S::Error: Into<value::Error>,
+    {
+        key.value_with_mode(value_name, value::ParseMode::PreserveRaw)
+    }
+
+    #[inline]
+    fn set_value<S>(&self, key: &RegKey, value_name: S, data: &Data) -> Result<(), value::Error>
+    where
+        S: TryInto<U16CString>,
+        S::Error: Into<value::Error>,
+    {
+        key.set_value(value_name, data)
+    }
+
+    #[inline]
+    fn delete_value<S>(&self, key: &RegKey, value_name: S) -> Result<(), value::Error>
+    where
+        S: TryInto<U16CString>,
+        S::Error: Into<value::Error>,
+    {
+        key.delete_value(value_name)
+    }
+
+    fn enum_key(&self, key: &RegKey, index: u32) -> Result<Option<U16CString>, key::Error> {
+        // Key names are limited to 255 characters.
+        let mut buf = vec![0u16; 256];
+        let mut len = buf.len() as u32;
+
+        let result = unsafe {
+            RegEnumKeyExW(
+                key.handle,
+                index,
+                buf.as_mut_ptr(),
+                &mut len,
+                null_mut(),
+                null_mut(),
+                null_mut(),
+                null_mut(),
+            )
+        };
+
+        if result == ERROR_NO_MORE_ITEMS as i32 {
+            return Ok(None);
+        }
+
+        if result != 0 {
+            return Err(key::Error::Unknown(
+                key.to_string(),
+                std::io::Error::from_raw_os_error(result),
+            ));
+        }
+
+        buf.truncate(len as usize);
+        Ok(Some(U16CString::new(buf)?))
+    }
+
+    fn enum_value(
+        &self,
+        key: &RegKey,
+        index: u32,
+    ) -> Result<Option<(U16CString, Data)>, value::Error> {
+        let mut max_value_name_len = 0u32;
+        let mut max_value_data_len = 0u32;
+
+        let result = unsafe {
+            RegQueryInfoKeyW(
+                key.handle,
+                null_mut(),
+                null_mut(),
+                null_mut(),
+                null_mut(),
+                null_mut(),
+                null_mut(),
+                null_mut(),
+                &mut max_value_name_len,
+                &mut max_value_data_len,
+                null_mut(),
+                null_mut(),
+            )
+        };
+
+        if result != 0 {
+            return Err(value::Error::Unknown(
+                key.to_string(),
+                std::io::Error::from_raw_os_error(result),
+            ));
+        }
+
+        let mut name_buf = vec![0u16; max_value_name_len as usize + 1];
+        let mut name_len = name_buf.len() as u32;
+        let mut data_buf = U16AlignedU8Vec::new(max_value_data_len as usize);
+        let mut data_len = data_buf.len() as u32;
+        let mut data_type = 0u32;
+
+        let result = unsafe {
+            RegEnumValueW(
+                key.handle,
+                index,
+                name_buf.as_mut_ptr(),
+                &mut name_len,
+                null_mut(),
+                &mut data_type,
+                data_buf.as_mut_ptr(),
+                &mut data_len,
+            )
+        };
+
+        if result == ERROR_NO_MORE_ITEMS as i32 {
+            return Ok(None);
+        }
+
+        if result != 0 {
+            return Err(value::Error::Unknown(
+                key.to_string(),
+                std::io::Error::from_raw_os_error(result),
+            ));
+        }
+
+        name_buf.truncate(name_len as usize);
+        let name = U16CString::new(name_buf)?;
+        let data = value::parse_value_type_data_with(
+            data_type,
+            &data_buf[..data_len as usize],
+            value::ParseMode::PreserveRaw,
+        )?;
+        Ok(Some((name, data)))
+    }
+}
@@ -0,0 +1,152 @@
+//! Evaluating which rights a security descriptor grants, the way `AccessCheck` does for a token.
+
+use super::{AceFlags, AceType, AclEntry, SecurityDescriptor, Sid};
+use crate::sec::Security;
+
+impl SecurityDescriptor {
+    /// The rights that the DACL grants to a principal holding every SID in `sids`, such as a user
+    /// along with its groups.
+    ///
+    /// ACEs are evaluated in order, so a right denied by an earlier ACE cannot be granted by a
+    /// later one. Inherit-only ACEs are skipped, since they only apply to subkeys, as are
+    /// [`AclEntry::Other`] entries, and generic rights are mapped onto registry rights. A missing
+    /// or null DACL grants everything, and the owner is always allowed to read and change the DACL
+    /// unless an `OWNER RIGHTS` ACE says otherwise.
+    pub fn granted_access(&self, sids: &[Sid]) -> Security {
+        // Both a null DACL and no DACL at all leave the key unprotected.
+        let dacl = match &self.dacl {
+            Some(v) => v,
+            None => return Security::AllAccess,
+        };
+
+        let owner_rights = Sid::new(3, &[4]);
+        let is_owner = self.owner.as_ref().is_some_and(|x| sids.contains(x));
+        let aces = dacl
+            .aces
+            .iter()
+            .filter_map(AclEntry::as_ace)
+            .filter(|x| !x.flags.contains(AceFlags::INHERIT_ONLY));
+
+        let mut granted = Security::empty();
+        let mut denied = Security::empty();
+        if is_owner && !aces.clone().any(|x| x.sid == owner_rights) {
+            granted = Security::ReadControl | Security::WriteDac;
+        }
+
+        for ace in aces {
+            let applies = sids.contains(&ace.sid) || (is_owner && ace.sid == owner_rights);
+            if !applies {
+                continue;
+            }
+
+            let mask = ace.mask.map_generic();
+            match ace.ace_type {
+                AceType::AccessAllowed => granted |= mask - denied,
+                AceType::AccessDenied => denied |= mask - granted,
+                _ => {}
+            }
+        }
+
+        granted
+    }
+
+    /// Whether a principal holding every SID in `sids` would be granted all of `desired`.
+    #[inline]
+    pub fn is_granted(&self, sids: &[Sid], desired: Security) -> bool {
+        let desired = desired.map_generic() - Security::MaximumAllowed;
+        self.granted_access(sids).contains(desired)
+    }
+}
+
+impl Security {
+    /// Replaces the generic rights with the registry rights they stand for.
+    pub fn map_generic(self) -> Security {
+        let mut mask = self
+            - (Security::GenericRead
+                | Security::GenericWrite
+                | Security::GenericExecute
+                | Security::GenericAll);
+        if self.contains(Security::GenericRead) {
+            mask |= Security::Read;
+        }
+        if self.contains(Security::GenericWrite) {
+            mask |= Security::Write;
+        }
+        if self.contains(Security::GenericExecute) {
+            mask |= Security::Execute;
+        }
+        if self.contains(Security::GenericAll) {
+            mask |= Security::AllAccess;
+        }
+        mask
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sid(s: &str) -> Sid {
+        s.parse().unwrap()
+    }
+
+    fn sd(sddl: &str) -> SecurityDescriptor {
+        SecurityDescriptor::from_sddl(sddl).unwrap()
+    }
+
+    #[test]
+    fn allow_and_deny() {
+        let users = [sid("S-1-1-0"), sid("S-1-5-11"), sid("S-1-5-32-545")];
+        let admins = [sid("S-1-1-0"), sid("S-1-5-32-544")];
+
+        let key = sd("O:BAG:SYD:(A;CI;KA;;;BA)(A;CI;KR;;;BU)(A;CI;0x2;;;AU)");
+        assert!(key.is_granted(&users, Security::SetValue));
+        assert!(!key.is_granted(&users, Security::CreateSubKey));
+        assert!(key.is_granted(&admins, Security::AllAccess));
+        assert_eq!(key.granted_access(&[sid("S-1-5-18")]), Security::empty());
+
+        // A deny ahead of an allow wins, but not the other way around.
+        let key = sd("D:(D;;DC;;;WD)(A;;KA;;;BU)");
+        assert!(!key.is_granted(&users, Security::SetValue));
+        assert!(key.is_granted(&users, Security::QueryValue));
+        let key = sd("D:(A;;KA;;;BU)(D;;DC;;;WD)");
+        assert!(key.is_granted(&users, Security::SetValue));
+    }
+
+    #[test]
+    fn inherit_only_and_generic_rights() {
+        let users = [sid("S-1-1-0")];
+
+        let key = sd("D:(A;CIIO;KA;;;WD)(A;CI;GR;;;WD)");
+        assert_eq!(key.granted_access(&users), Security::Read);
+        assert!(key.is_granted(&users, Security::GenericRead));
+        assert!(!key.is_granted(&users, Security::GenericWrite));
+
+        // Inherited ACEs count like any other.
+        let key = sd("D:AI(A;CIID;GW;;;WD)");
+        assert!(key.is_granted(&users, Security::SetValue));
+    }
+
+    #[test]
+    fn null_and_empty_dacls() {
+        let users = [sid("S-1-1-0")];
+        assert!(sd("D:NO_ACCESS_CONTROL").is_granted(&users, Security::AllAccess));
+        assert!(sd("O:SY").is_granted(&users, Security::AllAccess));
+        assert_eq!(sd("D:").granted_access(&users), Security::empty());
+    }
+
+    #[test]
+    fn owner_rights() {
+        let owner = [sid("S-1-5-21-1-2-3-1001")];
+
+        let key = sd("O:S-1-5-21-1-2-3-1001D:(D;;KA;;;S-1-5-21-1-2-3-1001)");
+        assert_eq!(
+            key.granted_access(&owner),
+            Security::ReadControl | Security::WriteDac
+        );
+
+        let key = sd("O:S-1-5-21-1-2-3-1001D:(A;;KR;;;OW)");
+        assert_eq!(key.granted_access(&owner), Security::Read);
+        assert_eq!(key.granted_access(&[sid("S-1-1-0")]), Security::empty());
+    }
+}
@@ -0,0 +1,712 @@
+//! Security descriptors, as attached to registry keys.
+//!
+//! Descriptors are parsed from and written to their self-relative binary form, which is what
+//! `RegGetKeySecurity` returns and what hive files store in their security cells, and from and to
+//! SDDL strings.
+
+use std::{convert::TryInto, fmt::Display, str::FromStr};
+
+use crate::sec::Security;
+
+mod access;
+mod sddl;
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Security descriptor is truncated")]
+    Truncated,
+
+    #[error("Unsupported revision: {0}")]
+    UnsupportedRevision(u8),
+
+    #[error("Unsupported ACE type: {0:#x}")]
+    UnsupportedAceType(u8),
+
+    #[error("Invalid SID: {0:?}")]
+    InvalidSid(String),
+
+    #[error("Invalid SDDL string: {0:?}")]
+    InvalidSddl(String),
+}
+
+/// A security identifier, such as `S-1-5-18` for the local system account.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Sid {
+    /// The identifier authority, a 48-bit number.
+    pub authority: u64,
+    pub sub_authorities: Vec<u32>,
+}
+
+impl Sid {
+    const REVISION: u8 = 1;
+
+    pub fn new(authority: u64, sub_authorities: &[u32]) -> Sid {
+        Sid {
+            authority,
+            sub_authorities: sub_authorities.to_vec(),
+        }
+    }
+
+    /// Parses a SID from the start of `buf`, returning it along with its length in bytes.
+    pub fn parse(buf: &[u8]) -> Result<(Sid, usize), Error> {
+        if buf.len() < 8 {
+            return Err(Error::Truncated);
+        }
+        if buf[0] != Self::REVISION {
+            return Err(Error::UnsupportedRevision(buf[0]));
+        }
+
+        let count = buf[1] as usize;
+        let len = 8 + count * 4;
+        if buf.len() < len {
+            return Err(Error::Truncated);
+        }
+
+        let authority = buf[2..8]
+            .iter()
+            .fold(0u64, |acc, x| (acc << 8) | u64::from(*x));
+        let sub_authorities = buf[8..len]
+            .chunks_exact(4)
+            .map(|x| u32::from_le_bytes(x.try_into().unwrap()))
+            .collect();
+
+        Ok((
+            Sid {
+                authority,
+                sub_authorities,
+            },
+            len,
+        ))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = vec![Self::REVISION, self.sub_authorities.len() as u8];
+        buf.extend_from_slice(&self.authority.to_be_bytes()[2..]);
+        for sub_authority in &self.sub_authorities {
+            buf.extend_from_slice(&sub_authority.to_le_bytes());
+        }
+        buf
+    }
+}
+
+impl Display for Sid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "S-{}-", Self::REVISION)?;
+        if self.authority >> 32 == 0 {
+            write!(f, "{}", self.authority)?;
+        } else {
+            write!(f, "0x{:012X}", self.authority)?;
+        }
+        for sub_authority in &self.sub_authorities {
+            write!(f, "-{}", sub_authority)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Sid {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidSid(s.to_string());
+        let mut parts = s.split('-');
+
+        if !matches!(parts.next(), Some("S") | Some("s")) || parts.next() != Some("1") {
+            return Err(invalid());
+        }
+
+        let authority = match parts.next() {
+            Some(x) if x.starts_with("0x") || x.starts_with("0X") => {
+                u64::from_str_radix(&x[2..], 16).map_err(|_| invalid())?
+            }
+            Some(x) => x.parse::<u64>().map_err(|_| invalid())?,
+            None => return Err(invalid()),
+        };
+        if authority >> 48 != 0 {
+            return Err(invalid());
+        }
+
+        let sub_authorities = parts
+            .map(|x| x.parse::<u32>().map_err(|_| invalid()))
+            .collect::<Result<Vec<_>, _>>()?;
+        if sub_authorities.len() > 15 {
+            return Err(invalid());
+        }
+
+        Ok(Sid {
+            authority,
+            sub_authorities,
+        })
+    }
+}
+
+/// The kind of an access control entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AceType {
+    AccessAllowed,
+    AccessDenied,
+    SystemAudit,
+    SystemAlarm,
+    SystemMandatoryLabel,
+}
+
+impl AceType {
+    fn from_u8(ty: u8) -> Option<AceType> {
+        Some(match ty {
+            0x0 => AceType::AccessAllowed,
+            0x1 => AceType::AccessDenied,
+            0x2 => AceType::SystemAudit,
+            0x3 => AceType::SystemAlarm,
+            0x11 => AceType::SystemMandatoryLabel,
+            _ => return None,
+        })
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            AceType::AccessAllowed => 0x0,
+            AceType::AccessDenied => 0x1,
+            AceType::SystemAudit => 0x2,
+            AceType::SystemAlarm => 0x3,
+            AceType::SystemMandatoryLabel => 0x11,
+        }
+    }
+}
+
+bitflags::bitflags! {
+    /// How an access control entry is inherited, and which accesses it audits.
+    pub struct AceFlags: u8 {
+        const OBJECT_INHERIT = 0x1;
+        const CONTAINER_INHERIT = 0x2;
+        const NO_PROPAGATE_INHERIT = 0x4;
+        const INHERIT_ONLY = 0x8;
+        const INHERITED = 0x10;
+        const SUCCESSFUL_ACCESS = 0x40;
+        const FAILED_ACCESS = 0x80;
+    }
+}
+
+impl AceFlags {
+    /// Keeps bits without a name, so that they survive being written back.
+    pub(crate) fn from_bits_retain(bits: u8) -> AceFlags {
+        AceFlags { bits }
+    }
+}
+
+/// An access control entry, granting, denying or auditing access for a SID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ace {
+    pub ace_type: AceType,
+    pub flags: AceFlags,
+    pub mask: Security,
+    pub sid: Sid,
+}
+
+impl Ace {
+    const HEADER_LEN: usize = 8;
+
+    pub fn new(ace_type: AceType, flags: AceFlags, mask: Security, sid: Sid) -> Ace {
+        Ace {
+            ace_type,
+            flags,
+            mask,
+            sid,
+        }
+    }
+
+    fn parse(ace_type: AceType, flags: AceFlags, body: &[u8]) -> Result<Ace, Error> {
+        let mask = body.get(..4).ok_or(Error::Truncated)?;
+        let (sid, _) = Sid::parse(&body[4..])?;
+        Ok(Ace {
+            ace_type,
+            flags,
+            mask: Security::from_bits_retain(u32::from_le_bytes(mask.try_into().unwrap())),
+            sid,
+        })
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let sid = self.sid.to_bytes();
+        let mut buf = vec![self.ace_type.to_u8(), self.flags.bits()];
+        buf.extend_from_slice(&((Self::HEADER_LEN + sid.len()) as u16).to_le_bytes());
+        buf.extend_from_slice(&self.mask.bits().to_le_bytes());
+        buf.extend_from_slice(&sid);
+        buf
+    }
+}
+
+/// An entry in an access control list.
+///
+/// Object, callback and other ACE types that carry more than a mask and a SID are kept as `Other`,
+/// with the bytes that follow the ACE header, so that a descriptor holding them can be written
+/// back unchanged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AclEntry {
+    Ace(Ace),
+    Other {
+        ace_type: u8,
+        flags: AceFlags,
+        body: Vec<u8>,
+    },
+}
+
+impl AclEntry {
+    const HEADER_LEN: usize = 4;
+
+    /// The entry as an ACE, if it is of a supported type.
+    #[inline]
+    pub fn as_ace(&self) -> Option<&Ace> {
+        match self {
+            AclEntry::Ace(ace) => Some(ace),
+            AclEntry::Other { .. } => None,
+        }
+    }
+
+    /// Parses an entry from the start of `buf`, returning it along with its length in bytes.
+    fn parse(buf: &[u8]) -> Result<(AclEntry, usize), Error> {
+        if buf.len() < Self::HEADER_LEN {
+            return Err(Error::Truncated);
+        }
+
+        let flags = AceFlags::from_bits_retain(buf[1]);
+        let len = u16::from_le_bytes([buf[2], buf[3]]) as usize;
+        if len < Ace::HEADER_LEN {
+            return Err(Error::Truncated);
+        }
+        let body = buf.get(Self::HEADER_LEN..len).ok_or(Error::Truncated)?;
+
+        let entry = match AceType::from_u8(buf[0]) {
+            Some(ace_type) => AclEntry::Ace(Ace::parse(ace_type, flags, body)?),
+            None => AclEntry::Other {
+                ace_type: buf[0],
+                flags,
+                body: body.to_vec(),
+            },
+        };
+        Ok((entry, len))
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            AclEntry::Ace(ace) => ace.to_bytes(),
+            AclEntry::Other {
+                ace_type,
+                flags,
+                body,
+            } => {
+                let mut buf = vec![*ace_type, flags.bits()];
+                buf.extend_from_slice(&((Self::HEADER_LEN + body.len()) as u16).to_le_bytes());
+                buf.extend_from_slice(body);
+                buf
+            }
+        }
+    }
+}
+
+impl From<Ace> for AclEntry {
+    #[inline]
+    fn from(ace: Ace) -> Self {
+        AclEntry::Ace(ace)
+    }
+}
+
+/// An access control list: a discretionary ACL deciding who has access to a key, or a system ACL
+/// deciding which accesses are audited.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Acl {
+    pub revision: u8,
+    pub aces: Vec<AclEntry>,
+}
+
+impl Default for Acl {
+    fn default() -> Self {
+        Acl {
+            revision: Acl::REVISION,
+            aces: vec![],
+        }
+    }
+}
+
+impl Acl {
+    const REVISION: u8 = 2;
+    const HEADER_LEN: usize = 8;
+
+    fn parse(buf: &[u8]) -> Result<Acl, Error> {
+        if buf.len() < Self::HEADER_LEN {
+            return Err(Error::Truncated);
+        }
+        if !(2..=4).contains(&buf[0]) {
+            return Err(Error::UnsupportedRevision(buf[0]));
+        }
+
+        let len = u16::from_le_bytes([buf[2], buf[3]]) as usize;
+        let count = u16::from_le_bytes([buf[4], buf[5]]) as usize;
+        if len < Self::HEADER_LEN {
+            return Err(Error::Truncated);
+        }
+        let buf = buf.get(..len).ok_or(Error::Truncated)?;
+
+        let mut aces = Vec::with_capacity(count);
+        let mut offset = Self::HEADER_LEN;
+        for _ in 0..count {
+            let (ace, len) = AclEntry::parse(buf.get(offset..).ok_or(Error::Truncated)?)?;
+            aces.push(ace);
+            offset += len;
+        }
+
+        Ok(Acl {
+            revision: buf[0],
+            aces,
+        })
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = vec![self.revision, 0, 0, 0, 0, 0, 0, 0];
+        for ace in &self.aces {
+            buf.extend_from_slice(&ace.to_bytes());
+        }
+        let len = buf.len() as u16;
+        buf[2..4].copy_from_slice(&len.to_le_bytes());
+        buf[4..6].copy_from_slice(&(self.aces.len() as u16).to_le_bytes());
+        buf
+    }
+}
+
+bitflags::bitflags! {
+    /// The control flags of a security descriptor.
+    pub struct Control: u16 {
+        const OWNER_DEFAULTED = 0x1;
+        const GROUP_DEFAULTED = 0x2;
+        const DACL_PRESENT = 0x4;
+        const DACL_DEFAULTED = 0x8;
+        const SACL_PRESENT = 0x10;
+        const SACL_DEFAULTED = 0x20;
+        const DACL_AUTO_INHERIT_REQ = 0x100;
+        const SACL_AUTO_INHERIT_REQ = 0x200;
+        const DACL_AUTO_INHERITED = 0x400;
+        const SACL_AUTO_INHERITED = 0x800;
+        const DACL_PROTECTED = 0x1000;
+        const SACL_PROTECTED = 0x2000;
+        const RM_CONTROL_VALID = 0x4000;
+        const SELF_RELATIVE = 0x8000;
+    }
+}
+
+impl Control {
+    /// Keeps bits without a name, so that they survive being written back.
+    pub(crate) fn from_bits_retain(bits: u16) -> Control {
+        Control { bits }
+    }
+}
+
+/// The owner, group and access control lists of a key.
+///
+/// A descriptor with [`DACL_PRESENT`](struct.Control.html#associatedconstant.DACL_PRESENT) set
+/// but no `dacl` has a null DACL, which grants everyone full access.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SecurityDescriptor {
+    pub control: Control,
+    pub owner: Option<Sid>,
+    pub group: Option<Sid>,
+    pub sacl: Option<Acl>,
+    pub dacl: Option<Acl>,
+}
+
+impl Default for Control {
+    fn default() -> Self {
+        Control::SELF_RELATIVE
+    }
+}
+
+impl SecurityDescriptor {
+    const REVISION: u8 = 1;
+    const HEADER_LEN: usize = 20;
+
+    /// Parses a descriptor in self-relative form.
+    pub fn parse(buf: &[u8]) -> Result<SecurityDescriptor, Error> {
+        if buf.len() < Self::HEADER_LEN {
+            return Err(Error::Truncated);
+        }
+        if buf[0] != Self::REVISION {
+            return Err(Error::UnsupportedRevision(buf[0]));
+        }
+
+        let control = Control::from_bits_retain(u16::from_le_bytes([buf[2], buf[3]]));
+        let field = |i: usize| {
+            let offset = u32::from_le_bytes(buf[4 + i * 4..8 + i * 4].try_into().unwrap());
+            match offset as usize {
+                0 => Ok(None),
+                offset => buf.get(offset..).map(Some).ok_or(Error::Truncated),
+            }
+        };
+
+        let owner = field(0)?.map(Sid::parse).transpose()?.map(|x| x.0);
+        let group = field(1)?.map(Sid::parse).transpose()?.map(|x| x.0);
+        let sacl = match control.contains(Control::SACL_PRESENT) {
+            true => field(2)?.map(Acl::parse).transpose()?,
+            false => None,
+        };
+        let dacl = match control.contains(Control::DACL_PRESENT) {
+            true => field(3)?.map(Acl::parse).transpose()?,
+            false => None,
+        };
+
+        Ok(SecurityDescriptor {
+            control,
+            owner,
+            group,
+            sacl,
+            dacl,
+        })
+    }
+
+    /// Encodes the descriptor in self-relative form, with the owner and group first.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut control = self.control | Control::SELF_RELATIVE;
+        control.set(Control::SACL_PRESENT, self.sacl.is_some());
+        if self.dacl.is_some() {
+            control |= Control::DACL_PRESENT;
+        }
+
+        let mut buf = vec![0u8; Self::HEADER_LEN];
+        buf[0] = Self::REVISION;
+        buf[2..4].copy_from_slice(&control.bits().to_le_bytes());
+
+        let parts = [
+            self.owner.as_ref().map(Sid::to_bytes),
+            self.group.as_ref().map(Sid::to_bytes),
+            self.sacl.as_ref().map(Acl::to_bytes),
+            self.dacl.as_ref().map(Acl::to_bytes),
+        ];
+        for (i, part) in parts.iter().enumerate() {
+            if let Some(part) = part {
+                let offset = buf.len() as u32;
+                buf[4 + i * 4..8 + i * 4].copy_from_slice(&offset.to_le_bytes());
+                buf.extend_from_slice(part);
+            }
+        }
+        buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::regf::default_security_descriptor;
+
+    #[test]
+    fn sid_strings() {
+        let sid = "S-1-5-32-544".parse::<Sid>().unwrap();
+        assert_eq!(sid, Sid::new(5, &[32, 544]));
+        assert_eq!(sid.to_string(), "S-1-5-32-544");
+        assert_eq!(Sid::parse(&sid.to_bytes()).unwrap(), (sid, 16));
+
+        let sid = "S-1-0x123456789ABC-1".parse::<Sid>().unwrap();
+        assert_eq!(sid.authority, 0x1234_5678_9ABC);
+        assert_eq!(sid.to_string(), "S-1-0x123456789ABC-1");
+
+        for invalid in &[
+            "",
+            "S-1",
+            "S-2-5",
+            "S-1-5-x",
+            "X-1-5-18",
+            "S-1-0x1000000000000",
+        ] {
+            assert!(invalid.parse::<Sid>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn parse_default_descriptor() {
+        let bytes = default_security_descriptor();
+        let sd = SecurityDescriptor::parse(&bytes).unwrap();
+        assert_eq!(sd.owner, Some(Sid::new(5, &[32, 544])));
+        assert_eq!(sd.group, Some(Sid::new(5, &[18])));
+        assert_eq!(sd.sacl, None);
+
+        let dacl = sd.dacl.as_ref().unwrap();
+        assert_eq!(dacl.aces.len(), 3);
+        assert_eq!(
+            dacl.aces[2],
+            AclEntry::Ace(Ace::new(
+                AceType::AccessAllowed,
+                AceFlags::CONTAINER_INHERIT,
+                Security::Read,
+                Sid::new(1, &[0])
+            ))
+        );
+        assert_eq!(sd.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn round_trip() {
+        let sd = SecurityDescriptor {
+            control: Control::DACL_PROTECTED,
+            owner: Some(Sid::new(5, &[18])),
+            group: None,
+            sacl: Some(Acl {
+                revision: 2,
+                aces: vec![Ace::new(
+                    AceType::SystemAudit,
+                    AceFlags::FAILED_ACCESS,
+                    Security::SetValue | Security::Delete,
+                    Sid::new(1, &[0]),
+                )
+                .into()],
+            }),
+            dacl: Some(Acl {
+                revision: 2,
+                aces: vec![Ace::new(
+                    AceType::AccessDenied,
+                    AceFlags::OBJECT_INHERIT | AceFlags::INHERITED,
+                    Security::from_bits_retain(0x0400_0001),
+                    Sid::new(5, &[21, 1, 2, 3, 500]),
+                )
+                .into()],
+            }),
+        };
+
+        let parsed = SecurityDescriptor::parse(&sd.to_bytes()).unwrap();
+        assert!(parsed.control.contains(
+            Control::SELF_RELATIVE
+                | Control::DACL_PROTECTED
+                | Control::DACL_PRESENT
+                | Control::SACL_PRESENT
+        ));
+        assert_eq!(parsed.owner, sd.owner);
+        assert_eq!(parsed.sacl, sd.sacl);
+        assert_eq!(parsed.dacl, sd.dacl);
+    }
+
+    #[test]
+    fn unknown_aces_and_bits() {
+        // A callback ACE, whose application data follows the SID, with an unnamed flag bit.
+        let mut body = 0x2_0019u32.to_le_bytes().to_vec();
+        body.extend_from_slice(&Sid::new(1, &[0]).to_bytes());
+        body.extend_from_slice(b"artx\0\0\0\0");
+        let sd = SecurityDescriptor {
+            control: Control::from_bits_retain(0x80),
+            dacl: Some(Acl {
+                revision: 2,
+                aces: vec![
+                    AclEntry::Other {
+                        ace_type: 0x9,
+                        flags: AceFlags::from_bits_retain(0x20) | AceFlags::CONTAINER_INHERIT,
+                        body,
+                    },
+                    Ace::new(
+                        AceType::AccessAllowed,
+                        AceFlags::from_bits_retain(0x20),
+                        Security::Read,
+                        Sid::new(5, &[18]),
+                    )
+                    .into(),
+                ],
+            }),
+            ..SecurityDescriptor::default()
+        };
+
+        let bytes = sd.to_bytes();
+        let parsed = SecurityDescriptor::parse(&bytes).unwrap();
+        assert_eq!(parsed.control.bits() & 0x80, 0x80);
+        assert_eq!(parsed.dacl, sd.dacl);
+        assert_eq!(parsed.to_bytes(), bytes);
+
+        // Only the ACE it understands is written as SDDL, or counts towards access.
+        assert_eq!(parsed.to_sddl(), "D:(A;;KR;;;SY)");
+        assert_eq!(
+            parsed.granted_access(&[Sid::new(1, &[0])]),
+            Security::empty()
+        );
+    }
+
+    #[test]
+    fn null_dacl() {
+        let sd = SecurityDescriptor {
+            control: Control::DACL_PRESENT,
+            ..SecurityDescriptor::default()
+        };
+        let parsed = SecurityDescriptor::parse(&sd.to_bytes()).unwrap();
+        assert!(parsed.control.contains(Control::DACL_PRESENT));
+        assert_eq!(parsed.dacl, None);
+    }
+
+    #[test]
+    fn invalid_descriptors() {
+        assert!(matches!(
+            SecurityDescriptor::parse(&[1, 0, 4]),
+            Err(Error::Truncated)
+        ));
+
+        let mut bytes = default_security_descriptor();
+        bytes[0] = 2;
+        assert!(matches!(
+            SecurityDescriptor::parse(&bytes),
+            Err(Error::UnsupportedRevision(2))
+        ));
+
+        // Point the DACL past the end.
+        let mut bytes = default_security_descriptor();
+        bytes[16..20].copy_from_slice(&1000u32.to_le_bytes());
+        assert!(matches!(
+            SecurityDescriptor::parse(&bytes),
+            Err(Error::Truncated)
+        ));
+    }
+
+    #[test]
+    fn malformed_acls() {
+        let with_dacl = |acl: &[u8]| {
+            let mut bytes = vec![1, 0, 0x04, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+            bytes.extend_from_slice(&20u32.to_le_bytes());
+            bytes.extend_from_slice(acl);
+            SecurityDescriptor::parse(&bytes)
+        };
+
+        let everyone = Sid::new(1, &[0]).to_bytes();
+        let ace = |size: u16| {
+            let mut ace = vec![0, 0];
+            ace.extend_from_slice(&size.to_le_bytes());
+            ace.extend_from_slice(&[0; 4]);
+            ace.extend_from_slice(&everyone);
+            ace
+        };
+        let acl = |size: u16, count: u16, aces: &[u8]| {
+            let mut acl = vec![2, 0];
+            acl.extend_from_slice(&size.to_le_bytes());
+            acl.extend_from_slice(&count.to_le_bytes());
+            acl.extend_from_slice(&[0, 0]);
+            acl.extend_from_slice(aces);
+            acl
+        };
+
+        let valid = ace(8 + everyone.len() as u16);
+        let len = 8 + valid.len() as u16;
+        assert_eq!(
+            with_dacl(&acl(len, 1, &valid))
+                .unwrap()
+                .dacl
+                .unwrap()
+                .aces
+                .len(),
+            1
+        );
+
+        for bytes in &[
+            acl(4, 1, &[]),
+            acl(len, 2, &valid),
+            acl(len, 1, &ace(0)),
+            acl(len, 1, &ace(4)),
+            acl(len - 1, 1, &valid),
+        ] {
+            assert!(
+                matches!(with_dacl(bytes), Err(Error::Truncated)),
+                "{:?}",
+                bytes
+            );
+        }
+    }
+}
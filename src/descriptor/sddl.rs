@@ -0,0 +1,526 @@
+//! The Security Descriptor Definition Language, the string form of a security descriptor such as
+//! `O:BAG:SYD:(A;CI;KA;;;SY)(A;CI;KR;;;WD)`.
+
+use std::{fmt::Write, str::FromStr};
+
+use super::{Ace, AceFlags, AceType, Acl, AclEntry, Control, Error, SecurityDescriptor, Sid};
+use crate::sec::Security;
+
+/// Well-known SIDs with a two-letter alias.
+const SID_ALIASES: &[(&str, &str)] = &[
+    ("WD", "S-1-1-0"),
+    ("CO", "S-1-3-0"),
+    ("CG", "S-1-3-1"),
+    ("OW", "S-1-3-4"),
+    ("NU", "S-1-5-2"),
+    ("IU", "S-1-5-4"),
+    ("SU", "S-1-5-6"),
+    ("AN", "S-1-5-7"),
+    ("PS", "S-1-5-10"),
+    ("AU", "S-1-5-11"),
+    ("RC", "S-1-5-12"),
+    ("SY", "S-1-5-18"),
+    ("LS", "S-1-5-19"),
+    ("NS", "S-1-5-20"),
+    ("BA", "S-1-5-32-544"),
+    ("BU", "S-1-5-32-545"),
+    ("BG", "S-1-5-32-546"),
+    ("PU", "S-1-5-32-547"),
+    ("AO", "S-1-5-32-548"),
+    ("SO", "S-1-5-32-549"),
+    ("PO", "S-1-5-32-550"),
+    ("BO", "S-1-5-32-551"),
+    ("RE", "S-1-5-32-552"),
+    ("RU", "S-1-5-32-554"),
+    ("RD", "S-1-5-32-555"),
+    ("NO", "S-1-5-32-556"),
+    ("WR", "S-1-5-33"),
+    ("AC", "S-1-15-2-1"),
+    ("LW", "S-1-16-4096"),
+    ("ME", "S-1-16-8192"),
+    ("HI", "S-1-16-12288"),
+    ("SI", "S-1-16-16384"),
+];
+
+/// Access rights made up of several bits, which are only used when a mask matches them exactly.
+const COMBINED_RIGHTS: &[(&str, u32)] = &[
+    ("KA", Security::AllAccess.bits()),
+    ("KR", Security::Read.bits()),
+    ("KW", Security::Write.bits()),
+    ("KX", Security::Execute.bits()),
+];
+
+/// Access rights of a single bit. The standard rights are listed under their directory service
+/// names, which is how Windows formats them for keys.
+const RIGHTS: &[(&str, u32)] = &[
+    ("GA", Security::GenericAll.bits()),
+    ("GR", Security::GenericRead.bits()),
+    ("GW", Security::GenericWrite.bits()),
+    ("GX", Security::GenericExecute.bits()),
+    ("RC", Security::ReadControl.bits()),
+    ("SD", Security::Delete.bits()),
+    ("WD", Security::WriteDac.bits()),
+    ("WO", Security::WriteOwner.bits()),
+    ("CC", Security::QueryValue.bits()),
+    ("DC", Security::SetValue.bits()),
+    ("LC", Security::CreateSubKey.bits()),
+    ("SW", Security::EnumerateSubKeys.bits()),
+    ("RP", Security::Notify.bits()),
+    ("WP", Security::CreateLink.bits()),
+];
+
+/// The rights of a mandatory label, which control access from lower integrity levels.
+const LABEL_RIGHTS: &[(&str, u32)] = &[("NW", 0x1), ("NR", 0x2), ("NX", 0x4)];
+
+const ACE_TYPES: &[(&str, AceType)] = &[
+    ("A", AceType::AccessAllowed),
+    ("D", AceType::AccessDenied),
+    ("AU", AceType::SystemAudit),
+    ("AL", AceType::SystemAlarm),
+    ("ML", AceType::SystemMandatoryLabel),
+];
+
+const ACE_FLAGS: &[(&str, AceFlags)] = &[
+    ("OI", AceFlags::OBJECT_INHERIT),
+    ("CI", AceFlags::CONTAINER_INHERIT),
+    ("NP", AceFlags::NO_PROPAGATE_INHERIT),
+    ("IO", AceFlags::INHERIT_ONLY),
+    ("ID", AceFlags::INHERITED),
+    ("SA", AceFlags::SUCCESSFUL_ACCESS),
+    ("FA", AceFlags::FAILED_ACCESS),
+];
+
+const NULL_ACL: &str = "NO_ACCESS_CONTROL";
+
+impl SecurityDescriptor {
+    /// Parses a descriptor from its SDDL string form. Well-known SID aliases such as `SY` and
+    /// `BA` are understood, along with the registry rights `KA`, `KR`, `KW` and `KX`. Object
+    /// ACEs and aliases relative to a domain are not supported.
+    pub fn from_sddl(sddl: &str) -> Result<SecurityDescriptor, Error> {
+        let invalid = || Error::InvalidSddl(sddl.to_string());
+        let mut sd = SecurityDescriptor::default();
+
+        let mut rest = sddl.trim();
+        while !rest.is_empty() {
+            let tag = rest.get(..2).ok_or_else(invalid)?;
+            let end = section_end(&rest[2..]) + 2;
+            let body = &rest[2..end];
+            rest = &rest[end..];
+
+            match tag {
+                "O:" => sd.owner = Some(parse_sid(body).ok_or_else(invalid)?),
+                "G:" => sd.group = Some(parse_sid(body).ok_or_else(invalid)?),
+                "D:" => {
+                    let (flags, acl) = parse_acl(body).ok_or_else(invalid)?;
+                    sd.control |= Control::DACL_PRESENT;
+                    if flags.contains(&"P") {
+                        sd.control |= Control::DACL_PROTECTED;
+                    }
+                    if flags.contains(&"AI") {
+                        sd.control |= Control::DACL_AUTO_INHERITED;
+                    }
+                    if flags.contains(&"AR") {
+                        sd.control |= Control::DACL_AUTO_INHERIT_REQ;
+                    }
+                    sd.dacl = acl;
+                }
+                "S:" => {
+                    let (flags, acl) = parse_acl(body).ok_or_else(invalid)?;
+                    sd.control |= Control::SACL_PRESENT;
+                    if flags.contains(&"P") {
+                        sd.control |= Control::SACL_PROTECTED;
+                    }
+                    if flags.contains(&"AI") {
+                        sd.control |= Control::SACL_AUTO_INHERITED;
+                    }
+                    if flags.contains(&"AR") {
+                        sd.control |= Control::SACL_AUTO_INHERIT_REQ;
+                    }
+                    sd.sacl = acl;
+                }
+                _ => return Err(invalid()),
+            }
+        }
+
+        Ok(sd)
+    }
+
+    /// Formats the descriptor as an SDDL string, using aliases for well-known SIDs and rights.
+    ///
+    /// [`AclEntry::Other`] entries have no SDDL form here and are left out.
+    pub fn to_sddl(&self) -> String {
+        let mut sddl = String::new();
+        if let Some(owner) = &self.owner {
+            sddl.push_str("O:");
+            sddl.push_str(&format_sid(owner));
+        }
+        if let Some(group) = &self.group {
+            sddl.push_str("G:");
+            sddl.push_str(&format_sid(group));
+        }
+
+        if self.control.contains(Control::DACL_PRESENT) || self.dacl.is_some() {
+            sddl.push_str("D:");
+            format_acl_flags(
+                &mut sddl,
+                self.control.contains(Control::DACL_PROTECTED),
+                self.control.contains(Control::DACL_AUTO_INHERIT_REQ),
+                self.control.contains(Control::DACL_AUTO_INHERITED),
+            );
+            format_acl(&mut sddl, self.dacl.as_ref());
+        }
+        if self.control.contains(Control::SACL_PRESENT) || self.sacl.is_some() {
+            sddl.push_str("S:");
+            format_acl_flags(
+                &mut sddl,
+                self.control.contains(Control::SACL_PROTECTED),
+                self.control.contains(Control::SACL_AUTO_INHERIT_REQ),
+                self.control.contains(Control::SACL_AUTO_INHERITED),
+            );
+            format_acl(&mut sddl, self.sacl.as_ref());
+        }
+
+        sddl
+    }
+}
+
+impl FromStr for SecurityDescriptor {
+    type Err = Error;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SecurityDescriptor::from_sddl(s)
+    }
+}
+
+impl std::fmt::Display for SecurityDescriptor {
+    #[inline]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.to_sddl())
+    }
+}
+
+/// The length of a section body: everything up to the next `O:`, `G:`, `D:` or `S:` outside of
+/// parentheses.
+fn section_end(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    let mut depth = 0;
+    for (i, x) in bytes.iter().enumerate() {
+        match x {
+            b'(' => depth += 1,
+            b')' => depth -= 1,
+            b'O' | b'G' | b'D' | b'S' if depth == 0 && bytes.get(i + 1) == Some(&b':') => return i,
+            _ => {}
+        }
+    }
+    s.len()
+}
+
+fn parse_sid(s: &str) -> Option<Sid> {
+    let s = s.trim();
+    match SID_ALIASES.iter().find(|(alias, _)| *alias == s) {
+        Some((_, sid)) => sid.parse().ok(),
+        None => s.parse().ok(),
+    }
+}
+
+fn format_sid(sid: &Sid) -> String {
+    let s = sid.to_string();
+    match SID_ALIASES.iter().find(|(_, x)| *x == s) {
+        Some((alias, _)) => alias.to_string(),
+        None => s,
+    }
+}
+
+/// Splits an ACL into its flags and its ACEs, where no ACL at all is a null ACL.
+fn parse_acl(s: &str) -> Option<(Vec<&str>, Option<Acl>)> {
+    let s = s.trim();
+    let (mut flags_str, mut aces_str) = s.split_at(s.find('(').unwrap_or(s.len()));
+
+    let mut flags = vec![];
+    let mut is_null = false;
+    while !flags_str.is_empty() {
+        let flag = ["P", "AI", "AR", NULL_ACL]
+            .iter()
+            .find(|x| flags_str.starts_with(*x))?;
+        if *flag == NULL_ACL {
+            is_null = true;
+        }
+        flags.push(*flag);
+        flags_str = &flags_str[flag.len()..];
+    }
+
+    let mut acl = Acl::default();
+    while !aces_str.is_empty() {
+        let end = aces_str.find(')')?;
+        acl.aces.push(parse_ace(aces_str.get(1..end)?)?.into());
+        aces_str = aces_str[end + 1..].trim_start();
+        if !aces_str.is_empty() && !aces_str.starts_with('(') {
+            return None;
+        }
+    }
+
+    match is_null {
+        true if acl.aces.is_empty() => Some((flags, None)),
+        true => None,
+        false => Some((flags, Some(acl))),
+    }
+}
+
+fn parse_ace(s: &str) -> Option<Ace> {
+    let fields = s.split(';').map(str::trim).collect::<Vec<_>>();
+    if fields.len() != 6 || !fields[3].is_empty() || !fields[4].is_empty() {
+        return None;
+    }
+
+    let ace_type = ACE_TYPES.iter().find(|(x, _)| *x == fields[0])?.1;
+
+    let mut flags = AceFlags::empty();
+    for flag in two_letter_codes(fields[1])? {
+        flags |= ACE_FLAGS.iter().find(|(x, _)| *x == flag)?.1;
+    }
+
+    let mask = parse_rights(fields[2], ace_type)?;
+    let sid = parse_sid(fields[5])?;
+
+    Some(Ace::new(
+        ace_type,
+        flags,
+        Security::from_bits_retain(mask),
+        sid,
+    ))
+}
+
+fn parse_rights(s: &str, ace_type: AceType) -> Option<u32> {
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        return u32::from_str_radix(hex, 16).ok();
+    }
+    if let Ok(mask) = s.parse::<u32>() {
+        return Some(mask);
+    }
+
+    let rights = match ace_type {
+        AceType::SystemMandatoryLabel => LABEL_RIGHTS,
+        _ => RIGHTS,
+    };
+    let mut mask = 0;
+    for code in two_letter_codes(s)? {
+        mask |= COMBINED_RIGHTS
+            .iter()
+            .chain(rights)
+            .find(|(x, _)| *x == code)?
+            .1;
+    }
+    Some(mask)
+}
+
+fn two_letter_codes(s: &str) -> Option<Vec<&str>> {
+    if !s.len().is_multiple_of(2) || !s.is_ascii() {
+        return None;
+    }
+    Some((0..s.len()).step_by(2).map(|i| &s[i..i + 2]).collect())
+}
+
+fn format_acl_flags(sddl: &mut String, is_protected: bool, is_req: bool, is_inherited: bool) {
+    if is_protected {
+        sddl.push('P');
+    }
+    if is_req {
+        sddl.push_str("AR");
+    }
+    if is_inherited {
+        sddl.push_str("AI");
+    }
+}
+
+fn format_acl(sddl: &mut String, acl: Option<&Acl>) {
+    let acl = match acl {
+        Some(v) => v,
+        None => {
+            sddl.push_str(NULL_ACL);
+            return;
+        }
+    };
+
+    for ace in acl.aces.iter().filter_map(AclEntry::as_ace) {
+        let ty = ACE_TYPES
+            .iter()
+            .find(|(_, x)| *x == ace.ace_type)
+            .unwrap()
+            .0;
+        let flags = ACE_FLAGS
+            .iter()
+            .filter(|(_, x)| ace.flags.contains(*x))
+            .map(|(x, _)| *x)
+            .collect::<String>();
+        let rights = format_rights(ace.mask.bits(), ace.ace_type);
+        let _ = write!(
+            sddl,
+            "({};{};{};;;{})",
+            ty,
+            flags,
+            rights,
+            format_sid(&ace.sid)
+        );
+    }
+}
+
+fn format_rights(mask: u32, ace_type: AceType) -> String {
+    let rights = match ace_type {
+        AceType::SystemMandatoryLabel => LABEL_RIGHTS,
+        _ => {
+            if let Some((code, _)) = COMBINED_RIGHTS.iter().find(|(_, x)| *x == mask) {
+                return code.to_string();
+            }
+            RIGHTS
+        }
+    };
+
+    let known = rights.iter().fold(0, |acc, (_, x)| acc | x);
+    if mask == 0 || mask & !known != 0 {
+        return format!("{:#x}", mask);
+    }
+    rights
+        .iter()
+        .filter(|(_, x)| mask & x != 0)
+        .map(|(code, _)| *code)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sddl() {
+        let sd = "O:BAG:SYD:(A;CI;KA;;;SY)(A;CI;KA;;;BA)(A;CI;KR;;;WD)"
+            .parse::<SecurityDescriptor>()
+            .unwrap();
+        let ace = |mask, sid: &str| {
+            Ace::new(
+                AceType::AccessAllowed,
+                AceFlags::CONTAINER_INHERIT,
+                mask,
+                sid.parse().unwrap(),
+            )
+        };
+        assert_eq!(
+            sd,
+            SecurityDescriptor {
+                control: Control::SELF_RELATIVE | Control::DACL_PRESENT,
+                owner: Some("S-1-5-32-544".parse().unwrap()),
+                group: Some("S-1-5-18".parse().unwrap()),
+                sacl: None,
+                dacl: Some(Acl {
+                    revision: 2,
+                    aces: vec![
+                        ace(Security::AllAccess, "S-1-5-18").into(),
+                        ace(Security::AllAccess, "S-1-5-32-544").into(),
+                        ace(Security::Read, "S-1-1-0").into(),
+                    ],
+                }),
+            }
+        );
+        assert_eq!(
+            sd.to_sddl(),
+            "O:BAG:SYD:(A;CI;KA;;;SY)(A;CI;KA;;;BA)(A;CI;KR;;;WD)"
+        );
+    }
+
+    #[test]
+    fn rights_and_flags() {
+        let sd = SecurityDescriptor::from_sddl(
+            "O:S-1-5-21-1-2-3-500D:PAI(D;OICIID;KW;;;S-1-5-21-1-2-3-1001)(A;;CCDCRC;;;AU)\
+             (A;IO;0x10000000;;;CO)S:(AU;FA;SDWDWO;;;WD)(ML;;NW;;;LW)",
+        )
+        .unwrap();
+        assert_eq!(sd.owner, Some("S-1-5-21-1-2-3-500".parse().unwrap()));
+        assert!(sd
+            .control
+            .contains(Control::DACL_PROTECTED | Control::DACL_AUTO_INHERITED));
+
+        let dacl = sd
+            .dacl
+            .as_ref()
+            .unwrap()
+            .aces
+            .iter()
+            .map(|x| x.as_ace().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(dacl[0].ace_type, AceType::AccessDenied);
+        assert_eq!(
+            dacl[0].flags,
+            AceFlags::OBJECT_INHERIT | AceFlags::CONTAINER_INHERIT | AceFlags::INHERITED
+        );
+        assert_eq!(dacl[0].mask, Security::Write);
+        assert_eq!(
+            dacl[1].mask,
+            Security::QueryValue | Security::SetValue | Security::ReadControl
+        );
+        assert_eq!(dacl[2].mask, Security::GenericAll);
+
+        let sacl = sd
+            .sacl
+            .as_ref()
+            .unwrap()
+            .aces
+            .iter()
+            .map(|x| x.as_ace().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sacl[0].mask,
+            Security::Delete | Security::WriteDac | Security::WriteOwner
+        );
+        assert_eq!(sacl[1].mask.bits(), 0x1);
+
+        // Single bits come out in a fixed order, and generic rights by name.
+        assert_eq!(
+            sd.to_sddl(),
+            "O:S-1-5-21-1-2-3-500D:PAI(D;OICIID;KW;;;S-1-5-21-1-2-3-1001)(A;;RCCCDC;;;AU)\
+             (A;IO;GA;;;CO)S:(AU;FA;SDWDWO;;;WD)(ML;;NW;;;LW)"
+        );
+        assert_eq!(SecurityDescriptor::from_sddl(&sd.to_sddl()).unwrap(), sd);
+    }
+
+    #[test]
+    fn unnamed_rights() {
+        let sd = SecurityDescriptor::from_sddl("D:(A;;0x400;;;BU)(A;;0x0;;;BG)").unwrap();
+        assert_eq!(sd.to_sddl(), "D:(A;;0x400;;;BU)(A;;0x0;;;BG)");
+    }
+
+    #[test]
+    fn null_and_empty_acls() {
+        let sd = SecurityDescriptor::from_sddl("D:NO_ACCESS_CONTROL").unwrap();
+        assert!(sd.control.contains(Control::DACL_PRESENT));
+        assert_eq!(sd.dacl, None);
+        assert_eq!(sd.to_sddl(), "D:NO_ACCESS_CONTROL");
+
+        let sd = SecurityDescriptor::from_sddl("O:SYD:").unwrap();
+        assert_eq!(sd.dacl, Some(Acl::default()));
+        assert_eq!(sd.to_sddl(), "O:SYD:");
+    }
+
+    #[test]
+    fn invalid_sddl() {
+        for invalid in &[
+            "X:SY",
+            "O:XX",
+            "D:(A;;KA;;SY)",
+            "D:(Q;;KA;;;SY)",
+            "D:(A;ZZ;KA;;;SY)",
+            "D:(A;;KAQ;;;SY)",
+            "D:(A;;KA;;;SY",
+            "D:(A;;KA;a0e4a1a5-1c1b-4d7d-b3c2-3c4f3b1c8f7e;;SY)",
+            "D:NO_ACCESS_CONTROL(A;;KA;;;SY)",
+        ] {
+            assert!(
+                matches!(
+                    SecurityDescriptor::from_sddl(invalid),
+                    Err(Error::InvalidSddl(_))
+                ),
+                "{}",
+                invalid
+            );
+        }
+    }
+}
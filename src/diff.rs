@@ -0,0 +1,335 @@
+//! Structural differences between two [`KeyTree`](../tree/struct.KeyTree.html)s.
+//!
+//! Trees can be read from the live registry with `RegKey::to_tree`, from hive files with
+//! [`OfflineKey::to_tree`](../regf/struct.OfflineKey.html#method.to_tree), or built by hand, so any
+//! two of them can be compared.
+
+use std::time::SystemTime;
+
+use utfx::U16CString;
+
+use crate::tree::KeyTree;
+use crate::util::eq_ignore_case;
+use crate::value::Data;
+
+/// How two trees are compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiffOptions {
+    /// Match key and value names without regard to case, as the registry does.
+    pub ignore_case: bool,
+    /// Do not report keys whose last write time is all that changed.
+    pub ignore_timestamps: bool,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        DiffOptions {
+            ignore_case: true,
+            ignore_timestamps: false,
+        }
+    }
+}
+
+/// A single difference between two trees. Paths are relative to the roots being compared, so the
+/// roots themselves have an empty path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// A key that only exists in the new tree. It is followed by changes adding its values and
+    /// subkeys.
+    KeyAdded {
+        path: U16CString,
+        class_name: Option<U16CString>,
+        last_written: SystemTime,
+    },
+    /// A key that only exists in the old tree, along with everything below it.
+    KeyRemoved { path: U16CString },
+    /// A key whose class name or last write time changed.
+    KeyModified {
+        path: U16CString,
+        old_class_name: Option<U16CString>,
+        new_class_name: Option<U16CString>,
+        old_last_written: SystemTime,
+        new_last_written: SystemTime,
+    },
+    ValueAdded {
+        path: U16CString,
+        name: U16CString,
+        data: Data,
+    },
+    ValueRemoved {
+        path: U16CString,
+        name: U16CString,
+        data: Data,
+    },
+    ValueModified {
+        path: U16CString,
+        name: U16CString,
+        old: Data,
+        new: Data,
+    },
+}
+
+impl Change {
+    /// The path of the key that this change is made to.
+    #[inline]
+    pub fn path(&self) -> &U16CString {
+        match self {
+            Change::KeyAdded { path, .. }
+            | Change::KeyRemoved { path }
+            | Change::KeyModified { path, .. }
+            | Change::ValueAdded { path, .. }
+            | Change::ValueRemoved { path, .. }
+            | Change::ValueModified { path, .. } => path,
+        }
+    }
+}
+
+impl KeyTree {
+    /// Lists the changes that turn this tree into `new`: values first, then subkeys, in the order
+    /// they are found. The names of the roots themselves are not compared, and neither are
+    /// security descriptors.
+    pub fn diff(&self, new: &KeyTree, options: DiffOptions) -> Vec<Change> {
+        let mut changes = vec![];
+        diff_keys(&mut changes, &[], self, new, options);
+        changes
+    }
+}
+
+fn names_eq(a: &U16CString, b: &U16CString, options: DiffOptions) -> bool {
+    match options.ignore_case {
+        true => eq_ignore_case(a.as_slice(), b.as_slice()),
+        false => a == b,
+    }
+}
+
+fn join(path: &[u16], name: &U16CString) -> Vec<u16> {
+    match path.is_empty() {
+        true => name.as_slice().to_vec(),
+        false => [path, &[u16::from(b'\\')], name.as_slice()].concat(),
+    }
+}
+
+fn to_ucstring(path: &[u16]) -> U16CString {
+    // Safety: paths are joined from names without interior nuls.
+    unsafe { U16CString::from_vec_unchecked(path.to_vec()) }
+}
+
+fn diff_keys(
+    changes: &mut Vec<Change>,
+    path: &[u16],
+    old: &KeyTree,
+    new: &KeyTree,
+    options: DiffOptions,
+) {
+    let is_timestamp_changed = !options.ignore_timestamps && old.last_written != new.last_written;
+    if old.class_name != new.class_name || is_timestamp_changed {
+        changes.push(Change::KeyModified {
+            path: to_ucstring(path),
+            old_class_name: old.class_name.clone(),
+            new_class_name: new.class_name.clone(),
+            old_last_written: old.last_written,
+            new_last_written: new.last_written,
+        });
+    }
+
+    for (name, data) in &old.values {
+        match new.values.iter().find(|(x, _)| names_eq(x, name, options)) {
+            Some((_, new_data)) if new_data != data => changes.push(Change::ValueModified {
+                path: to_ucstring(path),
+                name: name.clone(),
+                old: data.clone(),
+                new: new_data.clone(),
+            }),
+            Some(_) => {}
+            None => changes.push(Change::ValueRemoved {
+                path: to_ucstring(path),
+                name: name.clone(),
+                data: data.clone(),
+            }),
+        }
+    }
+    for (name, data) in &new.values {
+        if !old.values.iter().any(|(x, _)| names_eq(x, name, options)) {
+            changes.push(Change::ValueAdded {
+                path: to_ucstring(path),
+                name: name.clone(),
+                data: data.clone(),
+            });
+        }
+    }
+
+    for subkey in &old.subkeys {
+        let subkey_path = join(path, &subkey.name);
+        match new
+            .subkeys
+            .iter()
+            .find(|x| names_eq(&x.name, &subkey.name, options))
+        {
+            Some(new_subkey) => diff_keys(changes, &subkey_path, subkey, new_subkey, options),
+            None => changes.push(Change::KeyRemoved {
+                path: to_ucstring(&subkey_path),
+            }),
+        }
+    }
+    for subkey in &new.subkeys {
+        if !old
+            .subkeys
+            .iter()
+            .any(|x| names_eq(&x.name, &subkey.name, options))
+        {
+            add_key(changes, &join(path, &subkey.name), subkey);
+        }
+    }
+}
+
+fn add_key(changes: &mut Vec<Change>, path: &[u16], key: &KeyTree) {
+    changes.push(Change::KeyAdded {
+        path: to_ucstring(path),
+        class_name: key.class_name.clone(),
+        last_written: key.last_written,
+    });
+    for (name, data) in &key.values {
+        changes.push(Change::ValueAdded {
+            path: to_ucstring(path),
+            name: name.clone(),
+            data: data.clone(),
+        });
+    }
+    for subkey in &key.subkeys {
+        add_key(changes, &join(path, &subkey.name), subkey);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn s(x: &str) -> U16CString {
+        x.try_into().unwrap()
+    }
+
+    fn base_tree() -> KeyTree {
+        let mut root = KeyTree::new(s("ROOT"));
+        let vendor = root.create(&s(r"Software\Vendor"));
+        vendor.set_value(s("Version"), Data::U32(1));
+        vendor.set_value(s("Path"), Data::String(s(r"C:\Vendor")));
+        root.create(&s(r"Software\Old\Nested"));
+        set_timestamps(&mut root, UNIX_EPOCH);
+        root
+    }
+
+    fn set_timestamps(key: &mut KeyTree, time: SystemTime) {
+        key.last_written = time;
+        key.subkeys.iter_mut().for_each(|x| set_timestamps(x, time));
+    }
+
+    #[test]
+    fn identical_trees() {
+        let tree = base_tree();
+        assert_eq!(tree.diff(&tree.clone(), DiffOptions::default()), vec![]);
+    }
+
+    #[test]
+    fn added_removed_and_modified() {
+        let old = base_tree();
+        let mut new = base_tree();
+        let vendor = new.create(&s(r"Software\Vendor"));
+        vendor.set_value(s("Version"), Data::U32(2));
+        vendor.values.retain(|(x, _)| x != &s("Path"));
+        vendor.set_value(s("Installed"), Data::U32(1));
+        let app = new.create(&s(r"Software\Vendor\App"));
+        app.set_value(s("Enabled"), Data::U32(1));
+        app.last_written = UNIX_EPOCH;
+        new.subkeys[0].subkeys.retain(|x| x.name != s("Old"));
+
+        assert_eq!(
+            old.diff(&new, DiffOptions::default()),
+            vec![
+                Change::ValueModified {
+                    path: s(r"Software\Vendor"),
+                    name: s("Version"),
+                    old: Data::U32(1),
+                    new: Data::U32(2),
+                },
+                Change::ValueRemoved {
+                    path: s(r"Software\Vendor"),
+                    name: s("Path"),
+                    data: Data::String(s(r"C:\Vendor")),
+                },
+                Change::ValueAdded {
+                    path: s(r"Software\Vendor"),
+                    name: s("Installed"),
+                    data: Data::U32(1),
+                },
+                Change::KeyAdded {
+                    path: s(r"Software\Vendor\App"),
+                    class_name: None,
+                    last_written: UNIX_EPOCH,
+                },
+                Change::ValueAdded {
+                    path: s(r"Software\Vendor\App"),
+                    name: s("Enabled"),
+                    data: Data::U32(1),
+                },
+                Change::KeyRemoved {
+                    path: s(r"Software\Old"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn case_sensitivity() {
+        let old = base_tree();
+        let mut new = base_tree();
+        new.subkeys[0].name = s("SOFTWARE");
+
+        assert_eq!(old.diff(&new, DiffOptions::default()), vec![]);
+
+        let changes = old.diff(
+            &new,
+            DiffOptions {
+                ignore_case: false,
+                ..DiffOptions::default()
+            },
+        );
+        assert_eq!(
+            changes[0],
+            Change::KeyRemoved {
+                path: s("Software")
+            }
+        );
+        assert_eq!(changes[1].path(), &s("SOFTWARE"));
+        assert!(matches!(changes[1], Change::KeyAdded { .. }));
+    }
+
+    #[test]
+    fn timestamps() {
+        let old = base_tree();
+        let mut new = base_tree();
+        let later = UNIX_EPOCH + Duration::from_secs(60);
+        new.create(&s(r"Software\Vendor")).last_written = later;
+
+        assert_eq!(
+            old.diff(&new, DiffOptions::default()),
+            vec![Change::KeyModified {
+                path: s(r"Software\Vendor"),
+                old_class_name: None,
+                new_class_name: None,
+                old_last_written: UNIX_EPOCH,
+                new_last_written: later,
+            }]
+        );
+
+        let options = DiffOptions {
+            ignore_timestamps: true,
+            ..DiffOptions::default()
+        };
+        assert_eq!(old.diff(&new, options), vec![]);
+
+        new.create(&s(r"Software\Vendor")).class_name = Some(s("Class"));
+        assert_eq!(old.diff(&new, options).len(), 1);
+    }
+}
@@ -1,17 +1,26 @@
-use std::{convert::TryInto, fmt::Display};
+#[cfg(windows)]
+use std::convert::TryInto;
+use std::fmt::Display;
 
-use utfx::U16CString;
+#[cfg(windows)]
+use utfx::{U16CString, U16Str};
+#[cfg(windows)]
 use winapi::shared::minwindef::HKEY;
+#[cfg(windows)]
+use winapi::um::winnt::REG_OPTION_OPEN_LINK;
+#[cfg(windows)]
 use winapi::um::winreg::{
     HKEY_CLASSES_ROOT, HKEY_CURRENT_CONFIG, HKEY_CURRENT_USER, HKEY_CURRENT_USER_LOCAL_SETTINGS,
     HKEY_LOCAL_MACHINE, HKEY_PERFORMANCE_DATA, HKEY_USERS,
 };
 
+#[cfg(windows)]
 use crate::key::{self, Error};
+#[cfg(windows)]
 use crate::{sec::Security, RegKey};
 
 /// All hives of the Windows Registry. Start here to get to a registry key.
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Hive {
     ClassesRoot,
     CurrentConfig,
@@ -22,6 +31,7 @@ pub enum Hive {
     Users,
 }
 
+#[cfg(windows)]
 impl Hive {
     #[inline]
     fn as_hkey(&self) -> HKEY {
@@ -50,6 +60,23 @@ impl Hive {
         })
     }
 
+    /// Opens the symbolic link key at `path` itself, rather than the key that it points to.
+    #[inline]
+    pub fn open_link<P>(&self, path: P, sec: Security) -> Result<RegKey, Error>
+    where
+        P: TryInto<U16CString>,
+        P::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        key::open_hkey_with_options(self.as_hkey(), &path, REG_OPTION_OPEN_LINK, sec).map(
+            |handle| RegKey {
+                hive: *self,
+                handle,
+                path,
+            },
+        )
+    }
+
     #[inline]
     pub fn write<P>(&self, file_path: P) -> Result<(), Error>
     where
@@ -74,6 +101,23 @@ impl Hive {
         })
     }
 
+    /// Creates a symbolic link key at `path` that points to `target`, a native path such as
+    /// `\Registry\Machine\Software\Vendor`. If the target cannot be set, a newly created link key is
+    /// deleted again.
+    #[inline]
+    pub fn create_link<P>(&self, path: P, target: &U16Str, sec: Security) -> Result<RegKey, Error>
+    where
+        P: TryInto<U16CString>,
+        P::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        key::create_link_hkey(self.as_hkey(), &path, target, sec).map(|handle| RegKey {
+            hive: *self,
+            handle,
+            path,
+        })
+    }
+
     #[inline]
     pub fn delete<P>(&self, path: P, is_recursive: bool) -> Result<(), Error>
     where
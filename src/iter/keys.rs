@@ -1,14 +1,18 @@
 use std::{
     fmt::{Debug, Display},
     ptr::null_mut,
+    time::SystemTime,
 };
 
 use utfx::{U16CString, U16String};
+use winapi::shared::minwindef::FILETIME;
 use winapi::shared::winerror::ERROR_NO_MORE_ITEMS;
-use winapi::um::winreg::{RegEnumKeyExW, RegQueryInfoKeyW};
+use winapi::um::winnt::REG_OPTION_OPEN_LINK;
+use winapi::um::winreg::RegEnumKeyExW;
 
-use crate::key::RegKey;
+use crate::key::{KeyInfo, RegKey};
 use crate::sec::Security;
+use crate::util::filetime_to_system_time;
 
 #[derive(Debug, thiserror::Error)]
 #[non_exhaustive]
@@ -33,6 +37,7 @@ pub struct Keys<'a> {
 pub struct KeyRef<'a> {
     regkey: &'a RegKey,
     name: U16CString,
+    last_written: SystemTime,
 }
 
 impl Display for KeyRef<'_> {
@@ -50,8 +55,30 @@ impl<'a> Debug for KeyRef<'a> {
 }
 
 impl<'a> KeyRef<'a> {
+    /// When the subkey was last written, as reported while enumerating it.
+    #[inline]
+    pub fn last_written(&self) -> SystemTime {
+        self.last_written
+    }
+
+    /// Opens the subkey to query its full metadata.
+    #[inline]
+    pub fn info(&self) -> Result<KeyInfo, crate::key::Error> {
+        self.open(Security::QueryValue)?.info()
+    }
+
     #[inline]
     pub fn open(&self, sec: Security) -> Result<RegKey, crate::key::Error> {
+        self.open_with_options(0, sec)
+    }
+
+    /// Opens the subkey itself if it is a symbolic link, rather than the key that it points to.
+    #[inline]
+    pub fn open_link(&self, sec: Security) -> Result<RegKey, crate::key::Error> {
+        self.open_with_options(REG_OPTION_OPEN_LINK, sec)
+    }
+
+    fn open_with_options(&self, options: u32, sec: Security) -> Result<RegKey, crate::key::Error> {
         let path = self.regkey.path.to_ustring();
         let suffix = self.name.to_ustring();
         let bs = U16String::from_str("\\");
@@ -64,11 +91,13 @@ impl<'a> KeyRef<'a> {
             .collect::<Vec<u16>>();
 
         let path = U16CString::new(chars)?;
-        crate::key::open_hkey(self.regkey.handle, &self.name, sec).map(|handle| RegKey {
-            hive: self.regkey.hive,
-            handle,
-            path,
-        })
+        crate::key::open_hkey_with_options(self.regkey.handle, &self.name, options, sec).map(
+            |handle| RegKey {
+                hive: self.regkey.hive,
+                handle,
+                path,
+            },
+        )
     }
 }
 
@@ -79,6 +108,10 @@ impl<'a> Iterator for Keys<'a> {
         // Reset first byte, just in case.
         self.buf[0] = 0;
         let mut len = self.buf.len() as u32;
+        let mut last_written = FILETIME {
+            dwLowDateTime: 0,
+            dwHighDateTime: 0,
+        };
 
         let result = unsafe {
             RegEnumKeyExW(
@@ -89,7 +122,7 @@ impl<'a> Iterator for Keys<'a> {
                 null_mut(),
                 null_mut(),
                 null_mut(),
-                null_mut(),
+                &mut last_written,
             )
         };
 
@@ -109,43 +142,24 @@ impl<'a> Iterator for Keys<'a> {
             Err(e) => return Some(Err(Error::InvalidNul(e))),
         };
 
+        let last_written =
+            u64::from(last_written.dwHighDateTime) << 32 | u64::from(last_written.dwLowDateTime);
+
         Some(Ok(KeyRef {
             regkey: self.regkey,
             name,
+            last_written: filetime_to_system_time(last_written),
         }))
     }
 }
 
 impl<'a> Keys<'a> {
     pub fn new(regkey: &'a RegKey) -> Result<Keys<'a>, std::io::Error> {
-        let mut subkeys_max_str_len = 0u32;
-        // let mut subkeys_len = 0u32;
-
-        let result = unsafe {
-            RegQueryInfoKeyW(
-                regkey.handle,
-                null_mut(),
-                null_mut(),
-                null_mut(),
-                null_mut(), // &mut subkeys_len,
-                &mut subkeys_max_str_len,
-                null_mut(),
-                null_mut(),
-                null_mut(),
-                null_mut(),
-                null_mut(),
-                null_mut(),
-            )
-        };
-
-        if result == 0 {
-            return Ok(Keys {
-                regkey,
-                buf: vec![0u16; subkeys_max_str_len as usize + 1],
-                index: 0,
-            });
-        }
-
-        Err(std::io::Error::from_raw_os_error(result))
+        let info = crate::key::query_info_hkey(regkey.handle)?;
+        Ok(Keys {
+            regkey,
+            buf: vec![0u16; info.max_subkey_name_len as usize + 1],
+            index: 0,
+        })
     }
 }
@@ -4,7 +4,7 @@ use utfx::{U16CStr, U16CString};
 use winapi::shared::winerror::ERROR_NO_MORE_ITEMS;
 use winapi::um::winreg::{RegEnumValueW, RegQueryInfoKeyW};
 
-use crate::{key::RegKey, util::U16AlignedU8Vec, Data};
+use crate::{key::RegKey, util::U16AlignedU8Vec, value::ParseMode, Data};
 
 #[derive(Debug, thiserror::Error)]
 #[non_exhaustive]
@@ -31,6 +31,7 @@ pub struct Values<'a> {
     name_buf: Vec<u16>,
     data_buf: U16AlignedU8Vec,
     index: u32,
+    mode: ParseMode,
 }
 
 pub struct ValueRef<'a> {
@@ -135,7 +136,11 @@ impl<'a> Iterator for Values<'a> {
             Err(e) => return Some(Err(Error::InvalidNul(e))),
         };
 
-        let data = match crate::value::parse_value_type_data(data_type, self.data_buf.clone()) {
+        let data = match crate::value::parse_value_type_data_with(
+            data_type,
+            &self.data_buf[..data_len as usize],
+            self.mode,
+        ) {
             Ok(v) => v,
             Err(e) => return Some(Err(Error::Data(e))),
         };
@@ -149,7 +154,12 @@ impl<'a> Iterator for Values<'a> {
 }
 
 impl<'a> Values<'a> {
+    #[inline]
     pub fn new(regkey: &'a RegKey) -> Result<Values<'a>, std::io::Error> {
+        Values::with_mode(regkey, ParseMode::default())
+    }
+
+    pub fn with_mode(regkey: &'a RegKey, mode: ParseMode) -> Result<Values<'a>, std::io::Error> {
         let mut value_count = 0u32;
         let mut max_value_name_len = 0u32;
         let mut max_value_data_len = 0u32;
@@ -177,6 +187,7 @@ impl<'a> Values<'a> {
                 name_buf: vec![0u16; max_value_name_len as usize + 1],
                 data_buf: U16AlignedU8Vec::new(max_value_data_len as usize),
                 index: 0,
+                mode,
             });
         }
 
@@ -1,18 +1,40 @@
+use std::{convert::Infallible, time::SystemTime};
+#[cfg(windows)]
 use std::{
-    convert::{Infallible, TryInto},
+    convert::{TryFrom, TryInto},
     fmt::Display,
     ptr::null_mut,
 };
 
-use utfx::{U16CStr, U16CString};
+use utfx::U16CString;
+#[cfg(windows)]
+use utfx::{U16CStr, U16Str};
+#[cfg(windows)]
+use winapi::shared::minwindef::FILETIME;
+#[cfg(windows)]
 use winapi::shared::minwindef::HKEY;
+#[cfg(windows)]
+use winapi::shared::winerror::{ERROR_INSUFFICIENT_BUFFER, ERROR_MORE_DATA};
+#[cfg(windows)]
+use winapi::um::winnt::{REG_CREATED_NEW_KEY, REG_OPTION_CREATE_LINK, REG_OPTION_OPEN_LINK};
+#[cfg(windows)]
 use winapi::um::winreg::{
-    RegCloseKey, RegCreateKeyExW, RegDeleteKeyW, RegDeleteTreeW, RegOpenCurrentUser, RegOpenKeyExW,
-    RegSaveKeyExW,
+    RegCloseKey, RegCreateKeyExW, RegDeleteKeyW, RegDeleteTreeW, RegGetKeySecurity,
+    RegOpenCurrentUser, RegOpenKeyExW, RegQueryInfoKeyW, RegSaveKeyExW,
 };
 
+#[cfg(windows)]
+use crate::descriptor::SecurityDescriptor;
+
+#[cfg(windows)]
 use crate::iter;
+#[cfg(windows)]
 use crate::sec::Security;
+#[cfg(windows)]
+use crate::tree::KeyTree;
+#[cfg(windows)]
+use crate::util::filetime_to_system_time;
+#[cfg(windows)]
 use crate::{value, Hive};
 
 #[derive(Debug, thiserror::Error)]
@@ -27,6 +49,20 @@ pub enum Error {
     #[error("Invalid null found in provided path")]
     InvalidNul(#[from] utfx::NulError<u16>),
 
+    #[error("Error parsing security descriptor")]
+    Descriptor(#[from] crate::descriptor::Error),
+
+    #[error("Error accessing value")]
+    Value(#[from] crate::value::Error),
+
+    #[cfg(windows)]
+    #[error("Error enumerating subkeys")]
+    Keys(#[from] crate::iter::keys::Error),
+
+    #[cfg(windows)]
+    #[error("Error enumerating values")]
+    Values(#[from] crate::iter::values::Error),
+
     #[error("An unknown IO error occurred for given path: {0:?}")]
     Unknown(String, #[source] std::io::Error),
 }
@@ -37,6 +73,23 @@ impl From<Infallible> for Error {
     }
 }
 
+/// The metadata of a key: its class name, when it was last written and the size of its contents.
+///
+/// Name lengths are in UTF-16 code units, without a terminating null, and data lengths are in
+/// bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyInfo {
+    pub class_name: Option<U16CString>,
+    pub last_written: SystemTime,
+    pub subkey_count: u32,
+    pub max_subkey_name_len: u32,
+    pub max_class_name_len: u32,
+    pub value_count: u32,
+    pub max_value_name_len: u32,
+    pub max_value_data_len: u32,
+}
+
+#[cfg(windows)]
 /// The safe representation of a Windows registry key.
 #[derive(Debug)]
 pub struct RegKey {
@@ -45,12 +98,13 @@ pub struct RegKey {
     pub(crate) path: U16CString,
 }
 
+#[cfg(windows)]
 impl Display for RegKey {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", &self.hive)?;
         let path = self.path.to_string_lossy();
 
-        if path != "" {
+        if !path.is_empty() {
             f.write_str(r"\")?;
             f.write_str(&path)?;
         }
@@ -59,6 +113,7 @@ impl Display for RegKey {
     }
 }
 
+#[cfg(windows)]
 impl Drop for RegKey {
     fn drop(&mut self) {
         // No point checking the return value here.
@@ -66,6 +121,7 @@ impl Drop for RegKey {
     }
 }
 
+#[cfg(windows)]
 impl RegKey {
     #[inline]
     pub fn open<P>(&self, path: P, sec: Security) -> Result<RegKey, Error>
@@ -81,6 +137,22 @@ impl RegKey {
         })
     }
 
+    /// Opens the symbolic link key at `path` itself, rather than the key that it points to. Its
+    /// target can be read from its `SymbolicLinkValue` value.
+    #[inline]
+    pub fn open_link<P>(&self, path: P, sec: Security) -> Result<RegKey, Error>
+    where
+        P: TryInto<U16CString>,
+        P::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        open_hkey_with_options(self.handle, &path, REG_OPTION_OPEN_LINK, sec).map(|handle| RegKey {
+            hive: self.hive,
+            handle,
+            path,
+        })
+    }
+
     #[inline]
     pub fn write<P>(&self, file_path: P) -> Result<(), Error>
     where
@@ -105,6 +177,23 @@ impl RegKey {
         })
     }
 
+    /// Creates a symbolic link key at `path` that points to `target`, a native path such as
+    /// `\Registry\Machine\Software\Vendor`. If the target cannot be set, a newly created link key is
+    /// deleted again. The returned key is the link itself.
+    #[inline]
+    pub fn create_link<P>(&self, path: P, target: &U16Str, sec: Security) -> Result<RegKey, Error>
+    where
+        P: TryInto<U16CString>,
+        P::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        create_link_hkey(self.handle, &path, target, sec).map(|handle| RegKey {
+            hive: self.hive,
+            handle,
+            path,
+        })
+    }
+
     #[inline]
     pub fn delete<P>(&self, path: P, is_recursive: bool) -> Result<(), Error>
     where
@@ -120,13 +209,33 @@ impl RegKey {
         delete_hkey(self.handle, U16CString::default(), is_recursive)
     }
 
+    /// Reads a value with the default
+    /// [`ParseMode::PreserveRaw`](value/enum.ParseMode.html#variant.PreserveRaw), so that data
+    /// which cannot be decoded is returned as [`Data::Other`](value/enum.Data.html#variant.Other)
+    /// and can be written back with [`set_value`](#method.set_value) unchanged.
     #[inline]
     pub fn value<S>(&self, value_name: S) -> Result<value::Data, value::Error>
     where
         S: TryInto<U16CString>,
         S::Error: Into<value::Error>,
     {
-        value::query_value(self.handle, value_name)
+        value::query_value(self.handle, value_name, value::ParseMode::default())
+    }
+
+    /// Like [`value`](#method.value), decoding the data with the given mode, such as
+    /// [`ParseMode::Strict`](value/enum.ParseMode.html#variant.Strict) to get an error for data
+    /// that cannot be decoded.
+    #[inline]
+    pub fn value_with_mode<S>(
+        &self,
+        value_name: S,
+        mode: value::ParseMode,
+    ) -> Result<value::Data, value::Error>
+    where
+        S: TryInto<U16CString>,
+        S::Error: Into<value::Error>,
+    {
+        value::query_value(self.handle, value_name, mode)
     }
 
     #[inline]
@@ -147,19 +256,179 @@ impl RegKey {
         value::set_value(self.handle, value_name, data)
     }
 
+    /// Reads a value and converts it to `T`, failing with
+    /// [`value::Error::TypeMismatch`](value/enum.Error.html#variant.TypeMismatch) if it is stored
+    /// as a different type. Data that cannot be decoded fails as it would in
+    /// [`ParseMode::Strict`](value/enum.ParseMode.html#variant.Strict).
+    ///
+    /// ```no_run
+    /// # #[cfg(windows)]
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use registry::{Hive, Security};
+    ///
+    /// let key = Hive::CurrentUser.open(r"Control Panel\Desktop", Security::Read)?;
+    /// let wallpaper = key.get::<String>("Wallpaper")?;
+    /// # Ok(())
+    /// # }
+    /// # #[cfg(not(windows))]
+    /// # fn main() {}
+    /// ```
+    #[inline]
+    pub fn get<T>(
+        &self,
+        value_name: impl TryInto<U16CString, Error = impl Into<value::Error>>,
+    ) -> Result<T, value::Error>
+    where
+        T: TryFrom<value::Data, Error = value::Error>,
+    {
+        T::try_from(self.value_with_mode(value_name, value::ParseMode::Strict)?)
+    }
+
+    /// Converts `value` to registry data and writes it.
+    #[inline]
+    pub fn set<T, S>(&self, value_name: S, value: T) -> Result<(), value::Error>
+    where
+        T: TryInto<value::Data>,
+        T::Error: Into<value::Error>,
+        S: TryInto<U16CString>,
+        S::Error: Into<value::Error>,
+    {
+        let data = value.try_into().map_err(Into::into)?;
+        self.set_value(value_name, &data)
+    }
+
+    /// Reads the type and bytes of a value exactly as stored, without decoding them.
+    #[inline]
+    pub fn raw_value<S>(&self, value_name: S) -> Result<(u32, Vec<u8>), value::Error>
+    where
+        S: TryInto<U16CString>,
+        S::Error: Into<value::Error>,
+    {
+        value::query_raw_value(self.handle, value_name)
+    }
+
+    /// Writes a value with the given type and bytes as they are, without validating them.
+    #[inline]
+    pub fn set_raw_value<S>(&self, value_name: S, ty: u32, bytes: &[u8]) -> Result<(), value::Error>
+    where
+        S: TryInto<U16CString>,
+        S::Error: Into<value::Error>,
+    {
+        value::set_raw_value(self.handle, value_name, ty, bytes)
+    }
+
     #[inline]
     pub fn keys(&self) -> iter::Keys<'_> {
         match iter::Keys::new(self) {
             Ok(v) => v,
-            Err(e) => unreachable!(e),
+            Err(e) => unreachable!("{}", e),
         }
     }
 
+    /// Iterates over the values of this key, decoding them like [`value`](#method.value).
     #[inline]
     pub fn values(&self) -> iter::Values<'_> {
-        match iter::Values::new(self) {
+        self.values_with_mode(value::ParseMode::default())
+    }
+
+    /// Like [`values`](#method.values), decoding the data with the given mode.
+    #[inline]
+    pub fn values_with_mode(&self, mode: value::ParseMode) -> iter::Values<'_> {
+        match iter::Values::with_mode(self, mode) {
             Ok(v) => v,
-            Err(e) => unreachable!(e),
+            Err(e) => unreachable!("{}", e),
+        }
+    }
+
+    /// Queries the class name, last write time and content sizes of this key.
+    #[inline]
+    pub fn info(&self) -> Result<KeyInfo, Error> {
+        query_info_hkey(self.handle).map_err(|io_error| {
+            let path = self.to_string();
+            match io_error.kind() {
+                std::io::ErrorKind::NotFound => Error::NotFound(path, io_error),
+                std::io::ErrorKind::PermissionDenied => Error::PermissionDenied(path, io_error),
+                _ => Error::Unknown(path, io_error),
+            }
+        })
+    }
+
+    /// Reads this key and everything below it into a [`KeyTree`](../tree/struct.KeyTree.html).
+    ///
+    /// Subkeys are opened with `Security::Read`. Symbolic links are not followed, and are read as
+    /// keys holding their `SymbolicLinkValue`. Descriptors are kept as `RegGetKeySecurity` returns
+    /// them, and a key whose descriptor cannot be read is given none.
+    pub fn to_tree(&self) -> Result<KeyTree, Error> {
+        let info = self.info()?;
+        let name = match self
+            .path
+            .as_slice()
+            .rsplit(|x| *x == u16::from(b'\\'))
+            .next()
+        {
+            Some(name) if !name.is_empty() => U16CString::new(name)?,
+            _ => U16CString::from_str(self.hive.to_string())?,
+        };
+
+        Ok(KeyTree {
+            name,
+            class_name: info.class_name,
+            last_written: info.last_written,
+            security: self.raw_security_descriptor().ok(),
+            values: self
+                .values()
+                .map(|x| x.map(iter::values::ValueRef::into_inner))
+                .collect::<Result<_, _>>()?,
+            subkeys: self
+                .keys()
+                .map(|x| x?.open_link(Security::Read)?.to_tree())
+                .collect::<Result<_, Error>>()?,
+        })
+    }
+
+    /// Reads the owner, group and DACL of this key. The key must have been opened with
+    /// `Security::ReadControl`, which `Security::Read` includes.
+    #[inline]
+    pub fn security_descriptor(&self) -> Result<SecurityDescriptor, Error> {
+        Ok(SecurityDescriptor::parse(&self.raw_security_descriptor()?)?)
+    }
+
+    /// The owner, group and DACL of this key in self-relative form, as `RegGetKeySecurity`
+    /// returns them.
+    fn raw_security_descriptor(&self) -> Result<Vec<u8>, Error> {
+        const OWNER_GROUP_DACL: u32 = 0x1 | 0x2 | 0x4;
+
+        let mut buf = vec![0u8; 256];
+        loop {
+            let mut len = buf.len() as u32;
+            let result = unsafe {
+                RegGetKeySecurity(
+                    self.handle,
+                    OWNER_GROUP_DACL,
+                    buf.as_mut_ptr() as *mut _,
+                    &mut len,
+                )
+            };
+
+            if result == ERROR_INSUFFICIENT_BUFFER as i32 {
+                buf.resize(len as usize, 0);
+                continue;
+            }
+
+            if result == 0 {
+                buf.truncate(len as usize);
+                return Ok(buf);
+            }
+
+            let io_error = std::io::Error::from_raw_os_error(result);
+            let path = self.to_string();
+            return match io_error.kind() {
+                std::io::ErrorKind::NotFound => Err(Error::NotFound(path, io_error)),
+                std::io::ErrorKind::PermissionDenied => {
+                    Err(Error::PermissionDenied(path, io_error))
+                }
+                _ => Err(Error::Unknown(path, io_error)),
+            };
         }
     }
 
@@ -187,14 +456,94 @@ impl RegKey {
     }
 }
 
+#[cfg(windows)]
+pub(crate) fn query_info_hkey(hkey: HKEY) -> Result<KeyInfo, std::io::Error> {
+    let mut class = vec![0u16; 64];
+    loop {
+        let mut class_len = class.len() as u32;
+        let mut subkey_count = 0u32;
+        let mut max_subkey_name_len = 0u32;
+        let mut max_class_name_len = 0u32;
+        let mut value_count = 0u32;
+        let mut max_value_name_len = 0u32;
+        let mut max_value_data_len = 0u32;
+        let mut last_written = FILETIME {
+            dwLowDateTime: 0,
+            dwHighDateTime: 0,
+        };
+
+        let result = unsafe {
+            RegQueryInfoKeyW(
+                hkey,
+                class.as_mut_ptr(),
+                &mut class_len,
+                null_mut(),
+                &mut subkey_count,
+                &mut max_subkey_name_len,
+                &mut max_class_name_len,
+                &mut value_count,
+                &mut max_value_name_len,
+                &mut max_value_data_len,
+                null_mut(),
+                &mut last_written,
+            )
+        };
+
+        if result == ERROR_MORE_DATA as i32 {
+            let len = class.len() * 2;
+            class.resize(len, 0);
+            continue;
+        }
+
+        if result != 0 {
+            return Err(std::io::Error::from_raw_os_error(result));
+        }
+
+        // Class names are not meant to hold nulls, so anything after one is dropped.
+        let class = &class[..class_len as usize];
+        let class_name = match class.iter().position(|x| *x == 0).unwrap_or(class.len()) {
+            0 => None,
+            len => Some(U16CString::new(&class[..len]).unwrap()),
+        };
+        let last_written =
+            u64::from(last_written.dwHighDateTime) << 32 | u64::from(last_written.dwLowDateTime);
+
+        return Ok(KeyInfo {
+            class_name,
+            last_written: filetime_to_system_time(last_written),
+            subkey_count,
+            max_subkey_name_len,
+            max_class_name_len,
+            value_count,
+            max_value_name_len,
+            max_value_data_len,
+        });
+    }
+}
+
+#[cfg(windows)]
+#[inline]
+pub(crate) fn open_hkey<P>(base: HKEY, path: P, sec: Security) -> Result<HKEY, Error>
+where
+    P: AsRef<U16CStr>,
+{
+    open_hkey_with_options(base, path, 0, sec)
+}
+
+#[cfg(windows)]
 #[inline]
-pub(crate) fn open_hkey<'a, P>(base: HKEY, path: P, sec: Security) -> Result<HKEY, Error>
+pub(crate) fn open_hkey_with_options<P>(
+    base: HKEY,
+    path: P,
+    options: u32,
+    sec: Security,
+) -> Result<HKEY, Error>
 where
     P: AsRef<U16CStr>,
 {
     let path = path.as_ref();
     let mut hkey = std::ptr::null_mut();
-    let result = unsafe { RegOpenKeyExW(base, path.as_ptr(), 0, sec.bits(), &mut hkey) };
+    let result = unsafe { RegOpenKeyExW(base, path.as_ptr(), options, sec.bits(), &mut hkey) };
 
     if result == 0 {
         return Ok(hkey);
@@ -209,8 +558,9 @@ where
     }
 }
 
+#[cfg(windows)]
 #[inline]
-pub(crate) fn save_hkey<'a, P>(hkey: HKEY, path: P) -> Result<(), Error>
+pub(crate) fn save_hkey<P>(hkey: HKEY, path: P) -> Result<(), Error>
 where
     P: AsRef<U16CStr>,
 {
@@ -230,6 +580,7 @@ where
     }
 }
 
+#[cfg(windows)]
 #[inline]
 pub(crate) fn delete_hkey<P>(base: HKEY, path: P, is_recursive: bool) -> Result<(), Error>
 where
@@ -256,29 +607,72 @@ where
     }
 }
 
+#[cfg(windows)]
 #[inline]
 pub(crate) fn create_hkey<P>(base: HKEY, path: P, sec: Security) -> Result<HKEY, Error>
+where
+    P: AsRef<U16CStr>,
+{
+    create_hkey_with_options(base, path, 0, sec).map(|(hkey, _)| hkey)
+}
+
+#[cfg(windows)]
+pub(crate) fn create_link_hkey<P>(
+    base: HKEY,
+    path: P,
+    target: &U16Str,
+    sec: Security,
+) -> Result<HKEY, Error>
+where
+    P: AsRef<U16CStr>,
+{
+    let sec = sec | Security::CreateLink | Security::SetValue | Security::Delete;
+    let (hkey, disposition) = create_hkey_with_options(base, path, REG_OPTION_CREATE_LINK, sec)?;
+    let data = value::Data::Link(target.to_ustring());
+
+    if let Err(e) = value::set_value(hkey, "SymbolicLinkValue", &data) {
+        // Don't leave a link without a target behind, but never delete a key we didn't create.
+        if disposition == REG_CREATED_NEW_KEY {
+            unsafe { RegDeleteKeyW(hkey, U16CString::default().as_ptr()) };
+        }
+        unsafe { RegCloseKey(hkey) };
+        return Err(e.into());
+    }
+    Ok(hkey)
+}
+
+/// Creates or opens the key at `path`, returning it along with whether it was
+/// `REG_CREATED_NEW_KEY` or `REG_OPENED_EXISTING_KEY`.
+#[cfg(windows)]
+#[inline]
+pub(crate) fn create_hkey_with_options<P>(
+    base: HKEY,
+    path: P,
+    options: u32,
+    sec: Security,
+) -> Result<(HKEY, u32), Error>
 where
     P: AsRef<U16CStr>,
 {
     let path = path.as_ref();
     let mut hkey = std::ptr::null_mut();
+    let mut disposition = 0;
     let result = unsafe {
         RegCreateKeyExW(
             base,
             path.as_ptr(),
             0,
             std::ptr::null_mut(),
-            0,
+            options,
             sec.bits(),
             std::ptr::null_mut(),
             &mut hkey,
-            std::ptr::null_mut(),
+            &mut disposition,
         )
     };
 
     if result == 0 {
-        return Ok(hkey);
+        return Ok((hkey, disposition));
     }
 
     let io_error = std::io::Error::from_raw_os_error(result);
@@ -290,7 +684,7 @@ where
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, windows))]
 mod tests {
     use crate::Hive;
 
@@ -301,4 +695,68 @@ mod tests {
             .unwrap();
         assert_eq!(key.to_string(), "HKEY_CURRENT_USER\\SOFTWARE\\Microsoft")
     }
+
+    #[test]
+    fn key_info() {
+        let key = Hive::CurrentUser
+            .open("SOFTWARE", crate::Security::Read)
+            .unwrap();
+        let info = key.info().unwrap();
+        assert_eq!(info.subkey_count as usize, key.keys().count());
+        assert!(info.last_written > std::time::UNIX_EPOCH);
+    }
+
+    #[test]
+    fn security_descriptor() {
+        let key = Hive::CurrentUser
+            .open("SOFTWARE", crate::Security::Read)
+            .unwrap();
+        let sd = key.security_descriptor().unwrap();
+        assert!(sd.owner.is_some());
+        assert!(sd.dacl.is_some());
+    }
+
+    #[test]
+    fn get_with_turbofish() {
+        let key = Hive::CurrentUser
+            .create(r"Test\registry-rust-get", crate::Security::AllAccess)
+            .unwrap();
+        key.set("Enabled", true).unwrap();
+        let enabled = key.get::<bool>("Enabled");
+        let name = String::from("Enabled");
+        let count = key.get::<u32>(name);
+        Hive::CurrentUser.delete("Test", true).unwrap();
+
+        assert!(enabled.unwrap());
+        assert_eq!(count.unwrap(), 1);
+    }
+
+    #[test]
+    fn tree_does_not_follow_links() {
+        let key = Hive::CurrentUser
+            .create(
+                r"Test
+egistry-rust-tree",
+                crate::Security::AllAccess,
+            )
+            .unwrap();
+        let target = utfx::U16String::from_str(r"\REGISTRY\MACHINE\SOFTWARE\Microsoft");
+        key.create_link("link", &target, crate::Security::Read)
+            .unwrap();
+
+        let tree = key.to_tree();
+        let security = key.raw_security_descriptor().unwrap();
+        key.open_link("link", crate::Security::Delete)
+            .unwrap()
+            .delete_self(false)
+            .unwrap();
+        Hive::CurrentUser.delete("Test", true).unwrap();
+
+        let tree = tree.unwrap();
+        assert_eq!(tree.security, Some(security));
+        let link = &tree.subkeys[0];
+        assert!(link.subkeys.is_empty());
+        assert_eq!(link.values.len(), 1);
+        assert_eq!(link.values[0].0.to_string_lossy(), "SymbolicLinkValue");
+    }
 }
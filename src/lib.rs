@@ -1,4 +1,3 @@
-#![cfg(windows)]
 #![deny(rust_2018_idioms)]
 
 //! # Registry
@@ -22,24 +21,64 @@
 //! assert_eq!(regkey.value("SomeValue")?, Data::U32(42));
 //! ```
 //!
+//! Plain Rust types can be read and written directly, with an error if the stored type does not match.
+//!
+//! ```ignore
+//! regkey.set("InstallDir", PathBuf::from(r"C:\Program Files\App"))?;
+//! let enabled: bool = regkey.get("Enabled")?;
+//! ```
+//!
 //! [`RegKey`](struct.RegKey.html)s also support iteration of all subkeys with the `keys()` function, and all values with the `values()` function.
 //!
+//! ## Platform support
+//!
+//! The data model in [`value`](value/index.html), along with [`Hive`](enum.Hive.html) and [`Security`](struct.Security.html),
+//! is available on every platform. Only the handle-based types such as [`RegKey`](struct.RegKey.html) and the
+//! [`iter`](iter/index.html) module require Windows.
+//!
+//! Code that needs to run against more than the live registry can be written against the
+//! [`Backend`](backend/trait.Backend.html) trait, which is implemented both for the Windows registry and for an
+//! in-memory [`MemoryRegistry`](backend/struct.MemoryRegistry.html) that is useful in tests.
+//!
+//! Hive files copied off a Windows system can be read on any platform with [`OfflineHive`](regf/struct.OfflineHive.html),
+//! and `.reg` files can be read and written with [`RegFile`](regfile/struct.RegFile.html). Group Policy settings
+//! in `Registry.pol` files are handled by [`PolicyFile`](pol/struct.PolicyFile.html).
+//!
+//! The owner, group and access control lists of a key are available as a
+//! [`SecurityDescriptor`](descriptor/struct.SecurityDescriptor.html), both from a live key and from an offline hive.
+//!
+//! Any two [`KeyTree`](tree/struct.KeyTree.html) snapshots, whether read from the live registry or from a hive file, can be
+//! compared with [`KeyTree::diff`](tree/struct.KeyTree.html#method.diff). The changes can be saved as a
+//! [`Patch`](patch/struct.Patch.html) and replayed onto another registry.
+//!
 
+pub mod backend;
+pub mod descriptor;
+pub mod diff;
 mod hive;
+#[cfg(windows)]
 pub mod iter;
 pub mod key;
+pub mod patch;
+pub mod pol;
+pub mod regf;
+pub mod regfile;
 mod sec;
+pub mod tree;
 mod util;
 pub mod value;
 
 pub use hive::Hive;
+#[cfg(windows)]
 #[doc(inline)]
 pub use key::RegKey;
+#[doc(inline)]
+pub use regf::OfflineHive;
 pub use sec::Security;
 #[doc(inline)]
 pub use value::Data;
 
-#[cfg(test)]
+#[cfg(all(test, windows))]
 mod tests {
     use super::*;
     use std::convert::TryInto;
@@ -0,0 +1,598 @@
+//! Patches: lists of key and value operations that can be saved, reviewed and replayed onto
+//! another registry.
+//!
+//! A patch is usually made from the [`Change`](../diff/enum.Change.html)s between a reference tree
+//! and a modified one. Every operation on a value carries the data it expects to replace, so that
+//! applying the patch to a registry that has drifted reports conflicts instead of silently
+//! overwriting them.
+//!
+//! Patches are written as text, one operation per line with its fields separated by tabs:
+//!
+//! ```text
+//! create-key  Software\Vendor\App
+//! set-value  Software\Vendor  Version  4:01000000  4:02000000
+//! delete-value  Software\Vendor  Path  1:43003a000000
+//! delete-key  Software\Old
+//! ```
+//!
+//! Data is written as its registry type followed by its bytes in hex, with `-` standing for a
+//! value that does not exist. `%`, tabs and line breaks in names are escaped as `%25`, `%09`,
+//! `%0A` and `%0D`, and unpaired surrogates as `%uXXXX`.
+
+use std::{fmt::Display, str::FromStr};
+
+use utfx::U16CString;
+
+use crate::backend::Backend;
+use crate::diff::Change;
+use crate::key;
+use crate::sec::Security;
+use crate::value::{self, parse_value_type_data_with, Data, ParseMode};
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Invalid patch at line {0}: {1}")]
+    Parse(usize, String),
+
+    #[error("Patch does not apply, {} operations conflict", .0.len())]
+    Conflicts(Vec<Conflict>),
+
+    #[error("Error accessing key")]
+    Key(#[from] key::Error),
+
+    #[error("Error accessing value")]
+    Value(#[from] value::Error),
+}
+
+/// A single step of a [`Patch`](struct.Patch.html). Paths are relative to the key the patch is
+/// applied to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    /// Creates a key, along with any missing parents. Creating a key that exists does nothing.
+    CreateKey { path: U16CString },
+    /// Deletes a key and everything below it. The key must exist.
+    DeleteKey { path: U16CString },
+    /// Sets a value whose current data must be `old`, or which must not exist if `old` is `None`.
+    SetValue {
+        path: U16CString,
+        name: U16CString,
+        old: Option<Data>,
+        new: Data,
+    },
+    /// Deletes a value whose current data must be `old`.
+    DeleteValue {
+        path: U16CString,
+        name: U16CString,
+        old: Data,
+    },
+}
+
+/// An operation that does not match the registry it is being applied to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    /// The index of the operation within the patch.
+    pub index: usize,
+    pub operation: Operation,
+    /// The data found in place of what the operation expected, or `None` if the value or key does
+    /// not exist.
+    pub actual: Option<Data>,
+}
+
+/// An ordered list of operations.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Patch {
+    pub operations: Vec<Operation>,
+}
+
+impl Patch {
+    /// Makes a patch that replays `changes`. Changes to a key's class name or last write time
+    /// cannot be replayed, so they are left out.
+    pub fn from_changes(changes: &[Change]) -> Patch {
+        let operations = changes
+            .iter()
+            .filter_map(|change| {
+                Some(match change.clone() {
+                    Change::KeyAdded { path, .. } => Operation::CreateKey { path },
+                    Change::KeyRemoved { path } => Operation::DeleteKey { path },
+                    Change::KeyModified { .. } => return None,
+                    Change::ValueAdded { path, name, data } => Operation::SetValue {
+                        path,
+                        name,
+                        old: None,
+                        new: data,
+                    },
+                    Change::ValueRemoved { path, name, data } => Operation::DeleteValue {
+                        path,
+                        name,
+                        old: data,
+                    },
+                    Change::ValueModified {
+                        path,
+                        name,
+                        old,
+                        new,
+                    } => Operation::SetValue {
+                        path,
+                        name,
+                        old: Some(old),
+                        new,
+                    },
+                })
+            })
+            .collect();
+
+        Patch { operations }
+    }
+
+    /// Checks every operation against the keys and values below `base` without changing
+    /// anything. Keys that the patch itself creates are treated as empty.
+    pub fn conflicts<B: Backend>(
+        &self,
+        backend: &B,
+        base: &B::Key,
+    ) -> Result<Vec<Conflict>, Error> {
+        let mut conflicts = vec![];
+
+        for (index, operation) in self.operations.iter().enumerate() {
+            let (actual, is_conflict) = match operation {
+                Operation::CreateKey { .. } => continue,
+                Operation::DeleteKey { path } => match open(backend, base, path)? {
+                    Some(_) => continue,
+                    None => (None, true),
+                },
+                Operation::SetValue {
+                    path, name, old, ..
+                } => {
+                    let actual = query(backend, base, path, name)?;
+                    let is_conflict = actual != *old;
+                    (actual, is_conflict)
+                }
+                Operation::DeleteValue { path, name, old } => {
+                    let actual = query(backend, base, path, name)?;
+                    let is_conflict = actual.as_ref() != Some(old);
+                    (actual, is_conflict)
+                }
+            };
+
+            if is_conflict {
+                conflicts.push(Conflict {
+                    index,
+                    operation: operation.clone(),
+                    actual,
+                });
+            }
+        }
+
+        Ok(conflicts)
+    }
+
+    /// Applies the patch to the keys and values below `base`. If any operation conflicts, nothing
+    /// is changed and the conflicts are returned as an error.
+    pub fn apply<B: Backend>(&self, backend: &B, base: &B::Key) -> Result<(), Error> {
+        let conflicts = self.conflicts(backend, base)?;
+        if !conflicts.is_empty() {
+            return Err(Error::Conflicts(conflicts));
+        }
+
+        self.apply_unchecked(backend, base)
+    }
+
+    /// Applies every operation in order without checking the data it expects to replace.
+    pub(crate) fn apply_unchecked<B, E>(&self, backend: &B, base: &B::Key) -> Result<(), E>
+    where
+        B: Backend,
+        E: From<key::Error> + From<value::Error>,
+    {
+        for operation in &self.operations {
+            match operation {
+                Operation::CreateKey { path } => {
+                    backend.create_key(base, path, Security::AllAccess)?;
+                }
+                Operation::DeleteKey { path } => {
+                    backend.delete_key(base, path, true)?;
+                }
+                Operation::SetValue {
+                    path, name, new, ..
+                } => {
+                    let key = backend.create_key(base, path, Security::AllAccess)?;
+                    backend.set_value(&key, name, new)?;
+                }
+                Operation::DeleteValue { path, name, .. } => {
+                    let key = backend.open_key(base, path, Security::AllAccess)?;
+                    backend.delete_value(&key, name)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+impl crate::RegKey {
+    /// Applies `patch` to the keys and values below this key. If any operation conflicts, nothing
+    /// is changed and the conflicts are returned as an error.
+    #[inline]
+    pub fn apply_patch(&self, patch: &Patch) -> Result<(), Error> {
+        patch.apply(&crate::backend::Win32Registry, self)
+    }
+}
+
+fn open<B: Backend>(
+    backend: &B,
+    base: &B::Key,
+    path: &U16CString,
+) -> Result<Option<B::Key>, Error> {
+    match backend.open_key(base, path, Security::Read) {
+        Ok(v) => Ok(Some(v)),
+        Err(key::Error::NotFound(..)) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn query<B: Backend>(
+    backend: &B,
+    base: &B::Key,
+    path: &U16CString,
+    name: &U16CString,
+) -> Result<Option<Data>, Error> {
+    let key = match open(backend, base, path)? {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    match backend.query_value(&key, name) {
+        Ok(v) => Ok(Some(v)),
+        Err(value::Error::NotFound(..)) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+impl Display for Patch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for operation in &self.operations {
+            match operation {
+                Operation::CreateKey { path } => writeln!(f, "create-key\t{}", escape(path))?,
+                Operation::DeleteKey { path } => writeln!(f, "delete-key\t{}", escape(path))?,
+                Operation::SetValue {
+                    path,
+                    name,
+                    old,
+                    new,
+                } => writeln!(
+                    f,
+                    "set-value\t{}\t{}\t{}\t{}",
+                    escape(path),
+                    escape(name),
+                    old.as_ref().map(format_data).unwrap_or_else(|| "-".into()),
+                    format_data(new)
+                )?,
+                Operation::DeleteValue { path, name, old } => writeln!(
+                    f,
+                    "delete-value\t{}\t{}\t{}",
+                    escape(path),
+                    escape(name),
+                    format_data(old)
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Patch {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut operations = vec![];
+
+        for (i, line) in s.lines().enumerate() {
+            let invalid = |reason: &str| Error::Parse(i + 1, reason.to_string());
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields = line.split('\t').collect::<Vec<_>>();
+            let field = |index: usize| {
+                fields
+                    .get(index)
+                    .copied()
+                    .ok_or_else(|| invalid("missing field"))
+            };
+            let name =
+                |index: usize| unescape(field(index)?).ok_or_else(|| invalid("invalid name"));
+            let data =
+                |index: usize| parse_data(field(index)?).ok_or_else(|| invalid("invalid data"));
+
+            let (operation, len) = match fields[0] {
+                "create-key" => (Operation::CreateKey { path: name(1)? }, 2),
+                "delete-key" => (Operation::DeleteKey { path: name(1)? }, 2),
+                "set-value" => {
+                    let old = match field(3)? {
+                        "-" => None,
+                        _ => Some(data(3)?),
+                    };
+                    let operation = Operation::SetValue {
+                        path: name(1)?,
+                        name: name(2)?,
+                        old,
+                        new: data(4)?,
+                    };
+                    (operation, 5)
+                }
+                "delete-value" => {
+                    let operation = Operation::DeleteValue {
+                        path: name(1)?,
+                        name: name(2)?,
+                        old: data(3)?,
+                    };
+                    (operation, 4)
+                }
+                _ => return Err(invalid("unknown operation")),
+            };
+            if fields.len() != len {
+                return Err(invalid("unexpected field"));
+            }
+
+            operations.push(operation);
+        }
+
+        Ok(Patch { operations })
+    }
+}
+
+fn escape(s: &U16CString) -> String {
+    let mut escaped = String::new();
+    for c in std::char::decode_utf16(s.as_slice().iter().copied()) {
+        match c {
+            Ok('%') => escaped.push_str("%25"),
+            Ok('\t') => escaped.push_str("%09"),
+            Ok('\n') => escaped.push_str("%0A"),
+            Ok('\r') => escaped.push_str("%0D"),
+            Ok(c) => escaped.push(c),
+            Err(e) => escaped.push_str(&format!("%u{:04X}", e.unpaired_surrogate())),
+        }
+    }
+    escaped
+}
+
+fn unescape(s: &str) -> Option<U16CString> {
+    let mut units = vec![];
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            let mut buf = [0; 2];
+            units.extend_from_slice(c.encode_utf16(&mut buf));
+            continue;
+        }
+
+        let rest = chars.as_str();
+        let (unit, len) = match rest.strip_prefix('u') {
+            Some(hex) => (u16::from_str_radix(hex.get(..4)?, 16).ok()?, 5),
+            None => (u16::from(u8::from_str_radix(rest.get(..2)?, 16).ok()?), 2),
+        };
+        units.push(unit);
+        chars = rest[len..].chars();
+    }
+
+    U16CString::new(units).ok()
+}
+
+fn format_data(data: &Data) -> String {
+    let hex = data
+        .to_bytes()
+        .iter()
+        .map(|x| format!("{:02x}", x))
+        .collect::<String>();
+    format!("{}:{}", data.raw_type(), hex)
+}
+
+fn parse_data(s: &str) -> Option<Data> {
+    let (ty, hex) = s.split_at(s.find(':')?);
+    let ty = ty.parse::<u32>().ok()?;
+    let hex = &hex[1..];
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect::<Option<Vec<_>>>()?;
+
+    parse_value_type_data_with(ty, &bytes, ParseMode::PreserveRaw).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+    use std::time::UNIX_EPOCH;
+
+    use crate::backend::MemoryRegistry;
+    use crate::diff::DiffOptions;
+    use crate::tree::KeyTree;
+    use crate::Hive;
+
+    fn s(x: &str) -> U16CString {
+        x.try_into().unwrap()
+    }
+
+    fn sample_patch() -> Patch {
+        Patch {
+            operations: vec![
+                Operation::CreateKey {
+                    path: s(r"Software\Vendor\App"),
+                },
+                Operation::SetValue {
+                    path: s(r"Software\Vendor"),
+                    name: s("Version"),
+                    old: Some(Data::U32(1)),
+                    new: Data::U32(2),
+                },
+                Operation::SetValue {
+                    path: s(r"Software\Vendor\App"),
+                    name: s("Odd\t100%"),
+                    old: None,
+                    new: Data::MultiString(vec![s("a"), s("b")]),
+                },
+                Operation::DeleteValue {
+                    path: s(r"Software\Vendor"),
+                    name: s(""),
+                    old: Data::String(s("C:")),
+                },
+                Operation::DeleteKey {
+                    path: s(r"Software\Old"),
+                },
+            ],
+        }
+    }
+
+    fn sample_registry() -> (MemoryRegistry, crate::backend::MemoryKey) {
+        let registry = MemoryRegistry::new();
+        let root = registry
+            .open_hive(Hive::CurrentUser, Security::AllAccess)
+            .unwrap();
+        let vendor = registry
+            .create_key(&root, r"Software\Vendor", Security::AllAccess)
+            .unwrap();
+        registry
+            .set_value(&vendor, "Version", &Data::U32(1))
+            .unwrap();
+        registry
+            .set_value(&vendor, "", &Data::String(s("C:")))
+            .unwrap();
+        registry
+            .create_key(&root, r"Software\Old\Nested", Security::AllAccess)
+            .unwrap();
+        (registry, root)
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let patch = sample_patch();
+        let text = patch.to_string();
+        assert_eq!(
+            text.lines().nth(2).unwrap(),
+            "set-value\tSoftware\\Vendor\\App\tOdd%09100%25\t-\t7:61000000620000000000"
+        );
+        assert_eq!(text.parse::<Patch>().unwrap(), patch);
+
+        let name = U16CString::new(vec![0xD800, u16::from(b'x')]).unwrap();
+        assert_eq!(unescape(&escape(&name)), Some(name));
+
+        let text = "delete-value\tA\tB\t4:0100\nset-value\tA\tC\t-\t100000:ff\n";
+        let patch = text.parse::<Patch>().unwrap();
+        assert_eq!(
+            patch.operations[0],
+            Operation::DeleteValue {
+                path: s("A"),
+                name: s("B"),
+                old: Data::Other {
+                    ty: 4,
+                    bytes: vec![1, 0]
+                },
+            }
+        );
+        assert_eq!(patch.to_string(), text);
+    }
+
+    #[test]
+    fn invalid_text() {
+        for (text, line) in &[
+            ("create-key", 1),
+            ("\n# comment\nrename-key\tA", 3),
+            ("create-key\tA\tB", 1),
+            ("delete-value\tA\tB\t4:zz000000", 1),
+            ("delete-key\t%u12", 1),
+        ] {
+            assert!(
+                matches!(text.parse::<Patch>(), Err(Error::Parse(x, _)) if x == *line),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn apply_to_registry() {
+        let (registry, root) = sample_registry();
+        sample_patch().apply(&registry, &root).unwrap();
+
+        let vendor = registry
+            .open_key(&root, r"Software\Vendor", Security::Read)
+            .unwrap();
+        assert_eq!(
+            registry.query_value(&vendor, "Version").unwrap(),
+            Data::U32(2)
+        );
+        assert!(registry.query_value(&vendor, "").is_err());
+        let app = registry
+            .open_key(&root, r"Software\Vendor\App", Security::Read)
+            .unwrap();
+        assert_eq!(
+            registry.query_value(&app, "Odd\t100%").unwrap(),
+            Data::MultiString(vec![s("a"), s("b")])
+        );
+        assert!(registry
+            .open_key(&root, r"Software\Old", Security::Read)
+            .is_err());
+    }
+
+    #[test]
+    fn conflicts_leave_registry_unchanged() {
+        let (registry, root) = sample_registry();
+        let vendor = registry
+            .open_key(&root, r"Software\Vendor", Security::AllAccess)
+            .unwrap();
+        registry
+            .set_value(&vendor, "Version", &Data::U32(5))
+            .unwrap();
+        registry.delete_key(&root, r"Software\Old", true).unwrap();
+
+        let conflicts = match sample_patch().apply(&registry, &root) {
+            Err(Error::Conflicts(v)) => v,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(
+            conflicts
+                .iter()
+                .map(|x| (x.index, x.actual.clone()))
+                .collect::<Vec<_>>(),
+            vec![(1, Some(Data::U32(5))), (4, None)]
+        );
+        assert_eq!(
+            registry.query_value(&vendor, "Version").unwrap(),
+            Data::U32(5)
+        );
+        assert!(registry
+            .open_key(&root, r"Software\Vendor\App", Security::Read)
+            .is_err());
+    }
+
+    #[test]
+    fn replay_a_diff() {
+        let mut old = KeyTree::new(s("ROOT"));
+        old.create(&s(r"Software\Vendor"))
+            .set_value(s("Version"), Data::U32(1));
+        old.create(&s(r"Software\Old"));
+        let mut new = old.clone();
+        new.create(&s(r"Software\Vendor"))
+            .set_value(s("Version"), Data::U32(2));
+        new.create(&s(r"Software\Vendor\App"))
+            .set_value(s("Enabled"), Data::U32(1));
+        new.subkeys[0].subkeys.retain(|x| x.name != s("Old"));
+        new.last_written = UNIX_EPOCH;
+
+        let patch = Patch::from_changes(&old.diff(&new, DiffOptions::default()));
+        assert_eq!(patch.operations.len(), 4);
+
+        let (registry, root) = sample_registry();
+        patch.apply(&registry, &root).unwrap();
+        let app = registry
+            .open_key(&root, r"Software\Vendor\App", Security::Read)
+            .unwrap();
+        assert_eq!(registry.query_value(&app, "Enabled").unwrap(), Data::U32(1));
+    }
+}
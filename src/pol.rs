@@ -0,0 +1,465 @@
+//! Reading and writing Group Policy `Registry.pol` files.
+//!
+//! A `Registry.pol` file holds the registry settings of a Group Policy Object as a `PReg` header
+//! followed by `[key;value;type;size;data]` records in UTF-16LE. Besides plain values, a few
+//! pseudo-value names stand for deletions:
+//!
+//! - `**del.Name` deletes the value `Name`.
+//! - `**delvals.` deletes every value of the key.
+//! - `**DeleteValues` deletes the values listed in its data, separated by `;`.
+//!
+//! These are read into [`Action`](enum.Action.html)s, so policies can be authored and audited on
+//! any platform. Other pseudo-values, such as `**soft.`, are kept as ordinary values so that they
+//! are written back unchanged.
+
+use utfx::{U16CStr, U16CString};
+
+use crate::util::eq_ignore_case;
+use crate::value::{self, parse_value_type_data_with, Data, ParseMode, Type};
+
+const SIGNATURE: &[u8; 4] = b"PReg";
+const VERSION: u32 = 1;
+
+const DELETE_VALUE_PREFIX: &str = "**del.";
+const DELETE_ALL_VALUES: &str = "**delvals.";
+const DELETE_VALUES: &str = "**DeleteValues";
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Invalid Registry.pol signature")]
+    InvalidSignature,
+
+    #[error("Unsupported Registry.pol version: {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("Invalid Registry.pol record at offset {0:#x}: {1}")]
+    Parse(usize, String),
+
+    #[error("Value of the record at offset {0:#x} cannot be decoded")]
+    Data(usize, #[source] value::Error),
+}
+
+/// What a [`Record`](struct.Record.html) does to its key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Creates the key, written as a record with no value name, no data and type `REG_NONE`. An
+    /// empty default value of type `REG_NONE` is written the same way, and is read back as this.
+    CreateKey,
+    /// Sets a value. An empty name is the key's default value.
+    SetValue { name: U16CString, data: Data },
+    /// Deletes a single value, written as `**del.Name`.
+    DeleteValue { name: U16CString },
+    /// Deletes every value of the key, written as `**delvals.`.
+    DeleteAllValues,
+    /// Deletes the listed values, written as `**DeleteValues`.
+    DeleteValues { names: Vec<U16CString> },
+}
+
+/// A single setting: an action on the key at a path such as `Software\Policies\Vendor`, which is
+/// relative to the hive that the policy applies to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub key: U16CString,
+    pub action: Action,
+}
+
+/// The records of a `Registry.pol` file, in the order they are applied.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PolicyFile {
+    pub records: Vec<Record>,
+}
+
+impl Record {
+    /// Whether this record is for the key at `path`, compared without regard to case.
+    #[inline]
+    pub fn is_for_key(&self, path: &U16CStr) -> bool {
+        eq_ignore_case(self.key.as_slice(), path.as_slice())
+    }
+}
+
+impl PolicyFile {
+    #[inline]
+    pub fn new() -> PolicyFile {
+        PolicyFile::default()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<PolicyFile, Error> {
+        if bytes.get(..4) != Some(&SIGNATURE[..]) {
+            return Err(Error::InvalidSignature);
+        }
+        let mut reader = Reader { bytes, offset: 4 };
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+
+        let mut records = vec![];
+        while reader.offset < bytes.len() {
+            records.push(reader.record()?);
+        }
+
+        Ok(PolicyFile { records })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = SIGNATURE.to_vec();
+        buf.extend_from_slice(&VERSION.to_le_bytes());
+
+        for record in &self.records {
+            let (name, ty, data) = match &record.action {
+                Action::CreateKey => (vec![], Type::None as u32, vec![]),
+                Action::SetValue { name, data } => {
+                    (name.as_slice().to_vec(), data.raw_type(), data.to_bytes())
+                }
+                Action::DeleteValue { name } => {
+                    let name = [&to_units(DELETE_VALUE_PREFIX), name.as_slice()].concat();
+                    (name, Type::String as u32, placeholder())
+                }
+                Action::DeleteAllValues => (
+                    to_units(DELETE_ALL_VALUES),
+                    Type::String as u32,
+                    placeholder(),
+                ),
+                Action::DeleteValues { names } => {
+                    let list = names
+                        .iter()
+                        .flat_map(|x| x.as_slice().iter().copied().chain(to_units(";")))
+                        .chain(Some(0))
+                        .flat_map(u16::to_le_bytes)
+                        .collect();
+                    (to_units(DELETE_VALUES), Type::String as u32, list)
+                }
+            };
+
+            push_units(&mut buf, &to_units("["));
+            push_units(&mut buf, record.key.as_slice_with_nul());
+            push_units(&mut buf, &to_units(";"));
+            push_units(&mut buf, &name);
+            push_units(&mut buf, &[0]);
+            push_units(&mut buf, &to_units(";"));
+            buf.extend_from_slice(&ty.to_le_bytes());
+            push_units(&mut buf, &to_units(";"));
+            buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            push_units(&mut buf, &to_units(";"));
+            buf.extend_from_slice(&data);
+            push_units(&mut buf, &to_units("]"));
+        }
+
+        buf
+    }
+}
+
+fn to_units(s: &str) -> Vec<u16> {
+    s.encode_utf16().collect()
+}
+
+fn utf16_bytes(units: &[u16]) -> Vec<u8> {
+    units.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+fn push_units(buf: &mut Vec<u8>, units: &[u16]) {
+    buf.extend(utf16_bytes(units));
+}
+
+/// The data written for deletions, which Windows ignores: a single space.
+fn placeholder() -> Vec<u8> {
+    utf16_bytes(&to_units(" \0"))
+}
+
+fn strip_prefix_ignore_case<'a>(name: &'a [u16], prefix: &str) -> Option<&'a [u16]> {
+    let prefix = to_units(prefix);
+    match name.get(..prefix.len()) {
+        Some(x) if eq_ignore_case(x, &prefix) => Some(&name[prefix.len()..]),
+        _ => None,
+    }
+}
+
+fn to_ucstring(units: &[u16]) -> U16CString {
+    // Safety: names are read up to their nul terminator, and lists are split at nuls.
+    unsafe { U16CString::from_vec_unchecked(units.to_vec()) }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl Reader<'_> {
+    fn truncated(&self) -> Error {
+        Error::Parse(self.offset, "unexpected end of file".into())
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        let x = self
+            .bytes
+            .get(self.offset..self.offset + 2)
+            .ok_or_else(|| self.truncated())?;
+        self.offset += 2;
+        Ok(u16::from_le_bytes([x[0], x[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        let x = self
+            .bytes
+            .get(self.offset..self.offset + 4)
+            .ok_or_else(|| self.truncated())?;
+        self.offset += 4;
+        Ok(u32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), Error> {
+        let offset = self.offset;
+        match self.u16()? {
+            x if u32::from(x) == u32::from(c) => Ok(()),
+            _ => Err(Error::Parse(offset, format!("expected '{}'", c))),
+        }
+    }
+
+    /// Reads a string up to and including its nul terminator.
+    fn string(&mut self) -> Result<Vec<u16>, Error> {
+        let mut units = vec![];
+        loop {
+            match self.u16()? {
+                0 => return Ok(units),
+                x => units.push(x),
+            }
+        }
+    }
+
+    fn record(&mut self) -> Result<Record, Error> {
+        let offset = self.offset;
+        self.expect('[')?;
+        let key = self.string()?;
+        self.expect(';')?;
+        let name = self.string()?;
+        self.expect(';')?;
+        let ty = self.u32()?;
+        self.expect(';')?;
+        let size = self.u32()? as usize;
+        self.expect(';')?;
+        let data = self
+            .bytes
+            .get(self.offset..self.offset + size)
+            .ok_or_else(|| self.truncated())?;
+        self.offset += size;
+        self.expect(']')?;
+
+        let action = if name.is_empty() && data.is_empty() && ty == Type::None as u32 {
+            Action::CreateKey
+        } else if let Some(name) = strip_prefix_ignore_case(&name, DELETE_VALUE_PREFIX) {
+            Action::DeleteValue {
+                name: to_ucstring(name),
+            }
+        } else if eq_ignore_case(&name, &to_units(DELETE_ALL_VALUES)) {
+            Action::DeleteAllValues
+        } else if eq_ignore_case(&name, &to_units(DELETE_VALUES)) {
+            let units = data
+                .chunks_exact(2)
+                .map(|x| u16::from_le_bytes([x[0], x[1]]))
+                .take_while(|x| *x != 0)
+                .collect::<Vec<_>>();
+            let names = units
+                .split(|x| *x == u16::from(b';'))
+                .filter(|x| !x.is_empty())
+                .map(to_ucstring)
+                .collect();
+            Action::DeleteValues { names }
+        } else {
+            Action::SetValue {
+                name: to_ucstring(&name),
+                data: parse_value_type_data_with(ty, data, ParseMode::PreserveRaw)
+                    .map_err(|e| Error::Data(offset, e))?,
+            }
+        };
+
+        Ok(Record {
+            key: to_ucstring(&key),
+            action,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    fn s(x: &str) -> U16CString {
+        x.try_into().unwrap()
+    }
+
+    fn record(key: &str, action: Action) -> Record {
+        Record {
+            key: s(key),
+            action,
+        }
+    }
+
+    fn sample_file() -> PolicyFile {
+        let key = r"Software\Policies\Vendor";
+        PolicyFile {
+            records: vec![
+                record(key, Action::CreateKey),
+                record(
+                    key,
+                    Action::SetValue {
+                        name: s("Enabled"),
+                        data: Data::U32(1),
+                    },
+                ),
+                record(
+                    key,
+                    Action::SetValue {
+                        name: s("**soft.Server"),
+                        data: Data::String(s("example.com")),
+                    },
+                ),
+                record(key, Action::DeleteValue { name: s("Old") }),
+                record(r"Software\Policies\Vendor\List", Action::DeleteAllValues),
+                record(
+                    key,
+                    Action::DeleteValues {
+                        names: vec![s("A"), s("B")],
+                    },
+                ),
+            ],
+        }
+    }
+
+    fn utf16(s: &str) -> Vec<u8> {
+        s.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let file = sample_file();
+        assert_eq!(PolicyFile::from_bytes(&file.to_bytes()).unwrap(), file);
+        assert_eq!(
+            PolicyFile::from_bytes(b"PReg\x01\x00\x00\x00").unwrap(),
+            PolicyFile::new()
+        );
+    }
+
+    #[test]
+    fn empty_values_are_not_create_key() {
+        let key = r"Software\Policies\Vendor";
+        let file = PolicyFile {
+            records: vec![
+                record(
+                    key,
+                    Action::SetValue {
+                        name: s(""),
+                        data: Data::Binary(vec![]),
+                    },
+                ),
+                record(
+                    key,
+                    Action::SetValue {
+                        name: s("Empty"),
+                        data: Data::None,
+                    },
+                ),
+                record(key, Action::CreateKey),
+            ],
+        };
+        assert_eq!(PolicyFile::from_bytes(&file.to_bytes()).unwrap(), file);
+    }
+
+    #[test]
+    fn record_layout() {
+        let file = PolicyFile {
+            records: vec![record(
+                r"A\B",
+                Action::SetValue {
+                    name: s("V"),
+                    data: Data::U32(2),
+                },
+            )],
+        };
+
+        let mut expected = b"PReg\x01\x00\x00\x00".to_vec();
+        expected.extend(utf16("[A\\B\0;V\0;"));
+        expected.extend(&[4, 0, 0, 0]);
+        expected.extend(utf16(";"));
+        expected.extend(&[4, 0, 0, 0]);
+        expected.extend(utf16(";"));
+        expected.extend(&[2, 0, 0, 0]);
+        expected.extend(utf16("]"));
+        assert_eq!(file.to_bytes(), expected);
+    }
+
+    #[test]
+    fn pseudo_values() {
+        let mut bytes = b"PReg\x01\x00\x00\x00".to_vec();
+        for (name, data) in &[
+            ("**DEL.Old", " \0"),
+            ("**DelVals.", " \0"),
+            ("**DeleteValues", "A;B;\0"),
+        ] {
+            let data = utf16(data);
+            bytes.extend(utf16(&format!("[K\0;{}\0;", name)));
+            bytes.extend(&1u32.to_le_bytes());
+            bytes.extend(utf16(";"));
+            bytes.extend(&(data.len() as u32).to_le_bytes());
+            bytes.extend(utf16(";"));
+            bytes.extend(data);
+            bytes.extend(utf16("]"));
+        }
+
+        let file = PolicyFile::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            file.records,
+            vec![
+                record("K", Action::DeleteValue { name: s("Old") }),
+                record("K", Action::DeleteAllValues),
+                record(
+                    "K",
+                    Action::DeleteValues {
+                        names: vec![s("A"), s("B")]
+                    }
+                ),
+            ]
+        );
+        assert!(file.records.iter().all(|x| x.is_for_key(&s("k"))));
+    }
+
+    #[test]
+    fn invalid_files() {
+        assert!(matches!(
+            PolicyFile::from_bytes(b"REGF"),
+            Err(Error::InvalidSignature)
+        ));
+        assert!(matches!(
+            PolicyFile::from_bytes(b"PReg\x02\x00\x00\x00"),
+            Err(Error::UnsupportedVersion(2))
+        ));
+
+        let bytes = sample_file().to_bytes();
+        assert!(matches!(
+            PolicyFile::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(Error::Parse(..))
+        ));
+
+        let mut bytes = b"PReg\x01\x00\x00\x00".to_vec();
+        bytes.extend(utf16("[K\0;V\0;"));
+        bytes.extend(&4u32.to_le_bytes());
+        bytes.extend(utf16(";"));
+        bytes.extend(&2u32.to_le_bytes());
+        bytes.extend(utf16(";"));
+        bytes.extend(&[1, 0]);
+        bytes.extend(utf16("]"));
+        let file = PolicyFile::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            file.records[0].action,
+            Action::SetValue {
+                name: U16CString::from_str("V").unwrap(),
+                data: Data::Other {
+                    ty: 4,
+                    bytes: vec![1, 0]
+                },
+            }
+        );
+        assert_eq!(file.to_bytes(), bytes);
+    }
+}
@@ -0,0 +1,91 @@
+use std::{convert::TryInto, time::SystemTime};
+
+use utfx::U16String;
+
+use super::cell::{le_u32, le_u64};
+use super::Error;
+use crate::util::filetime_to_system_time;
+
+/// The size of the base block at the start of every primary hive file.
+pub(crate) const BASE_BLOCK_SIZE: usize = 4096;
+
+/// The number of bytes covered by the base block checksum.
+const CHECKSUM_LEN: usize = 508;
+
+/// The header of a regf hive file.
+#[derive(Debug, Clone)]
+pub struct BaseBlock {
+    /// Incremented before the hive is written to.
+    pub primary_sequence: u32,
+    /// Set to the primary sequence number once a write has completed.
+    pub secondary_sequence: u32,
+    pub last_written: SystemTime,
+    pub major_version: u32,
+    pub minor_version: u32,
+    /// `0` for a primary file, `1` or `2` for transaction logs.
+    pub file_type: u32,
+    /// The offset of the root key node, relative to the start of the hive bins.
+    pub root_cell: u32,
+    /// The total size of all hive bins, in bytes.
+    pub hive_bins_size: u32,
+    /// The trailing part of the path the hive was last loaded from.
+    pub file_name: U16String,
+    pub checksum: u32,
+}
+
+impl BaseBlock {
+    pub(crate) fn parse(buf: &[u8]) -> Result<BaseBlock, Error> {
+        if buf.len() < CHECKSUM_LEN + 4 {
+            return Err(Error::Truncated(buf.len()));
+        }
+
+        if &buf[0..4] != b"regf" {
+            return Err(Error::InvalidSignature);
+        }
+
+        let major_version = le_u32(buf, 20);
+        let minor_version = le_u32(buf, 24);
+        if major_version != 1 || !(2..=6).contains(&minor_version) {
+            return Err(Error::UnsupportedVersion(major_version, minor_version));
+        }
+
+        let file_name = buf[48..112]
+            .chunks_exact(2)
+            .map(|x| u16::from_le_bytes([x[0], x[1]]))
+            .take_while(|x| *x != 0)
+            .collect::<Vec<_>>();
+
+        Ok(BaseBlock {
+            primary_sequence: le_u32(buf, 4),
+            secondary_sequence: le_u32(buf, 8),
+            last_written: filetime_to_system_time(le_u64(buf, 12)),
+            major_version,
+            minor_version,
+            file_type: le_u32(buf, 28),
+            root_cell: le_u32(buf, 36),
+            hive_bins_size: le_u32(buf, 40),
+            file_name: U16String::from_vec(file_name),
+            checksum: le_u32(buf, CHECKSUM_LEN),
+        })
+    }
+
+    /// Whether the last write to the hive did not complete, so that the hive bins may be
+    /// inconsistent without replaying its transaction logs.
+    #[inline]
+    pub fn is_dirty(&self) -> bool {
+        self.primary_sequence != self.secondary_sequence
+    }
+}
+
+/// Calculates the checksum of a base block, the XOR of its first 127 doublewords.
+pub(crate) fn checksum(buf: &[u8]) -> u32 {
+    let sum = buf[..CHECKSUM_LEN].chunks_exact(4).fold(0u32, |acc, x| {
+        acc ^ u32::from_le_bytes(x.try_into().unwrap())
+    });
+
+    match sum {
+        0 => 1,
+        0xFFFF_FFFF => 0xFFFF_FFFE,
+        sum => sum,
+    }
+}
@@ -0,0 +1,291 @@
+use std::convert::TryInto;
+
+use super::{Error, NameRef};
+
+/// The key is the root of the hive.
+pub(crate) const KEY_HIVE_ENTRY: u16 = 0x0004;
+/// The key cannot be deleted.
+pub(crate) const KEY_NO_DELETE: u16 = 0x0008;
+/// The key name is stored as Latin-1 rather than UTF-16.
+pub(crate) const KEY_COMP_NAME: u16 = 0x0020;
+/// The value name is stored as Latin-1 rather than UTF-16.
+pub(crate) const VALUE_COMP_NAME: u16 = 0x0001;
+/// Set in a value's data size when the data is stored in the data offset field itself.
+pub(crate) const DATA_IN_OFFSET: u32 = 0x8000_0000;
+/// Marks an offset field that does not point to a cell.
+pub(crate) const NO_CELL: u32 = 0xFFFF_FFFF;
+/// From version 1.4, data longer than this is split into segments of this size.
+pub(crate) const MAX_DATA_SEGMENT_LEN: usize = 16344;
+/// Windows nests keys no deeper than this. Walks over a hive stop here, so that corrupt data
+/// cannot make them loop forever or overflow the stack.
+pub(crate) const MAX_DEPTH: usize = 512;
+
+/// A key node (`nk`) record.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct KeyNode<'a> {
+    pub flags: u16,
+    pub last_written: u64,
+    pub parent: u32,
+    pub subkey_count: u32,
+    pub subkey_list: u32,
+    pub value_count: u32,
+    pub value_list: u32,
+    pub security: u32,
+    pub class_name: u32,
+    /// The longest subkey name, in bytes as UTF-16. The upper half of the field holds flags.
+    pub max_subkey_name_len: u16,
+    /// The longest subkey class name, in bytes.
+    pub max_class_name_len: u32,
+    /// The longest value name, in bytes as UTF-16.
+    pub max_value_name_len: u32,
+    pub max_value_data_len: u32,
+    pub class_name_len: u16,
+    pub name: &'a [u8],
+}
+
+impl<'a> KeyNode<'a> {
+    const HEADER_LEN: usize = 76;
+
+    pub fn parse(offset: u32, buf: &'a [u8]) -> Result<KeyNode<'a>, Error> {
+        if buf.len() < Self::HEADER_LEN || &buf[0..2] != b"nk" {
+            return Err(Error::InvalidCell(offset));
+        }
+
+        let name_len = le_u16(buf, 72) as usize;
+        let name = buf
+            .get(Self::HEADER_LEN..Self::HEADER_LEN + name_len)
+            .ok_or(Error::InvalidCell(offset))?;
+
+        Ok(KeyNode {
+            flags: le_u16(buf, 2),
+            last_written: le_u64(buf, 4),
+            parent: le_u32(buf, 16),
+            subkey_count: le_u32(buf, 20),
+            subkey_list: le_u32(buf, 28),
+            value_count: le_u32(buf, 36),
+            value_list: le_u32(buf, 40),
+            security: le_u32(buf, 44),
+            class_name: le_u32(buf, 48),
+            max_subkey_name_len: le_u16(buf, 52),
+            max_class_name_len: le_u32(buf, 56),
+            max_value_name_len: le_u32(buf, 60),
+            max_value_data_len: le_u32(buf, 64),
+            class_name_len: le_u16(buf, 74),
+            name,
+        })
+    }
+
+    #[inline]
+    pub fn name(&self) -> Vec<u16> {
+        decode_name(self.name, self.flags & KEY_COMP_NAME != 0)
+    }
+
+    #[inline]
+    pub fn name_ref(&self) -> NameRef<'a> {
+        NameRef::new(self.name, self.flags & KEY_COMP_NAME != 0)
+    }
+}
+
+/// A value key (`vk`) record.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ValueKey<'a> {
+    pub data_size: u32,
+    pub data_offset: u32,
+    pub data_type: u32,
+    pub flags: u16,
+    /// The data offset field, which holds the data itself when it is small enough.
+    pub inline_data: &'a [u8],
+    pub name: &'a [u8],
+}
+
+impl<'a> ValueKey<'a> {
+    const HEADER_LEN: usize = 20;
+
+    pub fn parse(offset: u32, buf: &'a [u8]) -> Result<ValueKey<'a>, Error> {
+        if buf.len() < Self::HEADER_LEN || &buf[0..2] != b"vk" {
+            return Err(Error::InvalidCell(offset));
+        }
+
+        let name_len = le_u16(buf, 2) as usize;
+        let name = buf
+            .get(Self::HEADER_LEN..Self::HEADER_LEN + name_len)
+            .ok_or(Error::InvalidCell(offset))?;
+
+        Ok(ValueKey {
+            data_size: le_u32(buf, 4),
+            data_offset: le_u32(buf, 8),
+            data_type: le_u32(buf, 12),
+            flags: le_u16(buf, 16),
+            inline_data: &buf[8..12],
+            name,
+        })
+    }
+
+    #[inline]
+    pub fn name(&self) -> Vec<u16> {
+        decode_name(self.name, self.flags & VALUE_COMP_NAME != 0)
+    }
+
+    #[inline]
+    pub fn name_ref(&self) -> NameRef<'a> {
+        NameRef::new(self.name, self.flags & VALUE_COMP_NAME != 0)
+    }
+}
+
+/// A key security (`sk`) record, shared between all keys with the same security descriptor.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SecurityKey<'a> {
+    pub descriptor: &'a [u8],
+}
+
+impl<'a> SecurityKey<'a> {
+    const HEADER_LEN: usize = 20;
+
+    pub fn parse(offset: u32, buf: &'a [u8]) -> Result<SecurityKey<'a>, Error> {
+        if buf.len() < Self::HEADER_LEN || &buf[0..2] != b"sk" {
+            return Err(Error::InvalidCell(offset));
+        }
+
+        let len = le_u32(buf, 16) as usize;
+        let descriptor = buf
+            .get(Self::HEADER_LEN..Self::HEADER_LEN + len)
+            .ok_or(Error::InvalidCell(offset))?;
+
+        Ok(SecurityKey { descriptor })
+    }
+}
+
+/// A big data (`db`) record, pointing to the segments that a large value's data is split into.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BigData {
+    pub segment_count: u16,
+    pub segment_list: u32,
+}
+
+impl BigData {
+    const HEADER_LEN: usize = 8;
+
+    pub fn parse(offset: u32, buf: &[u8]) -> Result<BigData, Error> {
+        if buf.len() < Self::HEADER_LEN || &buf[0..2] != b"db" {
+            return Err(Error::InvalidCell(offset));
+        }
+
+        Ok(BigData {
+            segment_count: le_u16(buf, 2),
+            segment_list: le_u32(buf, 4),
+        })
+    }
+}
+
+/// A subkey list: an index leaf (`li`), fast leaf (`lf`), hash leaf (`lh`) or index root (`ri`).
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SubkeyList<'a> {
+    pub signature: [u8; 2],
+    /// Whether the entries point to further subkey lists rather than key nodes.
+    pub is_root: bool,
+    stride: usize,
+    entries: &'a [u8],
+}
+
+impl<'a> SubkeyList<'a> {
+    pub fn parse(offset: u32, buf: &'a [u8]) -> Result<SubkeyList<'a>, Error> {
+        if buf.len() < 4 {
+            return Err(Error::InvalidCell(offset));
+        }
+
+        let (is_root, stride) = match &buf[0..2] {
+            b"li" => (false, 4),
+            b"lf" | b"lh" => (false, 8),
+            b"ri" => (true, 4),
+            _ => return Err(Error::InvalidCell(offset)),
+        };
+
+        let count = le_u16(buf, 2) as usize;
+        let entries = buf
+            .get(4..4 + count * stride)
+            .ok_or(Error::InvalidCell(offset))?;
+
+        Ok(SubkeyList {
+            signature: [buf[0], buf[1]],
+            is_root,
+            stride,
+            entries,
+        })
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len() / self.stride
+    }
+
+    /// The offset of the cell referenced by the entry at `index`.
+    #[inline]
+    pub fn get(&self, index: usize) -> u32 {
+        le_u32(self.entries, index * self.stride)
+    }
+
+    /// The name hint or hash stored alongside the entry at `index`, in fast and hash leaves.
+    #[inline]
+    pub fn hash(&self, index: usize) -> Option<u32> {
+        match self.stride {
+            8 => Some(le_u32(self.entries, index * self.stride + 4)),
+            _ => None,
+        }
+    }
+}
+
+/// Decodes a key or value name, which is either Latin-1 or UTF-16LE.
+#[inline]
+pub(crate) fn decode_name(raw: &[u8], is_compressed: bool) -> Vec<u16> {
+    if is_compressed {
+        raw.iter().map(|x| u16::from(*x)).collect()
+    } else {
+        raw.chunks_exact(2)
+            .map(|x| u16::from_le_bytes([x[0], x[1]]))
+            .collect()
+    }
+}
+
+/// The hash stored in hash leaf (`lh`) entries, computed over the upper-cased name.
+pub(crate) fn name_hash(name: &[u16]) -> u32 {
+    name.iter().fold(0u32, |hash, c| {
+        hash.wrapping_mul(37).wrapping_add(upcase(*c))
+    })
+}
+
+/// The name hint stored in fast leaf (`lf`) entries: the first four characters of the name.
+pub(crate) fn name_hint(name: &[u16]) -> [u8; 4] {
+    let mut hint = [0u8; 4];
+    for (dst, c) in hint.iter_mut().zip(name) {
+        *dst = if *c < 0x100 { *c as u8 } else { 0 };
+    }
+    hint
+}
+
+#[inline]
+fn upcase(c: u16) -> u32 {
+    let mut upper = match std::char::from_u32(u32::from(c)) {
+        Some(c) => c.to_uppercase(),
+        None => return u32::from(c),
+    };
+
+    match (upper.next(), upper.next()) {
+        (Some(u), None) if (u as u32) <= 0xFFFF => u as u32,
+        _ => u32::from(c),
+    }
+}
+
+#[inline]
+pub(crate) fn le_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+#[inline]
+pub(crate) fn le_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+#[inline]
+pub(crate) fn le_u64(buf: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap())
+}
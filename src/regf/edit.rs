@@ -0,0 +1,907 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    convert::TryInto,
+    io::Write,
+    path::Path,
+    time::SystemTime,
+};
+
+use utfx::U16CString;
+
+use super::base::{checksum, BaseBlock, BASE_BLOCK_SIZE};
+use super::cell::{
+    le_u16, le_u32, BigData, KeyNode, SubkeyList, ValueKey, DATA_IN_OFFSET, MAX_DEPTH, NO_CELL,
+};
+use super::write::{
+    cell_size, key_node_record, put_u32, u32_bytes, value_key_record, write_subkey_list,
+    write_value, CellAlloc,
+};
+use super::{Error, OfflineHive, OfflineKey};
+use crate::util::{cmp_ignore_case, eq_ignore_case, system_time_to_filetime};
+use crate::value::Data;
+
+/// The size that hive bins are allocated in multiples of.
+const HBIN_ALIGN: usize = 4096;
+
+const HBIN_HEADER_LEN: usize = 32;
+
+/// A regf hive file opened for modification.
+///
+/// Changes are made to the hive bins in memory: cells that are no longer needed are freed and
+/// reused by later allocations, and new hive bins are appended once there is no free cell large
+/// enough. The sequence numbers and checksum in the base block are brought up to date when the
+/// hive is saved.
+///
+/// ```ignore
+/// let mut editor = HiveEditor::open("SOFTWARE")?;
+/// editor.create_key(r"Vendor\App")?;
+/// editor.set_value(r"Vendor\App", "Version", &Data::U32(2))?;
+/// editor.save("SOFTWARE")?;
+/// ```
+#[derive(Debug)]
+pub struct HiveEditor {
+    hive: OfflineHive,
+    /// The offset and size of every free cell.
+    free: BTreeMap<u32, u32>,
+}
+
+impl HiveEditor {
+    #[inline]
+    pub fn open<P: AsRef<Path>>(file_path: P) -> Result<HiveEditor, Error> {
+        HiveEditor::from_hive(OfflineHive::open(file_path)?)
+    }
+
+    #[inline]
+    pub fn from_bytes(data: Vec<u8>) -> Result<HiveEditor, Error> {
+        HiveEditor::from_hive(OfflineHive::from_bytes(data)?)
+    }
+
+    /// Starts editing a hive. Dirty hives are rejected, since their hive bins may not be
+    /// consistent.
+    pub fn from_hive(mut hive: OfflineHive) -> Result<HiveEditor, Error> {
+        if hive.base.is_dirty() {
+            return Err(Error::Dirty);
+        }
+
+        let end = BASE_BLOCK_SIZE + hive.base.hive_bins_size as usize;
+        if hive.data.len() < end {
+            return Err(Error::Truncated(hive.data.len()));
+        }
+        hive.data.to_mut().truncate(end);
+
+        let mut free = BTreeMap::new();
+        let bins = hive.bins();
+        let mut bin = 0;
+        while bin < bins.len() {
+            if bins.len() < bin + HBIN_HEADER_LEN || &bins[bin..bin + 4] != b"hbin" {
+                return Err(Error::InvalidCell(bin as u32));
+            }
+
+            let bin_end = bin + le_u32(bins, bin + 8) as usize;
+            if bin_end <= bin || bin_end > bins.len() {
+                return Err(Error::InvalidCell(bin as u32));
+            }
+
+            let mut offset = bin + HBIN_HEADER_LEN;
+            while offset + 4 <= bin_end {
+                let size = le_u32(bins, offset) as i32;
+                let len = size.unsigned_abs() as usize;
+                if len < 8 || offset + len > bin_end {
+                    return Err(Error::InvalidCell(offset as u32));
+                }
+                if size > 0 {
+                    free.insert(offset as u32, len as u32);
+                }
+                offset += len;
+            }
+
+            bin = bin_end;
+        }
+
+        Ok(HiveEditor { hive, free })
+    }
+
+    /// The hive as it currently stands, for reading.
+    #[inline]
+    pub fn hive(&self) -> &OfflineHive {
+        &self.hive
+    }
+
+    /// Creates a key by path, along with any missing keys above it. Existing keys are left as
+    /// they are. New keys share the security descriptor of their parent.
+    pub fn create_key<P>(&mut self, path: P) -> Result<(), Error>
+    where
+        P: TryInto<U16CString>,
+        P::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        let mut offset = self.hive.base.root_cell;
+
+        for segment in segments(path.as_slice()) {
+            let mut subkeys = self.subkeys(offset)?;
+            offset = match subkeys
+                .iter()
+                .find(|(name, _)| eq_ignore_case(name, segment))
+            {
+                Some((_, child)) => *child,
+                None => {
+                    let record = key_node_record(segment, 0, now(), offset)?;
+                    let child = self.alloc(&record);
+
+                    let security = KeyNode::parse(offset, self.hive.cell(offset)?)?.security;
+                    if security != NO_CELL {
+                        let references = le_u32(self.hive.cell(security)?, 12);
+                        put_u32(self.cell_mut(security), 12, references + 1);
+                        put_u32(self.cell_mut(child), 44, security);
+                    }
+
+                    subkeys.push((segment.to_vec(), child));
+                    self.set_subkeys(offset, subkeys)?;
+                    child
+                }
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Deletes a key by path. Keys with subkeys are only deleted when `is_recursive` is set.
+    pub fn delete_key<P>(&mut self, path: P, is_recursive: bool) -> Result<(), Error>
+    where
+        P: TryInto<U16CString>,
+        P::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        let offset = self.hive.open_key(path.as_ucstr())?.offset;
+        if offset == self.hive.base.root_cell {
+            return Err(Error::RootKey);
+        }
+
+        let node = KeyNode::parse(offset, self.hive.cell(offset)?)?;
+        if node.subkey_count > 0 && !is_recursive {
+            return Err(Error::HasSubkeys(path.to_string_lossy()));
+        }
+
+        // The whole subtree is checked before anything is changed.
+        let mut keys = vec![];
+        self.collect_subtree(offset, 0, &mut HashSet::new(), &mut keys)?;
+
+        let parent = node.parent;
+        let subkeys = self
+            .subkeys(parent)?
+            .into_iter()
+            .filter(|(_, x)| *x != offset)
+            .collect();
+        self.set_subkeys(parent, subkeys)?;
+        for key in keys {
+            self.free_key(key)?;
+        }
+        Ok(())
+    }
+
+    /// Renames the key at `path`, keeping its values and subkeys.
+    pub fn rename_key<P, N>(&mut self, path: P, new_name: N) -> Result<(), Error>
+    where
+        P: TryInto<U16CString>,
+        P::Error: Into<Error>,
+        N: TryInto<U16CString>,
+        N::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        let new_name = new_name.try_into().map_err(Into::into)?;
+        if new_name.is_empty() || new_name.as_slice().contains(&u16::from(b'\\')) {
+            return Err(Error::InvalidName(new_name.to_string_lossy()));
+        }
+        let offset = self.hive.open_key(path.as_ucstr())?.offset;
+        let old = self.hive.cell(offset)?;
+        let node = KeyNode::parse(offset, old)?;
+        let parent = node.parent;
+
+        let mut record = key_node_record(new_name.as_slice(), node.flags, now(), parent)?;
+        record[12..16].copy_from_slice(&old[12..16]);
+        record[20..72].copy_from_slice(&old[20..72]);
+        record[74..76].copy_from_slice(&old[74..76]);
+
+        if offset == self.hive.base.root_cell {
+            let renamed = self.replace_cell(offset, &record);
+            if renamed != offset {
+                self.hive.base.root_cell = renamed;
+                put_u32(&mut self.hive.data, 36, renamed);
+                self.reparent_subkeys(renamed)?;
+            }
+            return Ok(());
+        }
+
+        let mut subkeys = self.subkeys(parent)?;
+        if subkeys
+            .iter()
+            .any(|(name, x)| *x != offset && eq_ignore_case(name, new_name.as_slice()))
+        {
+            return Err(Error::AlreadyExists(new_name.to_string_lossy()));
+        }
+
+        let renamed = self.replace_cell(offset, &record);
+        if renamed != offset {
+            self.reparent_subkeys(renamed)?;
+        }
+
+        for entry in subkeys.iter_mut().filter(|(_, x)| *x == offset) {
+            *entry = (new_name.as_slice().to_vec(), renamed);
+        }
+        self.set_subkeys(parent, subkeys)
+    }
+
+    /// Sets a value of the key at `path`, replacing any existing value with the same name.
+    pub fn set_value<P, N>(&mut self, path: P, name: N, data: &Data) -> Result<(), Error>
+    where
+        P: TryInto<U16CString>,
+        P::Error: Into<Error>,
+        N: TryInto<U16CString>,
+        N::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        let name = name.try_into().map_err(Into::into)?;
+        let offset = self.hive.open_key(path.as_ucstr())?.offset;
+
+        let mut values = self.values(offset)?;
+        let value = write_value(self, name.as_slice(), data)?;
+        match self.find_value(&values, name.as_slice())? {
+            Some(index) => {
+                self.free_value(values[index])?;
+                values[index] = value;
+            }
+            None => values.push(value),
+        }
+        self.set_values(offset, &values)
+    }
+
+    /// Deletes a value of the key at `path`.
+    pub fn delete_value<P, N>(&mut self, path: P, name: N) -> Result<(), Error>
+    where
+        P: TryInto<U16CString>,
+        P::Error: Into<Error>,
+        N: TryInto<U16CString>,
+        N::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        let name = name.try_into().map_err(Into::into)?;
+        let offset = self.hive.open_key(path.as_ucstr())?.offset;
+
+        let mut values = self.values(offset)?;
+        let index = self
+            .find_value(&values, name.as_slice())?
+            .ok_or_else(|| Error::NotFound(name.to_string_lossy()))?;
+        self.free_value(values.remove(index))?;
+        self.set_values(offset, &values)
+    }
+
+    /// Renames a value of the key at `path`, keeping its data.
+    pub fn rename_value<P, N, M>(&mut self, path: P, name: N, new_name: M) -> Result<(), Error>
+    where
+        P: TryInto<U16CString>,
+        P::Error: Into<Error>,
+        N: TryInto<U16CString>,
+        N::Error: Into<Error>,
+        M: TryInto<U16CString>,
+        M::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        let name = name.try_into().map_err(Into::into)?;
+        let new_name = new_name.try_into().map_err(Into::into)?;
+        if new_name.len() > u16::MAX as usize / 2 {
+            return Err(Error::NameTooLong(new_name.to_string_lossy()));
+        }
+
+        let offset = self.hive.open_key(path.as_ucstr())?.offset;
+        let mut values = self.values(offset)?;
+        let index = self
+            .find_value(&values, name.as_slice())?
+            .ok_or_else(|| Error::NotFound(name.to_string_lossy()))?;
+        if let Some(existing) = self.find_value(&values, new_name.as_slice())? {
+            if existing != index {
+                return Err(Error::AlreadyExists(new_name.to_string_lossy()));
+            }
+        }
+
+        let vk = ValueKey::parse(values[index], self.hive.cell(values[index])?)?;
+        let record = value_key_record(
+            new_name.as_slice(),
+            vk.data_size,
+            vk.data_offset,
+            vk.data_type,
+        );
+        values[index] = self.replace_cell(values[index], &record);
+        self.set_values(offset, &values)
+    }
+
+    /// Writes the modified hive to the file at `file_path`.
+    pub fn save<P: AsRef<Path>>(&mut self, file_path: P) -> Result<(), Error> {
+        self.flush()?;
+        std::fs::File::create(file_path)?.write_all(&self.hive.data)?;
+        Ok(())
+    }
+
+    /// Finishes editing, returning the bytes of the modified hive.
+    pub fn to_bytes(mut self) -> Result<Vec<u8>, Error> {
+        self.flush()?;
+        Ok(self.hive.data.into_vec())
+    }
+
+    /// Finishes editing, returning the modified hive for reading.
+    pub fn into_hive(mut self) -> Result<OfflineHive, Error> {
+        self.flush()?;
+        Ok(self.hive)
+    }
+
+    /// Brings the base block up to date: both sequence numbers are advanced together, since the
+    /// hive is always written out as a whole.
+    fn flush(&mut self) -> Result<(), Error> {
+        let data = &mut self.hive.data;
+        let sequence = le_u32(data, 4).wrapping_add(1);
+        put_u32(data, 4, sequence);
+        put_u32(data, 8, sequence);
+        data[12..20].copy_from_slice(&now().to_le_bytes());
+        let checksum = checksum(data);
+        put_u32(data, 508, checksum);
+
+        self.hive.base = BaseBlock::parse(data)?;
+        Ok(())
+    }
+
+    /// The names and offsets of the subkeys of the key at `offset`.
+    fn subkeys(&self, offset: u32) -> Result<Vec<(Vec<u16>, u32)>, Error> {
+        OfflineKey::new(&self.hive, offset)?
+            .keys()
+            .map(|x| x.map(|x| (x.name().to_vec(), x.offset)))
+            .collect()
+    }
+
+    /// Replaces the subkey list of the key at `offset`.
+    fn set_subkeys(&mut self, offset: u32, mut subkeys: Vec<(Vec<u16>, u32)>) -> Result<(), Error> {
+        let node = KeyNode::parse(offset, self.hive.cell(offset)?)?;
+        if node.subkey_list != NO_CELL {
+            self.free_subkey_list(node.subkey_list)?;
+        }
+
+        subkeys.sort_by(|a, b| cmp_ignore_case(&a.0, &b.0));
+        let mut max_class_name = 0;
+        for (_, subkey) in &subkeys {
+            max_class_name = max_class_name.max(le_u16(self.hive.cell(*subkey)?, 74));
+        }
+        let entries = subkeys
+            .iter()
+            .map(|(name, x)| (name.as_slice(), *x))
+            .collect::<Vec<_>>();
+        let list = write_subkey_list(self, &entries);
+
+        let max_name = subkeys.iter().map(|(x, _)| x.len() * 2).max().unwrap_or(0);
+        let record = self.cell_mut(offset);
+        record[4..12].copy_from_slice(&now().to_le_bytes());
+        put_u32(record, 20, subkeys.len() as u32);
+        put_u32(record, 28, list);
+        put_u32(record, 52, max_name as u32);
+        put_u32(record, 56, u32::from(max_class_name));
+        Ok(())
+    }
+
+    /// Points the parent field of each subkey of the key at `offset` back at it, after the key
+    /// has moved.
+    fn reparent_subkeys(&mut self, offset: u32) -> Result<(), Error> {
+        for (_, subkey) in self.subkeys(offset)? {
+            put_u32(self.cell_mut(subkey), 16, offset);
+        }
+        Ok(())
+    }
+
+    /// The offsets of the value keys of the key at `offset`.
+    fn values(&self, offset: u32) -> Result<Vec<u32>, Error> {
+        let node = KeyNode::parse(offset, self.hive.cell(offset)?)?;
+        if node.value_count == 0 || node.value_list == NO_CELL {
+            return Ok(vec![]);
+        }
+
+        let list = self
+            .hive
+            .cell(node.value_list)?
+            .get(..node.value_count as usize * 4)
+            .ok_or(Error::InvalidCell(node.value_list))?;
+        Ok(list.chunks_exact(4).map(|x| le_u32(x, 0)).collect())
+    }
+
+    /// Replaces the value list of the key at `offset`.
+    fn set_values(&mut self, offset: u32, values: &[u32]) -> Result<(), Error> {
+        let node = KeyNode::parse(offset, self.hive.cell(offset)?)?;
+        if node.value_list != NO_CELL {
+            self.free(node.value_list);
+        }
+
+        let (mut max_name, mut max_data) = (0, 0);
+        for value in values {
+            let vk = ValueKey::parse(*value, self.hive.cell(*value)?)?;
+            max_name = max_name.max(vk.name().len() as u32 * 2);
+            max_data = max_data.max(vk.data_size & !DATA_IN_OFFSET);
+        }
+
+        let list = match values.is_empty() {
+            true => NO_CELL,
+            false => self.alloc(&u32_bytes(values)),
+        };
+
+        let record = self.cell_mut(offset);
+        record[4..12].copy_from_slice(&now().to_le_bytes());
+        put_u32(record, 36, values.len() as u32);
+        put_u32(record, 40, list);
+        put_u32(record, 60, max_name);
+        put_u32(record, 64, max_data);
+        Ok(())
+    }
+
+    /// The index of the value named `name` in `values`.
+    fn find_value(&self, values: &[u32], name: &[u16]) -> Result<Option<usize>, Error> {
+        for (i, value) in values.iter().enumerate() {
+            let vk = ValueKey::parse(*value, self.hive.cell(*value)?)?;
+            if eq_ignore_case(&vk.name(), name) {
+                return Ok(Some(i));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Frees a value key along with its data.
+    fn free_value(&mut self, offset: u32) -> Result<(), Error> {
+        let vk = ValueKey::parse(offset, self.hive.cell(offset)?)?;
+        let data_offset = vk.data_offset;
+        if self.hive.is_big_data(&vk) {
+            let list = BigData::parse(data_offset, self.hive.cell(data_offset)?)?.segment_list;
+            for segment in self.hive.data_segments(data_offset)? {
+                self.free(segment);
+            }
+            self.free(list);
+            self.free(data_offset);
+        } else if vk.data_size & DATA_IN_OFFSET == 0 && vk.data_size > 0 {
+            self.free(data_offset);
+        }
+        self.free(offset);
+        Ok(())
+    }
+
+    /// Frees a subkey list, along with the leaves of an index root.
+    fn free_subkey_list(&mut self, offset: u32) -> Result<(), Error> {
+        let list = SubkeyList::parse(offset, self.hive.cell(offset)?)?;
+        if list.is_root {
+            let leaves = (0..list.len()).map(|i| list.get(i)).collect::<Vec<_>>();
+            for leaf in leaves {
+                self.free(leaf);
+            }
+        }
+        self.free(offset);
+        Ok(())
+    }
+
+    /// Lists the key at `offset` and everything below it, children before their parents. A key
+    /// that is reached twice, or lies too deep, means the hive is corrupt.
+    fn collect_subtree(
+        &self,
+        offset: u32,
+        depth: usize,
+        visited: &mut HashSet<u32>,
+        keys: &mut Vec<u32>,
+    ) -> Result<(), Error> {
+        if depth >= MAX_DEPTH || !visited.insert(offset) {
+            return Err(Error::InvalidCell(offset));
+        }
+        for (_, subkey) in self.subkeys(offset)? {
+            self.collect_subtree(subkey, depth + 1, visited, keys)?;
+        }
+        keys.push(offset);
+        Ok(())
+    }
+
+    /// Frees a key node along with its values and lists, but not its subkeys or its entry in its
+    /// parent.
+    fn free_key(&mut self, offset: u32) -> Result<(), Error> {
+        for value in self.values(offset)? {
+            self.free_value(value)?;
+        }
+
+        let node = KeyNode::parse(offset, self.hive.cell(offset)?)?;
+        let (subkey_list, value_list, class_name, security) = (
+            node.subkey_list,
+            node.value_list,
+            node.class_name,
+            node.security,
+        );
+        if subkey_list != NO_CELL {
+            self.free_subkey_list(subkey_list)?;
+        }
+        if value_list != NO_CELL {
+            self.free(value_list);
+        }
+        if class_name != NO_CELL {
+            self.free(class_name);
+        }
+        if security != NO_CELL {
+            self.release_security(security)?;
+        }
+
+        self.free(offset);
+        Ok(())
+    }
+
+    /// Drops a reference to a security cell, unlinking and freeing it once it is unused.
+    fn release_security(&mut self, offset: u32) -> Result<(), Error> {
+        let sk = self.hive.cell(offset)?;
+        let (flink, blink, references) = (le_u32(sk, 4), le_u32(sk, 8), le_u32(sk, 12));
+        if references > 1 {
+            put_u32(self.cell_mut(offset), 12, references - 1);
+            return Ok(());
+        }
+
+        put_u32(self.cell_mut(blink), 4, flink);
+        put_u32(self.cell_mut(flink), 8, blink);
+        self.free(offset);
+        Ok(())
+    }
+
+    /// Writes `record` over the cell at `offset` if it fits, or moves it to a new cell otherwise.
+    /// Returns the offset the record ended up at.
+    fn replace_cell(&mut self, offset: u32, record: &[u8]) -> u32 {
+        let cell = self.cell_mut(offset);
+        if record.len() <= cell.len() {
+            cell[..record.len()].copy_from_slice(record);
+            cell[record.len()..].iter_mut().for_each(|x| *x = 0);
+            return offset;
+        }
+
+        let moved = self.alloc(record);
+        self.free(offset);
+        moved
+    }
+
+    /// Marks the cell at `offset` as free, merging it with any free cells next to it. The
+    /// contents are left in place.
+    fn free(&mut self, offset: u32) {
+        let start = BASE_BLOCK_SIZE + offset as usize;
+        let mut size = le_u32(&self.hive.data, start) as i32;
+        if size > 0 {
+            return;
+        }
+        size = -size;
+
+        let (mut offset, mut size) = (offset, size as u32);
+        if let Some(next) = self.free.remove(&(offset + size)) {
+            size += next;
+        }
+        if let Some((&prev, &prev_size)) = self.free.range(..offset).next_back() {
+            if prev + prev_size == offset {
+                offset = prev;
+                size += prev_size;
+            }
+        }
+
+        put_u32(&mut self.hive.data, BASE_BLOCK_SIZE + offset as usize, size);
+        self.free.insert(offset, size);
+    }
+
+    /// Appends a hive bin with room for a cell of `size` bytes, returning its free cell.
+    fn grow(&mut self, size: u32) -> (u32, u32) {
+        let start = self.hive.base.hive_bins_size;
+        let bin_size = (size as usize + HBIN_HEADER_LEN).div_ceil(HBIN_ALIGN) * HBIN_ALIGN;
+        let data = &mut self.hive.data;
+        data.to_mut()
+            .resize(BASE_BLOCK_SIZE + start as usize + bin_size, 0);
+
+        let header = &mut data[BASE_BLOCK_SIZE + start as usize..];
+        header[0..4].copy_from_slice(b"hbin");
+        put_u32(header, 4, start);
+        put_u32(header, 8, bin_size as u32);
+
+        let cell = (
+            start + HBIN_HEADER_LEN as u32,
+            (bin_size - HBIN_HEADER_LEN) as u32,
+        );
+        put_u32(data, BASE_BLOCK_SIZE + cell.0 as usize, cell.1);
+        self.hive.base.hive_bins_size = start + bin_size as u32;
+        put_u32(data, 40, self.hive.base.hive_bins_size);
+        cell
+    }
+}
+
+impl CellAlloc for HiveEditor {
+    #[inline]
+    fn minor_version(&self) -> u32 {
+        self.hive.base.minor_version
+    }
+
+    fn alloc(&mut self, payload: &[u8]) -> u32 {
+        let size = cell_size(payload.len()) as u32;
+        let (offset, free_size) = match self.free.iter().find(|(_, x)| **x >= size) {
+            Some((offset, free_size)) => (*offset, *free_size),
+            None => self.grow(size),
+        };
+        self.free.remove(&offset);
+
+        // Cell sizes are multiples of 8, so any remainder is large enough to be a cell itself.
+        if free_size > size {
+            let rest = offset + size;
+            put_u32(
+                &mut self.hive.data,
+                BASE_BLOCK_SIZE + rest as usize,
+                free_size - size,
+            );
+            self.free.insert(rest, free_size - size);
+        }
+
+        let start = BASE_BLOCK_SIZE + offset as usize;
+        let cell = &mut self.hive.data[start..start + size as usize];
+        cell[0..4].copy_from_slice(&(-(size as i32)).to_le_bytes());
+        cell[4..4 + payload.len()].copy_from_slice(payload);
+        cell[4 + payload.len()..].iter_mut().for_each(|x| *x = 0);
+        offset
+    }
+
+    fn cell_mut(&mut self, offset: u32) -> &mut [u8] {
+        let start = BASE_BLOCK_SIZE + offset as usize;
+        let size = (le_u32(&self.hive.data, start) as i32).unsigned_abs() as usize;
+        &mut self.hive.data[start + 4..start + size]
+    }
+}
+
+#[inline]
+fn segments(path: &[u16]) -> impl Iterator<Item = &[u16]> {
+    path.split(|x| *x == u16::from(b'\\'))
+        .filter(|x| !x.is_empty())
+}
+
+#[inline]
+fn now() -> u64 {
+    system_time_to_filetime(SystemTime::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::regf::HiveWriter;
+    use crate::tree::KeyTree;
+
+    fn s(x: &str) -> U16CString {
+        x.try_into().unwrap()
+    }
+
+    fn sample_editor() -> HiveEditor {
+        let mut root = KeyTree::new(s("ROOT"));
+        let vendor = root.create(&s(r"Software\Vendor"));
+        vendor.set_value(s("Version"), Data::U32(1));
+        vendor.set_value(s("Name"), Data::String(s("Vendor")));
+        root.create(&s(r"Software\Vendor\App\Settings"));
+        root.create(&s("System"));
+        HiveEditor::from_bytes(HiveWriter::new().to_bytes(&root).unwrap()).unwrap()
+    }
+
+    fn names(hive: &OfflineHive, path: &str) -> Vec<String> {
+        hive.open_key(path)
+            .unwrap()
+            .keys()
+            .map(|x| x.unwrap().name().to_string_lossy())
+            .collect()
+    }
+
+    #[test]
+    fn create_and_set_values() {
+        let mut editor = sample_editor();
+        editor.create_key(r"Software\Another\Nested").unwrap();
+        editor.create_key(r"software\vendor").unwrap();
+        editor
+            .set_value(r"Software\Another", "Path", &Data::String(s(r"C:\Temp")))
+            .unwrap();
+        editor
+            .set_value(r"Software\Vendor", "version", &Data::U32(2))
+            .unwrap();
+
+        let hive = editor.into_hive().unwrap();
+        assert_eq!(names(&hive, "Software"), vec!["Another", "Vendor"]);
+        assert_eq!(names(&hive, r"Software\Another"), vec!["Nested"]);
+
+        let another = hive.open_key(r"Software\Another").unwrap();
+        assert_eq!(another.value("path").unwrap(), Data::String(s(r"C:\Temp")));
+        let vendor = hive.open_key(r"Software\Vendor").unwrap();
+        assert_eq!(vendor.value("Version").unwrap(), Data::U32(2));
+        assert_eq!(vendor.values().count(), 2);
+
+        // New keys share their parent's security cell.
+        let software = hive.open_key("Software").unwrap();
+        assert_eq!(
+            another.node().unwrap().security,
+            software.node().unwrap().security
+        );
+    }
+
+    #[test]
+    fn rename_and_delete() {
+        let mut editor = sample_editor();
+        editor
+            .rename_value(r"Software\Vendor", "Name", "Title")
+            .unwrap();
+        editor.delete_value(r"Software\Vendor", "version").unwrap();
+        editor
+            .rename_key(
+                r"Software\Vendor",
+                "A vendor with a much longer name than before",
+            )
+            .unwrap();
+        editor.delete_key("System", false).unwrap();
+
+        let hive = editor.into_hive().unwrap();
+        assert_eq!(names(&hive, ""), vec!["Software"]);
+        let vendor = hive
+            .open_key(r"Software\A vendor with a much longer name than before")
+            .unwrap();
+        let values = vendor
+            .values()
+            .map(|x| x.unwrap().into_inner())
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![(s("Title"), Data::String(s("Vendor")))]);
+
+        // The key moved, so its subkeys must point at its new cell.
+        let app = vendor.open("App").unwrap();
+        assert_eq!(app.node().unwrap().parent, vendor.offset);
+        assert_eq!(
+            names(
+                &hive,
+                r"Software\A vendor with a much longer name than before\App"
+            ),
+            vec!["Settings"]
+        );
+    }
+
+    #[test]
+    fn rename_root() {
+        let mut editor = sample_editor();
+        editor
+            .rename_key("", "A root key with a much longer name than before")
+            .unwrap();
+        let hive = editor.into_hive().unwrap();
+        let root = hive.root().unwrap();
+        assert_eq!(
+            root.name().to_string_lossy(),
+            "A root key with a much longer name than before"
+        );
+        assert_eq!(
+            hive.open_key("System").unwrap().node().unwrap().parent,
+            root.offset
+        );
+    }
+
+    #[test]
+    fn invalid_edits() {
+        let mut editor = sample_editor();
+        assert!(matches!(editor.delete_key("", true), Err(Error::RootKey)));
+        assert!(matches!(
+            editor.delete_key("Software", false),
+            Err(Error::HasSubkeys(_))
+        ));
+        assert!(matches!(
+            editor.rename_key("Software", "system"),
+            Err(Error::AlreadyExists(_))
+        ));
+        for name in &["", r"Soft\ware", "\\"] {
+            assert!(matches!(
+                editor.rename_key("Software", *name),
+                Err(Error::InvalidName(_))
+            ));
+        }
+        assert!(matches!(
+            editor.rename_key("", ""),
+            Err(Error::InvalidName(_))
+        ));
+        assert!(matches!(
+            editor.rename_value(r"Software\Vendor", "Name", "VERSION"),
+            Err(Error::AlreadyExists(_))
+        ));
+        assert!(matches!(
+            editor.delete_value(r"Software\Vendor", "Missing"),
+            Err(Error::NotFound(_))
+        ));
+
+        let mut dirty = editor.to_bytes().unwrap();
+        dirty[4] ^= 1;
+        assert!(matches!(HiveEditor::from_bytes(dirty), Err(Error::Dirty)));
+    }
+
+    #[test]
+    fn delete_recursively_frees_cells() {
+        let mut editor = sample_editor();
+        editor.delete_key("Software", true).unwrap();
+
+        // Everything the subtree used can be allocated again without growing the hive.
+        let size = editor.hive.base.hive_bins_size;
+        editor.create_key(r"Software\Vendor\App\Settings").unwrap();
+        editor
+            .set_value(r"Software\Vendor", "Name", &Data::String(s("Vendor")))
+            .unwrap();
+        assert_eq!(editor.hive.base.hive_bins_size, size);
+        assert_eq!(names(editor.hive(), ""), vec!["Software", "System"]);
+    }
+
+    #[test]
+    fn delete_cyclic_subtree() {
+        let mut editor = sample_editor();
+        let vendor = editor.hive.open_key(r"Software\Vendor").unwrap().offset;
+        let settings = editor
+            .hive
+            .open_key(r"Software\Vendor\App\Settings")
+            .unwrap()
+            .offset;
+        editor
+            .set_subkeys(settings, vec![(s("Loop").as_slice().to_vec(), vendor)])
+            .unwrap();
+
+        // Nothing is freed, or unlinked from its parent, when the subtree loops.
+        let data = editor.hive.data.to_vec();
+        assert!(matches!(
+            editor.delete_key("Software", true),
+            Err(Error::InvalidCell(x)) if x == vendor
+        ));
+        assert!(editor.hive.data[..] == data[..]);
+        assert_eq!(names(editor.hive(), ""), vec!["Software", "System"]);
+    }
+
+    #[test]
+    fn freed_cells_are_reused() {
+        let mut editor = sample_editor();
+        let data = Data::Binary(vec![1; 1000]);
+        editor.set_value("System", "Blob", &data).unwrap();
+        let size = editor.hive.base.hive_bins_size;
+
+        for _ in 0..10 {
+            editor.set_value("System", "Blob", &data).unwrap();
+        }
+        assert_eq!(editor.hive.base.hive_bins_size, size);
+    }
+
+    #[test]
+    fn grow_hive_bins() {
+        let mut editor = sample_editor();
+        let size = editor.hive.base.hive_bins_size;
+        let primary_sequence = editor.hive.base.primary_sequence;
+
+        let data = Data::Binary((0..10_000).map(|x| x as u8).collect());
+        editor.set_value("System", "Large", &data).unwrap();
+        assert!(editor.hive.base.hive_bins_size > size);
+
+        let bytes = editor.to_bytes().unwrap();
+        assert_eq!(bytes.len() - BASE_BLOCK_SIZE, le_u32(&bytes, 40) as usize);
+
+        // The result can be picked up again for further edits, which walks every hive bin.
+        let editor = HiveEditor::from_bytes(bytes).unwrap();
+        let hive = editor.hive();
+        assert!(hive.is_checksum_valid());
+        assert_eq!(hive.base_block().primary_sequence, primary_sequence + 1);
+        assert_eq!(hive.base_block().secondary_sequence, primary_sequence + 1);
+        assert_eq!(
+            hive.open_key("System").unwrap().value("Large").unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn replace_big_data() {
+        let mut editor = sample_editor();
+        let large = Data::Binary(vec![3; 100_000]);
+        editor.set_value("System", "Large", &large).unwrap();
+        assert_eq!(
+            editor
+                .hive()
+                .open_key("System")
+                .unwrap()
+                .value("Large")
+                .unwrap(),
+            large
+        );
+
+        // Every segment is freed along with the value.
+        editor.set_value("System", "Large", &Data::U32(1)).unwrap();
+        assert_eq!(editor.into_hive().unwrap().verify(), vec![]);
+    }
+}
@@ -0,0 +1,281 @@
+use std::{
+    convert::TryInto,
+    fmt::{Debug, Display},
+};
+
+use utfx::U16CString;
+
+use super::cell::{decode_name, KeyNode, SecurityKey, SubkeyList, MAX_DEPTH, NO_CELL};
+use super::{Error, NameRef, OfflineHive, OfflineValue, RawValues, Values};
+use crate::descriptor::SecurityDescriptor;
+use crate::key::KeyInfo;
+use crate::tree::KeyTree;
+use crate::util::filetime_to_system_time;
+use crate::value::{Data, ParseMode};
+
+/// A key in an [`OfflineHive`](struct.OfflineHive.html).
+#[derive(Clone)]
+pub struct OfflineKey<'a> {
+    pub(crate) hive: &'a OfflineHive,
+    pub(crate) offset: u32,
+    name: NameRef<'a>,
+}
+
+impl Display for OfflineKey<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.name, f)
+    }
+}
+
+impl Debug for OfflineKey<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("OfflineKey").field(&self.name).finish()
+    }
+}
+
+impl<'a> OfflineKey<'a> {
+    pub(crate) fn new(hive: &'a OfflineHive, offset: u32) -> Result<OfflineKey<'a>, Error> {
+        let node = KeyNode::parse(offset, hive.cell(offset)?)?;
+        Ok(OfflineKey {
+            hive,
+            offset,
+            name: node.name_ref(),
+        })
+    }
+
+    #[inline]
+    pub(crate) fn node(&self) -> Result<KeyNode<'a>, Error> {
+        KeyNode::parse(self.offset, self.hive.cell(self.offset)?)
+    }
+
+    /// The name of the key, borrowed from the hive.
+    #[inline]
+    pub fn name(&self) -> NameRef<'a> {
+        self.name
+    }
+
+    /// Opens a subkey by path, relative to this key.
+    pub fn open<P>(&self, path: P) -> Result<OfflineKey<'a>, Error>
+    where
+        P: TryInto<U16CString>,
+        P::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        let mut key = self.clone();
+
+        for segment in path
+            .as_slice()
+            .split(|x| *x == u16::from(b'\\'))
+            .filter(|x| !x.is_empty())
+        {
+            key = key
+                .keys()
+                .find(|x| match x {
+                    Ok(x) => x.name.eq_ignore_case(segment),
+                    Err(_) => true,
+                })
+                .unwrap_or_else(|| Err(Error::NotFound(path.to_string_lossy())))?;
+        }
+
+        Ok(key)
+    }
+
+    /// Queries the data of a value of this key by name. Data that cannot be decoded exactly is kept
+    /// as [`Data::Other`](../value/enum.Data.html#variant.Other).
+    pub fn value<S>(&self, value_name: S) -> Result<Data, Error>
+    where
+        S: TryInto<U16CString>,
+        S::Error: Into<Error>,
+    {
+        let value_name = value_name.try_into().map_err(Into::into)?;
+        self.raw_values()
+            .find(|x| match x {
+                Ok(x) => x.name().eq_ignore_case(value_name.as_slice()),
+                Err(_) => true,
+            })
+            .unwrap_or_else(|| Err(Error::NotFound(value_name.to_string_lossy())))?
+            .to_data_with(ParseMode::PreserveRaw)
+    }
+
+    /// Reads this key and everything below it into a [`KeyTree`](../tree/struct.KeyTree.html).
+    #[inline]
+    pub fn to_tree(&self) -> Result<KeyTree, Error> {
+        self.to_tree_below(&mut vec![])
+    }
+
+    /// Reads a subtree, failing if this key is one of its own `ancestors` or lies too deep.
+    fn to_tree_below(&self, ancestors: &mut Vec<u32>) -> Result<KeyTree, Error> {
+        if ancestors.len() >= MAX_DEPTH || ancestors.contains(&self.offset) {
+            return Err(Error::InvalidCell(self.offset));
+        }
+        let node = self.node()?;
+
+        let class_name = self.class_name(&node)?;
+
+        let security = match node.security {
+            NO_CELL => None,
+            offset => Some(
+                SecurityKey::parse(offset, self.hive.cell(offset)?)?
+                    .descriptor
+                    .to_vec(),
+            ),
+        };
+
+        ancestors.push(self.offset);
+        let subkeys = self
+            .keys()
+            .map(|x| x.and_then(|x| x.to_tree_below(ancestors)))
+            .collect::<Result<_, _>>();
+        ancestors.pop();
+
+        Ok(KeyTree {
+            name: self.name.to_ucstring()?,
+            class_name,
+            last_written: filetime_to_system_time(node.last_written),
+            security,
+            values: self
+                .values()
+                .map(|x| x.map(OfflineValue::into_inner))
+                .collect::<Result<_, _>>()?,
+            subkeys: subkeys?,
+        })
+    }
+
+    /// The class name, last write time and content sizes recorded in this key's node.
+    pub fn info(&self) -> Result<KeyInfo, Error> {
+        let node = self.node()?;
+        Ok(KeyInfo {
+            class_name: self.class_name(&node)?,
+            last_written: filetime_to_system_time(node.last_written),
+            subkey_count: node.subkey_count,
+            max_subkey_name_len: u32::from(node.max_subkey_name_len) / 2,
+            max_class_name_len: node.max_class_name_len / 2,
+            value_count: node.value_count,
+            max_value_name_len: node.max_value_name_len / 2,
+            max_value_data_len: node.max_value_data_len,
+        })
+    }
+
+    /// The security descriptor of this key, or `None` if it has no security record.
+    pub fn security_descriptor(&self) -> Result<Option<SecurityDescriptor>, Error> {
+        match self.node()?.security {
+            NO_CELL => Ok(None),
+            offset => {
+                let sk = SecurityKey::parse(offset, self.hive.cell(offset)?)?;
+                Ok(Some(SecurityDescriptor::parse(sk.descriptor)?))
+            }
+        }
+    }
+
+    fn class_name(&self, node: &KeyNode<'a>) -> Result<Option<U16CString>, Error> {
+        let offset = match node.class_name {
+            NO_CELL => return Ok(None),
+            v => v,
+        };
+
+        let buf = self
+            .hive
+            .cell(offset)?
+            .get(..node.class_name_len as usize)
+            .ok_or(Error::InvalidCell(offset))?;
+        let mut class_name = decode_name(buf, false);
+        if let Some(nul) = class_name.iter().position(|x| *x == 0) {
+            class_name.truncate(nul);
+        }
+        Ok(Some(U16CString::new(class_name)?))
+    }
+
+    #[inline]
+    pub fn keys(&self) -> Keys<'a> {
+        Keys::new(self)
+    }
+
+    #[inline]
+    pub fn values(&self) -> Values<'a> {
+        Values::new(self)
+    }
+
+    /// Iterates over the values of this key without decoding their names or data.
+    #[inline]
+    pub fn raw_values(&self) -> RawValues<'a> {
+        RawValues::new(self)
+    }
+}
+
+/// An iterator over the subkeys of an [`OfflineKey`](struct.OfflineKey.html).
+#[derive(Debug)]
+pub struct Keys<'a> {
+    hive: &'a OfflineHive,
+    // Subkey lists can be nested one level deep through an index root, so this holds the offset of
+    // each list being walked along with the index of its next entry. An index root below the top
+    // list is rejected, which also stops lists that refer back to themselves.
+    stack: Vec<(u32, usize)>,
+    error: Option<Error>,
+}
+
+impl<'a> Keys<'a> {
+    fn new(key: &OfflineKey<'a>) -> Keys<'a> {
+        let mut keys = Keys {
+            hive: key.hive,
+            stack: vec![],
+            error: None,
+        };
+
+        match key.node() {
+            Ok(node) if node.subkey_count > 0 && node.subkey_list != NO_CELL => {
+                keys.stack.push((node.subkey_list, 0))
+            }
+            Ok(_) => {}
+            Err(e) => keys.error = Some(e),
+        }
+
+        keys
+    }
+}
+
+impl<'a> Iterator for Keys<'a> {
+    type Item = Result<OfflineKey<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.error.take() {
+            return Some(Err(e));
+        }
+
+        loop {
+            let is_top = self.stack.len() == 1;
+            let (list_offset, index) = self.stack.last_mut()?;
+            let list = match self
+                .hive
+                .cell(*list_offset)
+                .and_then(|buf| SubkeyList::parse(*list_offset, buf))
+            {
+                Ok(v) => v,
+                Err(e) => {
+                    self.stack.clear();
+                    return Some(Err(e));
+                }
+            };
+
+            if list.is_root && !is_top {
+                let offset = *list_offset;
+                self.stack.clear();
+                return Some(Err(Error::InvalidCell(offset)));
+            }
+
+            if *index >= list.len() {
+                self.stack.pop();
+                continue;
+            }
+
+            let offset = list.get(*index);
+            *index += 1;
+
+            if list.is_root {
+                self.stack.push((offset, 0));
+                continue;
+            }
+
+            return Some(OfflineKey::new(self.hive, offset));
+        }
+    }
+}
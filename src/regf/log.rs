@@ -0,0 +1,538 @@
+use std::{ffi::OsString, path::Path};
+
+use super::base::{checksum, BaseBlock, BASE_BLOCK_SIZE};
+use super::cell::{le_u32, le_u64};
+use super::{Error, OfflineHive};
+
+/// The part of a base block that is copied into transaction logs.
+const LOG_BASE_BLOCK_LEN: usize = 512;
+
+/// Old format logs track dirty data in sectors of this size.
+const SECTOR_SIZE: usize = 512;
+
+const LOG_ENTRY_HEADER_LEN: usize = 40;
+
+/// The seed of the Marvin32 hashes that protect new format log entries.
+const MARVIN32_SEED: u64 = 0x82EF_4D88_7A4E_55C5;
+
+/// A `.LOG`, `.LOG1` or `.LOG2` transaction log belonging to a hive file.
+///
+/// Both the old format written before Windows 8.1, with a single dirty vector (`DIRT`), and the
+/// new format made up of a sequence of log entries (`HvLE`) are understood. Entries that fail their
+/// hash checks are treated as the end of the log, as are entries that would leave a gap in the
+/// sequence or give the hive an implausible size.
+#[derive(Debug)]
+pub struct TransactionLog {
+    data: Vec<u8>,
+    base: BaseBlock,
+    is_new_format: bool,
+    entries: Vec<LogEntry>,
+}
+
+/// A set of pages written to the hive bins in one go.
+#[derive(Debug)]
+struct LogEntry {
+    sequence: u32,
+    hive_bins_size: u32,
+    /// The offset of each page in the hive bins, with its offset and length in the log file.
+    pages: Vec<(u32, usize, usize)>,
+}
+
+impl TransactionLog {
+    #[inline]
+    pub fn open<P: AsRef<Path>>(file_path: P) -> Result<TransactionLog, Error> {
+        TransactionLog::from_bytes(std::fs::read(file_path)?)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<TransactionLog, Error> {
+        let base = BaseBlock::parse(&data)?;
+
+        let (is_new_format, entries) = match data.get(LOG_BASE_BLOCK_LEN..LOG_BASE_BLOCK_LEN + 4) {
+            Some(b"HvLE") => (true, new_format_entries(&data)),
+            Some(b"DIRT") => (false, old_format_entries(&data, &base)?),
+            _ => (base.file_type == 6, vec![]),
+        };
+
+        Ok(TransactionLog {
+            data,
+            base,
+            is_new_format,
+            entries,
+        })
+    }
+
+    #[inline]
+    pub fn base_block(&self) -> &BaseBlock {
+        &self.base
+    }
+
+    /// Whether the log is made up of `HvLE` entries rather than a dirty vector.
+    #[inline]
+    pub fn is_new_format(&self) -> bool {
+        self.is_new_format
+    }
+
+    /// The number of intact entries in the log. Old format logs hold at most one.
+    #[inline]
+    pub fn entry_count(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+fn new_format_entries(data: &[u8]) -> Vec<LogEntry> {
+    let mut entries = vec![];
+    let mut offset = LOG_BASE_BLOCK_LEN;
+
+    while let Some(header) = data.get(offset..offset + LOG_ENTRY_HEADER_LEN) {
+        let size = le_u32(header, 4) as usize;
+        if &header[0..4] != b"HvLE"
+            || size < LOG_ENTRY_HEADER_LEN
+            || !size.is_multiple_of(SECTOR_SIZE)
+        {
+            break;
+        }
+
+        let entry = match data.get(offset..offset + size) {
+            Some(v) => v,
+            None => break,
+        };
+        if marvin32(MARVIN32_SEED, &entry[LOG_ENTRY_HEADER_LEN..]) != le_u64(entry, 24)
+            || marvin32(MARVIN32_SEED, &entry[..32]) != le_u64(entry, 32)
+        {
+            break;
+        }
+
+        let page_count = le_u32(entry, 20) as usize;
+        let mut page = LOG_ENTRY_HEADER_LEN + page_count * 8;
+        let mut pages = vec![];
+        for i in 0..page_count {
+            let reference = LOG_ENTRY_HEADER_LEN + i * 8;
+            if reference + 8 > size {
+                break;
+            }
+            let len = le_u32(entry, reference + 4) as usize;
+            if page + len > size {
+                break;
+            }
+            pages.push((le_u32(entry, reference), offset + page, len));
+            page += len;
+        }
+        if pages.len() != page_count {
+            break;
+        }
+
+        entries.push(LogEntry {
+            sequence: le_u32(entry, 12),
+            hive_bins_size: le_u32(entry, 16),
+            pages,
+        });
+        offset += size;
+    }
+
+    entries
+}
+
+fn old_format_entries(data: &[u8], base: &BaseBlock) -> Result<Vec<LogEntry>, Error> {
+    // A log whose own write did not complete holds nothing that can be trusted.
+    if base.is_dirty() || checksum(data) != base.checksum {
+        return Ok(vec![]);
+    }
+
+    let sectors = base.hive_bins_size as usize / SECTOR_SIZE;
+    let bitmap_start = LOG_BASE_BLOCK_LEN + 4;
+    let bitmap = data
+        .get(bitmap_start..bitmap_start + sectors.div_ceil(8))
+        .ok_or(Error::Truncated(data.len()))?;
+
+    let mut sector = (bitmap_start + bitmap.len()).div_ceil(SECTOR_SIZE) * SECTOR_SIZE;
+    let mut pages = vec![];
+    for i in (0..sectors).filter(|i| bitmap[i / 8] & (1 << (i % 8)) != 0) {
+        if sector + SECTOR_SIZE > data.len() {
+            return Err(Error::Truncated(data.len()));
+        }
+        pages.push(((i * SECTOR_SIZE) as u32, sector, SECTOR_SIZE));
+        sector += SECTOR_SIZE;
+    }
+
+    Ok(vec![LogEntry {
+        sequence: base.primary_sequence,
+        hive_bins_size: base.hive_bins_size,
+        pages,
+    }])
+}
+
+impl OfflineHive {
+    /// Opens a hive file, replaying its `.LOG1` and `.LOG2` transaction logs (or `.LOG`, for
+    /// older hives) if it is dirty.
+    pub fn open_with_logs<P: AsRef<Path>>(file_path: P) -> Result<OfflineHive, Error> {
+        let mut hive = OfflineHive::open(&file_path)?;
+        if !hive.base.is_dirty() {
+            return Ok(hive);
+        }
+
+        let mut logs = vec![];
+        for extension in &[".LOG", ".LOG1", ".LOG2"] {
+            let mut log_path = OsString::from(file_path.as_ref());
+            log_path.push(extension);
+            match TransactionLog::open(&log_path) {
+                Ok(log) => logs.push(log),
+                Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        hive.replay(&logs)?;
+        Ok(hive)
+    }
+
+    /// Brings a dirty hive up to date by applying the entries of its transaction logs, in order of
+    /// sequence number, starting from the last write that completed. Returns the number of
+    /// entries applied.
+    ///
+    /// Once at least one entry has been applied the hive is no longer dirty, and can be saved or
+    /// edited.
+    pub fn replay(&mut self, logs: &[TransactionLog]) -> Result<usize, Error> {
+        if !self.base.is_dirty() {
+            return Ok(0);
+        }
+
+        let mut entries = logs
+            .iter()
+            .flat_map(|log| log.entries.iter().map(move |entry| (log, entry)))
+            .filter(|(_, entry)| entry.sequence >= self.base.secondary_sequence)
+            .collect::<Vec<_>>();
+        entries.sort_by_key(|(_, entry)| entry.sequence);
+
+        let log_len = logs.iter().map(|log| log.data.len()).sum();
+        let mut applied: Option<(&TransactionLog, &LogEntry)> = None;
+        let mut count = 0;
+        for (log, entry) in entries {
+            match applied {
+                // Both logs can hold a copy of the same entry.
+                Some((_, last)) if entry.sequence == last.sequence => continue,
+                Some((_, last)) if entry.sequence != last.sequence.wrapping_add(1) => break,
+                // The first entry must follow on from the last write that completed. Old format logs
+                // hold everything written since then in a single entry.
+                None if log.is_new_format && entry.sequence != self.base.secondary_sequence => {
+                    break
+                }
+                _ => {}
+            }
+            if !self.is_valid_resize(entry, log_len) {
+                break;
+            }
+
+            let end = BASE_BLOCK_SIZE + entry.hive_bins_size as usize;
+            self.data.to_mut().resize(end, 0);
+            for (offset, start, len) in &entry.pages {
+                let page = BASE_BLOCK_SIZE + *offset as usize;
+                let dst = self
+                    .data
+                    .get_mut(page..page + len)
+                    .ok_or(Error::InvalidCell(*offset))?;
+                dst.copy_from_slice(&log.data[*start..start + len]);
+            }
+            applied = Some((log, entry));
+            count += 1;
+        }
+
+        let (log, last) = match applied {
+            Some(v) => v,
+            None => return Ok(0),
+        };
+
+        // The base block of the log describes the hive as of its last write.
+        self.data[..LOG_BASE_BLOCK_LEN].copy_from_slice(&log.data[..LOG_BASE_BLOCK_LEN]);
+        let data = &mut self.data;
+        let sequence = le_u32(data, 4).max(last.sequence.wrapping_add(1));
+        data[4..8].copy_from_slice(&sequence.to_le_bytes());
+        data[8..12].copy_from_slice(&sequence.to_le_bytes());
+        data[28..32].copy_from_slice(&0u32.to_le_bytes());
+        data[40..44].copy_from_slice(&last.hive_bins_size.to_le_bytes());
+        let checksum = checksum(data);
+        data[508..512].copy_from_slice(&checksum.to_le_bytes());
+
+        self.base = BaseBlock::parse(&self.data)?;
+        Ok(count)
+    }
+
+    /// Whether the hive bins size of a log entry is a whole number of pages, and grows the hive by
+    /// no more than the size of the logs. New hive bins are always dirty, so they are written to
+    /// the logs along with the entries that add them.
+    fn is_valid_resize(&self, entry: &LogEntry, log_len: usize) -> bool {
+        let current = self.data.len().saturating_sub(BASE_BLOCK_SIZE);
+        entry.hive_bins_size.is_multiple_of(4096)
+            && entry.hive_bins_size as usize <= current.saturating_add(log_len)
+    }
+}
+
+/// The Marvin32 hash of `data`, as used by Windows for hash tables and registry logs.
+pub(crate) fn marvin32(seed: u64, data: &[u8]) -> u64 {
+    let mut lo = seed as u32;
+    let mut hi = (seed >> 32) as u32;
+
+    let mut block = |value: u32| {
+        lo = lo.wrapping_add(value);
+        hi ^= lo;
+        lo = lo.rotate_left(20).wrapping_add(hi);
+        hi = hi.rotate_left(9) ^ lo;
+        lo = lo.rotate_left(27).wrapping_add(hi);
+        hi = hi.rotate_left(19);
+    };
+
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        block(le_u32(chunk, 0));
+    }
+
+    let rest = chunks.remainder();
+    let last = rest
+        .iter()
+        .rev()
+        .fold(0x80u32, |acc, x| (acc << 8) | u32::from(*x));
+    block(last);
+    block(0);
+
+    (u64::from(hi) << 32) | u64::from(lo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    use crate::regf::{HiveEditor, HiveWriter};
+    use crate::tree::KeyTree;
+    use crate::value::Data;
+
+    fn s(x: &str) -> utfx::U16CString {
+        x.try_into().unwrap()
+    }
+
+    /// A hive, and the same hive after a few edits.
+    fn hive_versions() -> (Vec<u8>, Vec<u8>) {
+        let mut root = KeyTree::new(s("ROOT"));
+        root.create(&s(r"Software\Vendor"))
+            .set_value(s("Version"), Data::U32(1));
+        let before = HiveWriter::new().to_bytes(&root).unwrap();
+
+        let mut editor = HiveEditor::from_bytes(before.clone()).unwrap();
+        editor
+            .set_value(r"Software\Vendor", "Version", &Data::U32(2))
+            .unwrap();
+        editor
+            .set_value("Software", "Large", &Data::Binary(vec![7; 6000]))
+            .unwrap();
+        editor.create_key(r"Software\Vendor\App").unwrap();
+        (before, editor.to_bytes().unwrap())
+    }
+
+    /// Marks the start of a write in the base block without completing it.
+    fn make_dirty(mut hive: Vec<u8>) -> Vec<u8> {
+        let sequence = le_u32(&hive, 4) + 1;
+        hive[4..8].copy_from_slice(&sequence.to_le_bytes());
+        let checksum = checksum(&hive);
+        hive[508..512].copy_from_slice(&checksum.to_le_bytes());
+        hive
+    }
+
+    fn log_base_block(after: &[u8], file_type: u32) -> Vec<u8> {
+        let mut log = after[..LOG_BASE_BLOCK_LEN].to_vec();
+        log[28..32].copy_from_slice(&file_type.to_le_bytes());
+        let checksum = checksum(&log);
+        log[508..512].copy_from_slice(&checksum.to_le_bytes());
+        log
+    }
+
+    /// The offsets of the pages of `page_size` bytes that differ between two versions of a hive.
+    fn dirty_pages(before: &[u8], after: &[u8], page_size: usize) -> Vec<usize> {
+        let bins = |x: &[u8]| x[BASE_BLOCK_SIZE..].to_vec();
+        let (before, after) = (bins(before), bins(after));
+        (0..after.len() / page_size)
+            .map(|i| i * page_size)
+            .filter(|x| before.get(*x..x + page_size) != Some(&after[*x..x + page_size]))
+            .collect()
+    }
+
+    fn log_entry(sequence: u32, after: &[u8], pages: &[usize]) -> Vec<u8> {
+        let mut entry = vec![0u8; LOG_ENTRY_HEADER_LEN];
+        entry[0..4].copy_from_slice(b"HvLE");
+        entry[12..16].copy_from_slice(&sequence.to_le_bytes());
+        entry[16..20].copy_from_slice(&((after.len() - BASE_BLOCK_SIZE) as u32).to_le_bytes());
+        entry[20..24].copy_from_slice(&(pages.len() as u32).to_le_bytes());
+        for page in pages {
+            entry.extend_from_slice(&(*page as u32).to_le_bytes());
+            entry.extend_from_slice(&4096u32.to_le_bytes());
+        }
+        for page in pages {
+            let start = BASE_BLOCK_SIZE + page;
+            entry.extend_from_slice(&after[start..start + 4096]);
+        }
+        entry.resize(entry.len().div_ceil(SECTOR_SIZE) * SECTOR_SIZE, 0);
+
+        let size = entry.len() as u32;
+        entry[4..8].copy_from_slice(&size.to_le_bytes());
+        let hash = marvin32(MARVIN32_SEED, &entry[LOG_ENTRY_HEADER_LEN..]);
+        entry[24..32].copy_from_slice(&hash.to_le_bytes());
+        let hash = marvin32(MARVIN32_SEED, &entry[..32]);
+        entry[32..40].copy_from_slice(&hash.to_le_bytes());
+        entry
+    }
+
+    fn assert_recovered(hive: &OfflineHive) {
+        assert!(!hive.base_block().is_dirty());
+        assert!(hive.is_checksum_valid());
+        assert_eq!(hive.base_block().file_type, 0);
+        let vendor = hive.open_key(r"Software\Vendor").unwrap();
+        assert_eq!(vendor.value("Version").unwrap(), Data::U32(2));
+        assert!(vendor.open("App").is_ok());
+        assert_eq!(
+            hive.open_key("Software").unwrap().value("Large").unwrap(),
+            Data::Binary(vec![7; 6000])
+        );
+    }
+
+    #[test]
+    fn marvin32_test_vectors() {
+        let seed = 0x004F_B61A_001B_DBCC;
+        assert_eq!(marvin32(seed, b""), 0x30ED_35C1_00CD_3C7D);
+        assert_eq!(marvin32(seed, &[0xAF]), 0x48E7_3FC7_7D75_DDC1);
+        assert_eq!(marvin32(seed, &[0xE7, 0x0F]), 0xB5F6_E1FC_485D_BFF8);
+        assert_eq!(marvin32(seed, &[0x37, 0xF4, 0x95]), 0xF0B0_7C78_9B8C_F7E8);
+        assert_eq!(
+            marvin32(seed, &[0x86, 0x42, 0xDC, 0x59]),
+            0x7008_F2E8_7E9C_F556
+        );
+    }
+
+    #[test]
+    fn replay_new_format() {
+        let (before, after) = hive_versions();
+        let pages = dirty_pages(&before, &after, 4096);
+        let (first, second) = pages.split_at(1);
+
+        // The changes are split over two entries, in different logs.
+        let mut log1 = log_base_block(&after, 6);
+        log1.extend_from_slice(&log_entry(1, &after, first));
+        let mut log2 = log_base_block(&after, 6);
+        log2.extend_from_slice(&log_entry(2, &after, second));
+        let logs = vec![
+            TransactionLog::from_bytes(log2).unwrap(),
+            TransactionLog::from_bytes(log1).unwrap(),
+        ];
+        assert!(logs[0].is_new_format());
+        assert_eq!(logs[0].entry_count(), 1);
+
+        let mut hive = OfflineHive::from_bytes(make_dirty(before)).unwrap();
+        assert!(hive.base_block().is_dirty());
+        assert_eq!(hive.replay(&logs).unwrap(), 2);
+        assert_recovered(&hive);
+        assert_eq!(hive.bins(), &after[BASE_BLOCK_SIZE..]);
+    }
+
+    #[test]
+    fn corrupt_entries_end_the_log() {
+        let (before, after) = hive_versions();
+        let pages = dirty_pages(&before, &after, 4096);
+
+        let mut log = log_base_block(&after, 6);
+        log.extend_from_slice(&log_entry(1, &after, &pages[..1]));
+        let mut corrupt = log_entry(2, &after, &pages[1..]);
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 1;
+        log.extend_from_slice(&corrupt);
+
+        let log = TransactionLog::from_bytes(log).unwrap();
+        assert_eq!(log.entry_count(), 1);
+
+        // Entries from before the last completed write are ignored.
+        let mut hive = OfflineHive::from_bytes(make_dirty(before.clone())).unwrap();
+        hive.data[8..12].copy_from_slice(&2u32.to_le_bytes());
+        hive.base.secondary_sequence = 2;
+        hive.data[4..8].copy_from_slice(&3u32.to_le_bytes());
+        hive.base.primary_sequence = 3;
+        assert_eq!(hive.replay(std::slice::from_ref(&log)).unwrap(), 0);
+        assert!(hive.base_block().is_dirty());
+    }
+
+    #[test]
+    fn implausible_entries_end_the_log() {
+        let (before, after) = hive_versions();
+        let pages = dirty_pages(&before, &after, 4096);
+        let replay = |entries: &[Vec<u8>]| {
+            let mut log = log_base_block(&after, 6);
+            for entry in entries {
+                log.extend_from_slice(entry);
+            }
+            let log = TransactionLog::from_bytes(log).unwrap();
+            let mut hive = OfflineHive::from_bytes(make_dirty(before.clone())).unwrap();
+            let count = hive.replay(std::slice::from_ref(&log)).unwrap();
+            (count, hive.data.len())
+        };
+
+        // The first entry does not follow on from the last completed write.
+        assert_eq!(replay(&[log_entry(2, &after, &pages)]).0, 0);
+
+        let resized = |sequence: u32, size: u32| {
+            let mut entry = log_entry(sequence, &after, &pages);
+            entry[16..20].copy_from_slice(&size.to_le_bytes());
+            let hash = marvin32(MARVIN32_SEED, &entry[..32]);
+            entry[32..40].copy_from_slice(&hash.to_le_bytes());
+            entry
+        };
+        assert_eq!(replay(&[resized(1, 0xFFFF_F000)]), (0, before.len()));
+        assert_eq!(replay(&[resized(1, 4097)]), (0, before.len()));
+        assert_eq!(
+            replay(&[log_entry(1, &after, &pages), resized(2, 0xFFFF_F000)]),
+            (1, after.len())
+        );
+    }
+
+    #[test]
+    fn replay_old_format() {
+        let (before, after) = hive_versions();
+        let sectors = dirty_pages(&before, &after, SECTOR_SIZE);
+
+        let mut log = log_base_block(&after, 1);
+        log.extend_from_slice(b"DIRT");
+        let mut bitmap = vec![0u8; (after.len() - BASE_BLOCK_SIZE) / SECTOR_SIZE / 8];
+        for sector in &sectors {
+            let i = sector / SECTOR_SIZE;
+            bitmap[i / 8] |= 1 << (i % 8);
+        }
+        log.extend_from_slice(&bitmap);
+        log.resize(log.len().div_ceil(SECTOR_SIZE) * SECTOR_SIZE, 0);
+        for sector in &sectors {
+            let start = BASE_BLOCK_SIZE + sector;
+            log.extend_from_slice(&after[start..start + SECTOR_SIZE]);
+        }
+
+        let log = TransactionLog::from_bytes(log).unwrap();
+        assert!(!log.is_new_format());
+
+        let mut hive = OfflineHive::from_bytes(make_dirty(before)).unwrap();
+        assert_eq!(hive.replay(&[log]).unwrap(), 1);
+        assert_recovered(&hive);
+    }
+
+    #[test]
+    fn open_with_logs() {
+        let (before, after) = hive_versions();
+        let pages = dirty_pages(&before, &after, 4096);
+        let mut log = log_base_block(&after, 6);
+        log.extend_from_slice(&log_entry(1, &after, &pages));
+
+        let dir = std::env::temp_dir().join(format!("registry-log-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("SOFTWARE");
+        std::fs::write(&path, make_dirty(before)).unwrap();
+        std::fs::write(dir.join("SOFTWARE.LOG1"), log).unwrap();
+
+        let hive = OfflineHive::open_with_logs(&path).unwrap();
+        assert_recovered(&hive);
+
+        hive.save(&path).unwrap();
+        assert_recovered(&OfflineHive::open(&path).unwrap());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,592 @@
+//! Reading and writing of registry hive files in the regf format, on any platform.
+//!
+//! Hive files such as `SYSTEM`, `SOFTWARE` or `NTUSER.DAT` can be opened with
+//! [`OfflineHive`](struct.OfflineHive.html), and browsed with the same [`Data`](../value/enum.Data.html)
+//! type and iterator style that [`RegKey`](../struct.RegKey.html) offers. New hives can be built from a
+//! [`KeyTree`](../tree/struct.KeyTree.html) with [`HiveWriter`](struct.HiveWriter.html), and existing
+//! hives modified in place with [`HiveEditor`](struct.HiveEditor.html).
+//!
+//! Hives copied from a running or crashed system are often dirty, with their latest changes only
+//! in the `.LOG1` and `.LOG2` files next to them. [`OfflineHive::open_with_logs`](struct.OfflineHive.html#method.open_with_logs)
+//! replays these [`TransactionLog`](struct.TransactionLog.html)s to give a consistent view.
+//!
+//! ```ignore
+//! let hive = OfflineHive::open("SOFTWARE")?;
+//! let key = hive.open_key(r"Microsoft\Windows NT\CurrentVersion")?;
+//! println!("{}", key.value("ProductName")?);
+//! ```
+
+use std::{
+    convert::Infallible,
+    convert::TryInto,
+    ops::{Deref, DerefMut},
+    path::Path,
+};
+
+use utfx::U16CString;
+
+mod base;
+mod cell;
+mod edit;
+mod key;
+mod log;
+mod name;
+mod recover;
+mod value;
+mod verify;
+mod write;
+
+pub use base::BaseBlock;
+pub use edit::HiveEditor;
+pub use key::{Keys, OfflineKey};
+pub use log::TransactionLog;
+pub use name::NameRef;
+pub use recover::{Deleted, Recovered, RecoveredKey, RecoveredValue};
+pub use value::{OfflineValue, RawValues, ValueRef, Values};
+pub use verify::Finding;
+#[cfg(test)]
+pub(crate) use write::default_security_descriptor;
+pub use write::HiveWriter;
+
+use base::BASE_BLOCK_SIZE;
+use cell::le_u32;
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Invalid hive file signature")]
+    InvalidSignature,
+
+    #[error("Unsupported hive format version: {0}.{1}")]
+    UnsupportedVersion(u32, u32),
+
+    #[error("Hive file is truncated at {0} bytes")]
+    Truncated(usize),
+
+    #[error("Invalid or corrupt cell at offset {0:#x}")]
+    InvalidCell(u32),
+
+    #[error("Provided path not found: {0:?}")]
+    NotFound(String),
+
+    #[error("Name is too long to be stored in a hive: {0:?}")]
+    NameTooLong(String),
+
+    #[error("Key names cannot be empty or contain a backslash: {0:?}")]
+    InvalidName(String),
+
+    #[error("A key or value with this name already exists: {0:?}")]
+    AlreadyExists(String),
+
+    #[error("Key has subkeys and cannot be deleted without recursion: {0:?}")]
+    HasSubkeys(String),
+
+    #[error("The root key of a hive cannot be deleted")]
+    RootKey,
+
+    #[error("Hive is dirty and its transaction logs must be replayed before it can be modified")]
+    Dirty,
+
+    #[error("Invalid null found in name")]
+    InvalidNul(#[from] utfx::NulError<u16>),
+
+    #[error("Error parsing data")]
+    Data(#[from] crate::value::Error),
+
+    #[error("Error parsing security descriptor")]
+    Descriptor(#[from] crate::descriptor::Error),
+
+    #[error("An IO error occurred while reading the hive")]
+    Io(#[from] std::io::Error),
+}
+
+impl From<Infallible> for Error {
+    fn from(_: Infallible) -> Self {
+        unsafe { std::hint::unreachable_unchecked() }
+    }
+}
+
+/// The contents of a hive file, either read into memory or mapped from disk.
+#[derive(Debug)]
+pub(crate) enum Storage {
+    Owned(Vec<u8>),
+    Mapped(memmap2::Mmap),
+}
+
+impl Storage {
+    /// The contents as a vector, copying them out of the mapping first if need be.
+    pub(crate) fn to_mut(&mut self) -> &mut Vec<u8> {
+        if let Storage::Mapped(map) = self {
+            *self = Storage::Owned(map.to_vec());
+        }
+
+        match self {
+            Storage::Owned(data) => data,
+            Storage::Mapped(_) => unreachable!(),
+        }
+    }
+
+    pub(crate) fn into_vec(self) -> Vec<u8> {
+        match self {
+            Storage::Owned(data) => data,
+            Storage::Mapped(map) => map.to_vec(),
+        }
+    }
+}
+
+impl Deref for Storage {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        match self {
+            Storage::Owned(data) => data,
+            Storage::Mapped(map) => map,
+        }
+    }
+}
+
+impl DerefMut for Storage {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.to_mut()
+    }
+}
+
+/// A registry hive file, parsed without any help from Windows.
+///
+/// Cells are decoded on demand as keys and values are visited, so only the file itself needs to be
+/// held in memory. For very large hives, [`open_mapped`](#method.open_mapped) avoids even that.
+#[derive(Debug)]
+pub struct OfflineHive {
+    pub(crate) data: Storage,
+    pub(crate) base: BaseBlock,
+}
+
+impl OfflineHive {
+    #[inline]
+    pub fn open<P: AsRef<Path>>(file_path: P) -> Result<OfflineHive, Error> {
+        OfflineHive::from_bytes(std::fs::read(file_path)?)
+    }
+
+    /// Opens a hive file by mapping it into memory, so that only the pages that are visited are
+    /// read from disk.
+    ///
+    /// Replaying transaction logs or editing the hive copies it into memory first.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated by this or any other process while the hive is
+    /// open, as the mapping would change underneath borrowed names and data.
+    pub unsafe fn open_mapped<P: AsRef<Path>>(file_path: P) -> Result<OfflineHive, Error> {
+        let file = std::fs::File::open(file_path)?;
+        OfflineHive::from_storage(Storage::Mapped(memmap2::Mmap::map(&file)?))
+    }
+
+    #[inline]
+    pub fn from_bytes(data: Vec<u8>) -> Result<OfflineHive, Error> {
+        OfflineHive::from_storage(Storage::Owned(data))
+    }
+
+    fn from_storage(data: Storage) -> Result<OfflineHive, Error> {
+        let base = BaseBlock::parse(&data)?;
+
+        if data.len() < BASE_BLOCK_SIZE {
+            return Err(Error::Truncated(data.len()));
+        }
+
+        Ok(OfflineHive { data, base })
+    }
+
+    /// Writes the hive back out to the file at `file_path`, for instance after its transaction
+    /// logs have been replayed.
+    #[inline]
+    pub fn save<P: AsRef<Path>>(&self, file_path: P) -> Result<(), Error> {
+        std::fs::write(file_path, &*self.data)?;
+        Ok(())
+    }
+
+    /// The contents of the hive file.
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    #[inline]
+    pub fn base_block(&self) -> &BaseBlock {
+        &self.base
+    }
+
+    /// Whether the checksum stored in the base block matches its contents.
+    #[inline]
+    pub fn is_checksum_valid(&self) -> bool {
+        base::checksum(&self.data) == self.base.checksum
+    }
+
+    #[inline]
+    pub fn root(&self) -> Result<OfflineKey<'_>, Error> {
+        OfflineKey::new(self, self.base.root_cell)
+    }
+
+    /// Opens a key by its path relative to the root of the hive.
+    #[inline]
+    pub fn open_key<P>(&self, path: P) -> Result<OfflineKey<'_>, Error>
+    where
+        P: TryInto<U16CString>,
+        P::Error: Into<Error>,
+    {
+        self.root()?.open(path)
+    }
+
+    /// The hive bins, which cell offsets are relative to.
+    #[inline]
+    pub(crate) fn bins(&self) -> &[u8] {
+        let end = (BASE_BLOCK_SIZE + self.base.hive_bins_size as usize).min(self.data.len());
+        &self.data[BASE_BLOCK_SIZE..end]
+    }
+
+    /// The contents of the cell at `offset`, without its size header.
+    pub(crate) fn cell(&self, offset: u32) -> Result<&[u8], Error> {
+        let bins = self.bins();
+        let start = offset as usize;
+
+        if start + 4 > bins.len() {
+            return Err(Error::InvalidCell(offset));
+        }
+
+        let size = (le_u32(bins, start) as i32).unsigned_abs() as usize;
+        if size < 4 || start + size > bins.len() {
+            return Err(Error::InvalidCell(offset));
+        }
+
+        Ok(&bins[start + 4..start + size])
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    use crate::value::Data;
+
+    /// Lays out cells one after another in a single hive bin.
+    pub(crate) struct TestHive {
+        bins: Vec<u8>,
+    }
+
+    impl TestHive {
+        pub(crate) fn new() -> TestHive {
+            let mut bins = b"hbin".to_vec();
+            bins.resize(32, 0);
+            TestHive { bins }
+        }
+
+        pub(crate) fn cell(&mut self, payload: &[u8]) -> u32 {
+            let offset = self.bins.len() as u32;
+            let size = (payload.len() + 4 + 7) & !7;
+            self.bins.extend_from_slice(&(-(size as i32)).to_le_bytes());
+            self.bins.extend_from_slice(payload);
+            self.bins.resize(offset as usize + size, 0);
+            offset
+        }
+
+        pub(crate) fn nk(&mut self, name: &str, subkeys: (u32, u32), values: (u32, u32)) -> u32 {
+            let (flags, name) = encode_name(name, 0x0020);
+            let mut buf = vec![0u8; 76];
+            buf[0..2].copy_from_slice(b"nk");
+            buf[2..4].copy_from_slice(&flags.to_le_bytes());
+            buf[20..24].copy_from_slice(&subkeys.0.to_le_bytes());
+            buf[28..32].copy_from_slice(&subkeys.1.to_le_bytes());
+            buf[32..36].copy_from_slice(&u32::MAX.to_le_bytes());
+            buf[36..40].copy_from_slice(&values.0.to_le_bytes());
+            buf[40..44].copy_from_slice(&values.1.to_le_bytes());
+            buf[44..48].copy_from_slice(&u32::MAX.to_le_bytes());
+            buf[48..52].copy_from_slice(&u32::MAX.to_le_bytes());
+            buf[72..74].copy_from_slice(&(name.len() as u16).to_le_bytes());
+            buf.extend_from_slice(&name);
+            self.cell(&buf)
+        }
+
+        pub(crate) fn vk(&mut self, name: &str, data: &Data) -> u32 {
+            let (flags, name) = encode_name(name, 0x0001);
+            let bytes = data.to_bytes();
+            let (size, offset) = if bytes.len() <= 4 {
+                let mut inline = [0u8; 4];
+                inline[..bytes.len()].copy_from_slice(&bytes);
+                (bytes.len() as u32 | 0x8000_0000, u32::from_le_bytes(inline))
+            } else {
+                (bytes.len() as u32, self.cell(&bytes))
+            };
+
+            let mut buf = vec![0u8; 20];
+            buf[0..2].copy_from_slice(b"vk");
+            buf[2..4].copy_from_slice(&(name.len() as u16).to_le_bytes());
+            buf[4..8].copy_from_slice(&size.to_le_bytes());
+            buf[8..12].copy_from_slice(&offset.to_le_bytes());
+            buf[12..16].copy_from_slice(&data.raw_type().to_le_bytes());
+            buf[16..18].copy_from_slice(&flags.to_le_bytes());
+            buf.extend_from_slice(&name);
+            self.cell(&buf)
+        }
+
+        pub(crate) fn list(&mut self, signature: &[u8; 2], offsets: &[u32]) -> u32 {
+            let mut buf = signature.to_vec();
+            buf.extend_from_slice(&(offsets.len() as u16).to_le_bytes());
+            for offset in offsets {
+                buf.extend_from_slice(&offset.to_le_bytes());
+                if signature == b"lf" || signature == b"lh" {
+                    buf.extend_from_slice(&[0; 4]);
+                }
+            }
+            self.cell(&buf)
+        }
+
+        pub(crate) fn value_list(&mut self, offsets: &[u32]) -> u32 {
+            let buf = offsets
+                .iter()
+                .flat_map(|x| x.to_le_bytes().to_vec())
+                .collect::<Vec<_>>();
+            self.cell(&buf)
+        }
+
+        pub(crate) fn finish(mut self, root: u32) -> Vec<u8> {
+            let used = self.bins.len();
+            let size = (used + 4095) & !4095;
+            if size > used {
+                self.bins
+                    .extend_from_slice(&((size - used) as i32).to_le_bytes());
+                self.bins.resize(size, 0);
+            }
+            self.bins[8..12].copy_from_slice(&(size as u32).to_le_bytes());
+
+            let mut data = vec![0u8; BASE_BLOCK_SIZE];
+            data[0..4].copy_from_slice(b"regf");
+            data[4..8].copy_from_slice(&1u32.to_le_bytes());
+            data[8..12].copy_from_slice(&1u32.to_le_bytes());
+            data[20..24].copy_from_slice(&1u32.to_le_bytes());
+            data[24..28].copy_from_slice(&5u32.to_le_bytes());
+            data[32..36].copy_from_slice(&1u32.to_le_bytes());
+            data[36..40].copy_from_slice(&root.to_le_bytes());
+            data[40..44].copy_from_slice(&(size as u32).to_le_bytes());
+            data[44..48].copy_from_slice(&1u32.to_le_bytes());
+            let checksum = base::checksum(&data);
+            data[508..512].copy_from_slice(&checksum.to_le_bytes());
+            data.extend_from_slice(&self.bins);
+            data
+        }
+    }
+
+    fn encode_name(name: &str, compressed_flag: u16) -> (u16, Vec<u8>) {
+        if name.chars().all(|c| (c as u32) < 0x100) {
+            (compressed_flag, name.chars().map(|c| c as u8).collect())
+        } else {
+            (
+                0,
+                name.encode_utf16()
+                    .flat_map(|x| x.to_le_bytes().to_vec())
+                    .collect(),
+            )
+        }
+    }
+
+    /// Builds a small hive with a nested index root, every kind of leaf list, and a few values.
+    pub(crate) fn sample_hive() -> Vec<u8> {
+        let mut hive = TestHive::new();
+
+        let version = hive.vk("Version", &Data::U32(42));
+        let default = hive.vk("", &Data::String("hello".try_into().unwrap()));
+        let multi = hive.vk(
+            "Multi",
+            &Data::MultiString(vec!["a".try_into().unwrap(), "bc".try_into().unwrap()]),
+        );
+        let values = hive.value_list(&[version, default, multi]);
+        let vendor = hive.nk("Vëndor", (0, u32::MAX), (3, values));
+        let unicode = hive.nk("Ключ", (0, u32::MAX), (0, u32::MAX));
+        let software_list = hive.list(b"lh", &[unicode, vendor]);
+        let software = hive.nk("Software", (2, software_list), (0, u32::MAX));
+        let system = hive.nk("System", (0, u32::MAX), (0, u32::MAX));
+
+        let first = hive.list(b"li", &[software]);
+        let second = hive.list(b"lf", &[system]);
+        let root_list = hive.list(b"ri", &[first, second]);
+        let root = hive.nk("ROOT", (2, root_list), (0, u32::MAX));
+
+        hive.finish(root)
+    }
+
+    fn names<'a>(keys: impl Iterator<Item = Result<OfflineKey<'a>, Error>>) -> Vec<String> {
+        keys.map(|x| x.unwrap().name().to_string_lossy()).collect()
+    }
+
+    #[test]
+    fn read_base_block() {
+        let hive = OfflineHive::from_bytes(sample_hive()).unwrap();
+        let base = hive.base_block();
+        assert_eq!((base.major_version, base.minor_version), (1, 5));
+        assert!(!base.is_dirty());
+        assert!(hive.is_checksum_valid());
+
+        let mut data = sample_hive();
+        data[4] ^= 1;
+        assert!(!OfflineHive::from_bytes(data).unwrap().is_checksum_valid());
+    }
+
+    #[test]
+    fn reject_invalid_files() {
+        assert!(matches!(
+            OfflineHive::from_bytes(vec![0; 4096]),
+            Err(Error::InvalidSignature)
+        ));
+        assert!(matches!(
+            OfflineHive::from_bytes(b"regf".to_vec()),
+            Err(Error::Truncated(4))
+        ));
+    }
+
+    #[test]
+    fn iterate_keys() {
+        let hive = OfflineHive::from_bytes(sample_hive()).unwrap();
+        let root = hive.root().unwrap();
+        assert_eq!(root.name().to_string_lossy(), "ROOT");
+        assert_eq!(names(root.keys()), vec!["Software", "System"]);
+
+        let software = root.open("software").unwrap();
+        assert_eq!(names(software.keys()), vec!["Ключ", "Vëndor"]);
+    }
+
+    #[test]
+    fn reject_nested_index_roots() {
+        let mut hive = TestHive::new();
+        let looped = hive.bins.len() as u32;
+        assert_eq!(hive.list(b"ri", &[looped]), looped);
+        let root = hive.nk("ROOT", (1, looped), (0, u32::MAX));
+        let hive = OfflineHive::from_bytes(hive.finish(root)).unwrap();
+
+        let root = hive.root().unwrap();
+        let keys = root.keys().take(5).collect::<Vec<_>>();
+        assert!(matches!(keys[..], [Err(Error::InvalidCell(x))] if x == looped));
+        assert!(root.open("a").is_err());
+        assert!(root.to_tree().is_err());
+    }
+
+    #[test]
+    fn reject_cyclic_trees() {
+        let mut hive = TestHive::new();
+        // The root's subkey list holds the root itself, and a child whose list points back up.
+        let root = hive.nk("ROOT", (2, u32::MAX), (0, u32::MAX));
+        let child_list = hive.list(b"li", &[root]);
+        let child = hive.nk("Child", (1, child_list), (0, u32::MAX));
+        let list = hive.list(b"li", &[root, child]);
+        let field = root as usize + 32;
+        hive.bins[field..field + 4].copy_from_slice(&list.to_le_bytes());
+        let hive = OfflineHive::from_bytes(hive.finish(root)).unwrap();
+
+        let root = hive.root().unwrap();
+        assert!(matches!(root.to_tree(), Err(Error::InvalidCell(x)) if x == root.offset));
+        let child = root.open("Child").unwrap();
+        assert!(matches!(child.to_tree(), Err(Error::InvalidCell(x)) if x == root.offset));
+    }
+
+    #[test]
+    fn out_of_range_last_written() {
+        let mut hive = TestHive::new();
+        let root = hive.nk("ROOT", (0, u32::MAX), (0, u32::MAX));
+        let mut bytes = hive.finish(root);
+        let field = BASE_BLOCK_SIZE + root as usize + 8;
+        bytes[field..field + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+
+        let hive = OfflineHive::from_bytes(bytes).unwrap();
+        let root = hive.root().unwrap();
+        let last_written = root.info().unwrap().last_written;
+        assert_eq!(root.to_tree().unwrap().last_written, last_written);
+    }
+
+    #[test]
+    fn open_nested_keys() {
+        let hive = OfflineHive::from_bytes(sample_hive()).unwrap();
+        let key = hive.open_key(r"Software\VËNDOR").unwrap();
+        assert_eq!(key.to_string(), "Vëndor");
+        assert!(matches!(
+            hive.open_key(r"Software\Missing"),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn read_values() {
+        let hive = OfflineHive::from_bytes(sample_hive()).unwrap();
+        let key = hive.open_key(r"Software\Vëndor").unwrap();
+
+        let values = key
+            .values()
+            .map(|x| x.map(|x| (x.name().to_string_lossy(), x.into_data())))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            values,
+            vec![
+                ("Version".to_string(), Data::U32(42)),
+                ("".to_string(), Data::String("hello".try_into().unwrap())),
+                (
+                    "Multi".to_string(),
+                    Data::MultiString(vec!["a".try_into().unwrap(), "bc".try_into().unwrap()])
+                ),
+            ]
+        );
+        assert_eq!(key.value("version").unwrap(), Data::U32(42));
+        assert!(matches!(key.value("Missing"), Err(Error::NotFound(_))));
+    }
+
+    #[test]
+    fn invalid_cell_offsets() {
+        let mut data = sample_hive();
+        // Point the root cell past the end of the hive bins.
+        data[36..40].copy_from_slice(&0x10_0000u32.to_le_bytes());
+        let hive = OfflineHive::from_bytes(data).unwrap();
+        assert!(matches!(hive.root(), Err(Error::InvalidCell(0x10_0000))));
+    }
+
+    #[test]
+    fn borrowed_values() {
+        let hive = OfflineHive::from_bytes(sample_hive()).unwrap();
+        let key = hive.open_key(r"Software\Vëndor").unwrap();
+        assert_eq!(key.name().len(), 6);
+        assert!(key
+            .name()
+            .eq_ignore_case(&"VËNDOR".encode_utf16().collect::<Vec<_>>()));
+
+        let values = key.raw_values().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(values[0].name().to_string_lossy(), "Version");
+        assert_eq!(values[0].data_type(), 4);
+        assert_eq!(values[0].raw_data(), &42u32.to_le_bytes());
+        assert!(values[1].name().is_empty());
+        assert_eq!(
+            values[2].to_data().unwrap(),
+            Data::MultiString(vec!["a".try_into().unwrap(), "bc".try_into().unwrap()])
+        );
+    }
+
+    #[test]
+    fn open_mapped() {
+        let path = std::env::temp_dir().join(format!("registry-mapped-{}", std::process::id()));
+        std::fs::write(&path, sample_hive()).unwrap();
+
+        let mut hive = unsafe { OfflineHive::open_mapped(&path) }.unwrap();
+        assert!(matches!(hive.data, Storage::Mapped(_)));
+        assert_eq!(
+            hive.open_key(r"Software\Vëndor")
+                .unwrap()
+                .value("Version")
+                .unwrap(),
+            Data::U32(42)
+        );
+
+        // Writing copies the hive out of the mapping rather than changing the file.
+        hive.data[4] ^= 1;
+        assert!(matches!(hive.data, Storage::Owned(_)));
+        drop(hive);
+        assert_eq!(std::fs::read(&path).unwrap(), sample_hive());
+        std::fs::remove_file(path).unwrap();
+    }
+}
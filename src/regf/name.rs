@@ -0,0 +1,89 @@
+use std::fmt::{Debug, Display};
+
+use utfx::U16CString;
+
+use super::Error;
+use crate::util::units_eq_ignore_case;
+
+/// A key or value name, borrowed from the hive and decoded only when it is used.
+///
+/// Names are stored either as Latin-1, one byte per character, or as UTF-16LE.
+#[derive(Clone, Copy)]
+pub struct NameRef<'a> {
+    raw: &'a [u8],
+    is_compressed: bool,
+}
+
+impl<'a> NameRef<'a> {
+    #[inline]
+    pub(crate) fn new(raw: &'a [u8], is_compressed: bool) -> NameRef<'a> {
+        NameRef { raw, is_compressed }
+    }
+
+    /// The length of the name in UTF-16 code units.
+    #[inline]
+    pub fn len(&self) -> usize {
+        match self.is_compressed {
+            true => self.raw.len(),
+            false => self.raw.len() / 2,
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The UTF-16 code units of the name.
+    pub fn code_units(&self) -> impl Iterator<Item = u16> + 'a {
+        let (raw, is_compressed) = (self.raw, self.is_compressed);
+        let step = if is_compressed { 1 } else { 2 };
+        raw.chunks_exact(step).map(move |x| match is_compressed {
+            true => u16::from(x[0]),
+            false => u16::from_le_bytes([x[0], x[1]]),
+        })
+    }
+
+    #[inline]
+    pub fn to_vec(&self) -> Vec<u16> {
+        self.code_units().collect()
+    }
+
+    #[inline]
+    pub fn to_ucstring(&self) -> Result<U16CString, Error> {
+        Ok(U16CString::new(self.to_vec())?)
+    }
+
+    #[inline]
+    pub fn to_string_lossy(&self) -> String {
+        std::char::decode_utf16(self.code_units())
+            .map(|c| c.unwrap_or(std::char::REPLACEMENT_CHARACTER))
+            .collect()
+    }
+
+    /// Compares the name with `other` the way the registry does, ignoring case.
+    #[inline]
+    pub fn eq_ignore_case(&self, other: &[u16]) -> bool {
+        units_eq_ignore_case(self.code_units(), other)
+    }
+}
+
+impl PartialEq for NameRef<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.code_units().eq(other.code_units())
+    }
+}
+
+impl Eq for NameRef<'_> {}
+
+impl Display for NameRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.to_string_lossy())
+    }
+}
+
+impl Debug for NameRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&self.to_string_lossy(), f)
+    }
+}
@@ -0,0 +1,312 @@
+use std::{collections::HashSet, time::SystemTime};
+
+use utfx::U16CString;
+
+use super::cell::{le_u32, KeyNode, ValueKey, DATA_IN_OFFSET, MAX_DEPTH};
+use super::OfflineHive;
+use crate::util::filetime_to_system_time;
+use crate::value::{parse_value_type_data_with, Data, ParseMode};
+
+const HBIN_HEADER_LEN: usize = 32;
+
+/// A record found in the unallocated space of a hive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Recovered {
+    Key(RecoveredKey),
+    /// A value that does not belong to any recovered key, such as one deleted from a key that
+    /// still exists.
+    Value(RecoveredValue),
+}
+
+/// A deleted key, rebuilt from a key node in a free cell.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecoveredKey {
+    /// The offset of the key node, relative to the start of the hive bins.
+    pub offset: u32,
+    pub name: U16CString,
+    /// The path of the key relative to the root of the hive, as far as its parents could be
+    /// followed.
+    pub path: U16CString,
+    /// Whether every parent up to the root key could be found, so that `path` is complete.
+    pub is_path_complete: bool,
+    pub last_written: SystemTime,
+    /// The values of the key whose records are still intact.
+    pub values: Vec<RecoveredValue>,
+}
+
+/// A deleted value, rebuilt from a value key in a free cell.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecoveredValue {
+    /// The offset of the value key, relative to the start of the hive bins.
+    pub offset: u32,
+    pub name: U16CString,
+    pub data_type: u32,
+    /// The decoded data, or `None` if its cell has since been reused or cannot be decoded.
+    pub data: Option<Data>,
+}
+
+/// An iterator over the deleted keys and values of an [`OfflineHive`](struct.OfflineHive.html).
+#[derive(Debug)]
+pub struct Deleted {
+    items: std::vec::IntoIter<Recovered>,
+}
+
+impl Iterator for Deleted {
+    type Item = Recovered;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.items.next()
+    }
+}
+
+impl OfflineHive {
+    /// Scans the free cells of the hive for key and value records left behind by deletions.
+    ///
+    /// Freed cells keep their contents until they are reused, so this finds most recently
+    /// deleted keys and values. Free cells that have been merged are searched throughout, since
+    /// they can hold several old records. The scan is best effort: anything that does not parse
+    /// is skipped.
+    pub fn deleted(&self) -> Deleted {
+        let mut keys = vec![];
+        let mut values = vec![];
+
+        let free = self.free_cells();
+        for (start, end) in free.iter().copied() {
+            for offset in (start..end).step_by(8) {
+                match self.bins().get(offset + 4..offset + 6) {
+                    Some(b"nk") => keys.extend(self.recover_key(&free, offset, end)),
+                    Some(b"vk") => values.extend(self.recover_value(&free, offset, end)),
+                    _ => {}
+                }
+            }
+        }
+
+        let owned = keys
+            .iter()
+            .flat_map(|x: &RecoveredKey| x.values.iter().map(|x| x.offset))
+            .collect::<HashSet<_>>();
+        let items = keys
+            .into_iter()
+            .map(Recovered::Key)
+            .chain(
+                values
+                    .into_iter()
+                    .filter(|x| !owned.contains(&x.offset))
+                    .map(Recovered::Value),
+            )
+            .collect::<Vec<_>>();
+
+        Deleted {
+            items: items.into_iter(),
+        }
+    }
+
+    /// The start and end of every free cell, stopping at the first malformed hive bin.
+    fn free_cells(&self) -> Vec<(usize, usize)> {
+        let bins = self.bins();
+        let mut cells = vec![];
+        let mut bin = 0;
+
+        while bin + HBIN_HEADER_LEN <= bins.len() && &bins[bin..bin + 4] == b"hbin" {
+            let bin_end = bin + le_u32(bins, bin + 8) as usize;
+            if bin_end <= bin || bin_end > bins.len() {
+                break;
+            }
+
+            let mut offset = bin + HBIN_HEADER_LEN;
+            while offset + 4 <= bin_end {
+                let size = le_u32(bins, offset) as i32;
+                let len = size.unsigned_abs() as usize;
+                if len < 8 || offset + len > bin_end {
+                    break;
+                }
+                if size > 0 {
+                    cells.push((offset, offset + len));
+                }
+                offset += len;
+            }
+
+            bin = bin_end;
+        }
+
+        cells
+    }
+
+    /// The contents of an old cell inside a free cell ending at `end`, going by its stale size.
+    fn stale_cell(&self, offset: usize, end: usize) -> Option<&[u8]> {
+        let bins = self.bins();
+        let size = (le_u32(bins, offset) as i32).unsigned_abs() as usize;
+        bins.get(offset + 4..(offset + size).min(end))
+    }
+
+    fn recover_key(
+        &self,
+        free: &[(usize, usize)],
+        offset: usize,
+        end: usize,
+    ) -> Option<RecoveredKey> {
+        let node = KeyNode::parse(offset as u32, self.stale_cell(offset, end)?).ok()?;
+        let name = U16CString::new(node.name()).ok()?;
+
+        let mut path = name.as_slice().to_vec();
+        let mut parent = node.parent;
+        let mut is_path_complete = false;
+        for _ in 0..MAX_DEPTH {
+            if parent == self.base.root_cell {
+                is_path_complete = true;
+                break;
+            }
+            let node = match self.cell(parent).and_then(|x| KeyNode::parse(parent, x)) {
+                Ok(v) => v,
+                Err(_) => break,
+            };
+            path = [node.name(), vec![u16::from(b'\\')], path].concat();
+            parent = node.parent;
+        }
+
+        let mut values = vec![];
+        if let Some(list_end) = free_cell_end(free, node.value_list) {
+            let list = self.stale_cell(node.value_list as usize, list_end)?;
+            for entry in list.chunks_exact(4).take(node.value_count as usize) {
+                let value = le_u32(entry, 0);
+                if let Some(value_end) = free_cell_end(free, value) {
+                    values.extend(self.recover_value(free, value as usize, value_end));
+                }
+            }
+        }
+
+        Some(RecoveredKey {
+            offset: offset as u32,
+            name,
+            path: U16CString::new(path).ok()?,
+            is_path_complete,
+            last_written: filetime_to_system_time(node.last_written),
+            values,
+        })
+    }
+
+    fn recover_value(
+        &self,
+        free: &[(usize, usize)],
+        offset: usize,
+        end: usize,
+    ) -> Option<RecoveredValue> {
+        let vk = ValueKey::parse(offset as u32, self.stale_cell(offset, end)?).ok()?;
+        let name = U16CString::new(vk.name()).ok()?;
+
+        let is_intact = vk.data_size & DATA_IN_OFFSET != 0
+            || vk.data_size == 0
+            || free_cell_end(free, vk.data_offset).is_some();
+        let data = match is_intact {
+            true => self.value_data(&vk).ok().and_then(|x| {
+                parse_value_type_data_with(vk.data_type, &x, ParseMode::PreserveRaw).ok()
+            }),
+            false => None,
+        };
+
+        Some(RecoveredValue {
+            offset: offset as u32,
+            name,
+            data_type: vk.data_type,
+            data,
+        })
+    }
+}
+
+/// The end of the free cell that `offset` lies within, if any. Anything outside of a free cell has
+/// been reused since it was deleted.
+fn free_cell_end(free: &[(usize, usize)], offset: u32) -> Option<usize> {
+    let offset = offset as usize;
+    let index = free.partition_point(|(start, _)| *start <= offset);
+    match index.checked_sub(1).map(|i| free[i]) {
+        Some((_, end)) if offset + 4 <= end => Some(end),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    use crate::regf::{HiveEditor, HiveWriter};
+    use crate::tree::KeyTree;
+
+    fn s(x: &str) -> U16CString {
+        x.try_into().unwrap()
+    }
+
+    fn edited_hive() -> OfflineHive {
+        let mut root = KeyTree::new(s("ROOT"));
+        let vendor = root.create(&s(r"Software\Vendor"));
+        vendor.set_value(s("Version"), Data::U32(7));
+        vendor.set_value(s("Path"), Data::String(s(r"C:\Program Files\Vendor")));
+        root.create(&s("System"))
+            .set_value(s("Secret"), Data::Binary(vec![0x55; 64]));
+
+        let mut editor =
+            HiveEditor::from_bytes(HiveWriter::new().to_bytes(&root).unwrap()).unwrap();
+        editor.delete_key(r"Software\Vendor", true).unwrap();
+        editor.delete_value("System", "Secret").unwrap();
+        editor.into_hive().unwrap()
+    }
+
+    #[test]
+    fn recover_deleted_key() {
+        let hive = edited_hive();
+        assert!(hive.open_key(r"Software\Vendor").is_err());
+
+        let keys = hive
+            .deleted()
+            .filter_map(|x| match x {
+                Recovered::Key(key) => Some(key),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(keys.len(), 1);
+
+        let vendor = &keys[0];
+        assert_eq!(vendor.path, s(r"Software\Vendor"));
+        assert!(vendor.is_path_complete);
+        let mut values = vendor
+            .values
+            .iter()
+            .map(|x| (x.name.to_string_lossy(), x.data.clone()))
+            .collect::<Vec<_>>();
+        values.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            values,
+            vec![
+                (
+                    "Path".to_string(),
+                    Some(Data::String(s(r"C:\Program Files\Vendor")))
+                ),
+                ("Version".to_string(), Some(Data::U32(7))),
+            ]
+        );
+    }
+
+    #[test]
+    fn recover_deleted_value() {
+        let hive = edited_hive();
+        let values = hive
+            .deleted()
+            .filter_map(|x| match x {
+                Recovered::Value(value) => Some(value),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(values.len(), 1);
+        assert_eq!(values[0].name, s("Secret"));
+        assert_eq!(values[0].data_type, 3);
+        assert_eq!(values[0].data, Some(Data::Binary(vec![0x55; 64])));
+    }
+
+    #[test]
+    fn nothing_deleted() {
+        let root = KeyTree::new(s("ROOT"));
+        let hive = OfflineHive::from_bytes(HiveWriter::new().to_bytes(&root).unwrap()).unwrap();
+        assert_eq!(hive.deleted().count(), 0);
+    }
+}
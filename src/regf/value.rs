@@ -0,0 +1,252 @@
+use std::{borrow::Cow, fmt::Debug};
+
+use utfx::{U16CStr, U16CString};
+
+use super::cell::{le_u32, BigData, ValueKey, DATA_IN_OFFSET, MAX_DATA_SEGMENT_LEN, NO_CELL};
+use super::{Error, NameRef, OfflineHive, OfflineKey};
+use crate::value::{parse_value_type_data, parse_value_type_data_with, Data, ParseMode};
+
+/// A value of an [`OfflineKey`](struct.OfflineKey.html), with its data decoded.
+pub struct OfflineValue {
+    name: U16CString,
+    data: Data,
+}
+
+impl Debug for OfflineValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("OfflineValue")
+            .field(&self.name.to_string_lossy())
+            .field(&self.data)
+            .finish()
+    }
+}
+
+impl OfflineValue {
+    pub fn name(&self) -> &U16CStr {
+        &self.name
+    }
+
+    pub fn data(&self) -> &Data {
+        &self.data
+    }
+
+    pub fn into_name(self) -> U16CString {
+        self.name
+    }
+
+    pub fn into_data(self) -> Data {
+        self.data
+    }
+
+    pub fn into_inner(self) -> (U16CString, Data) {
+        (self.name, self.data)
+    }
+}
+
+/// A value of an [`OfflineKey`](struct.OfflineKey.html), borrowed from the hive without decoding
+/// its name or data.
+#[derive(Debug, Clone)]
+pub struct ValueRef<'a> {
+    name: NameRef<'a>,
+    data_type: u32,
+    data: Cow<'a, [u8]>,
+}
+
+impl<'a> ValueRef<'a> {
+    #[inline]
+    pub fn name(&self) -> NameRef<'a> {
+        self.name
+    }
+
+    /// The raw type of the value, such as `REG_SZ`.
+    #[inline]
+    pub fn data_type(&self) -> u32 {
+        self.data_type
+    }
+
+    /// The undecoded data. This is only copied out of the hive when it is split into segments.
+    #[inline]
+    pub fn raw_data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Decodes the data in [`ParseMode::Strict`](../value/enum.ParseMode.html#variant.Strict).
+    #[inline]
+    pub fn to_data(&self) -> Result<Data, Error> {
+        Ok(parse_value_type_data(self.data_type, &self.data)?)
+    }
+
+    #[inline]
+    pub fn to_data_with(&self, mode: ParseMode) -> Result<Data, Error> {
+        Ok(parse_value_type_data_with(
+            self.data_type,
+            &self.data,
+            mode,
+        )?)
+    }
+
+    /// Copies the value out of the hive. Data that cannot be decoded exactly is kept as
+    /// [`Data::Other`](../value/enum.Data.html#variant.Other), so it can be written back unchanged.
+    pub fn to_value(&self) -> Result<OfflineValue, Error> {
+        Ok(OfflineValue {
+            name: self.name.to_ucstring()?,
+            data: self.to_data_with(ParseMode::PreserveRaw)?,
+        })
+    }
+}
+
+/// An iterator over the values of an [`OfflineKey`](struct.OfflineKey.html), as borrowed
+/// [`ValueRef`](struct.ValueRef.html)s.
+#[derive(Debug)]
+pub struct RawValues<'a> {
+    hive: &'a OfflineHive,
+    list: u32,
+    count: u32,
+    index: u32,
+    error: Option<Error>,
+}
+
+impl<'a> RawValues<'a> {
+    pub(crate) fn new(key: &OfflineKey<'a>) -> RawValues<'a> {
+        let mut values = RawValues {
+            hive: key.hive,
+            list: NO_CELL,
+            count: 0,
+            index: 0,
+            error: None,
+        };
+
+        match key.node() {
+            Ok(node) if node.value_list != NO_CELL => {
+                values.list = node.value_list;
+                values.count = node.value_count;
+            }
+            Ok(_) => {}
+            Err(e) => values.error = Some(e),
+        }
+
+        values
+    }
+
+    fn read(&self, index: u32) -> Result<ValueRef<'a>, Error> {
+        let list = self.hive.cell(self.list)?;
+        let entry = index as usize * 4;
+        if list.len() < entry + 4 {
+            return Err(Error::InvalidCell(self.list));
+        }
+
+        let offset = le_u32(list, entry);
+        let vk = ValueKey::parse(offset, self.hive.cell(offset)?)?;
+        Ok(ValueRef {
+            name: vk.name_ref(),
+            data_type: vk.data_type,
+            data: self.hive.value_data(&vk)?,
+        })
+    }
+}
+
+impl<'a> Iterator for RawValues<'a> {
+    type Item = Result<ValueRef<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.error.take() {
+            return Some(Err(e));
+        }
+
+        if self.index >= self.count {
+            return None;
+        }
+
+        let result = self.read(self.index);
+        self.index += 1;
+        Some(result)
+    }
+}
+
+/// An iterator over the values of an [`OfflineKey`](struct.OfflineKey.html).
+#[derive(Debug)]
+pub struct Values<'a> {
+    inner: RawValues<'a>,
+}
+
+impl<'a> Values<'a> {
+    #[inline]
+    pub(crate) fn new(key: &OfflineKey<'a>) -> Values<'a> {
+        Values {
+            inner: RawValues::new(key),
+        }
+    }
+}
+
+impl Iterator for Values<'_> {
+    type Item = Result<OfflineValue, Error>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|x| x.and_then(|x| x.to_value()))
+    }
+}
+
+impl OfflineHive {
+    /// Reads the raw data of a value, which is either inline in the record, in its own cell, or
+    /// split into segments.
+    pub(crate) fn value_data<'a>(&'a self, vk: &ValueKey<'a>) -> Result<Cow<'a, [u8]>, Error> {
+        if vk.data_size & DATA_IN_OFFSET != 0 {
+            let len = (vk.data_size & !DATA_IN_OFFSET).min(4) as usize;
+            return Ok(Cow::Borrowed(&vk.inline_data[..len]));
+        }
+
+        if vk.data_size == 0 {
+            return Ok(Cow::Borrowed(&[]));
+        }
+
+        if self.is_big_data(vk) {
+            // The size comes from the hive, so it is only trusted as far as the segments can hold.
+            let size = vk.data_size as usize;
+            let segments = self.data_segments(vk.data_offset)?;
+            if segments.len().saturating_mul(MAX_DATA_SEGMENT_LEN) < size {
+                return Err(Error::InvalidCell(vk.data_offset));
+            }
+
+            let mut data = Vec::with_capacity(size);
+            for segment in segments {
+                let cell = self.cell(segment)?;
+                let len = (size - data.len())
+                    .min(MAX_DATA_SEGMENT_LEN)
+                    .min(cell.len());
+                data.extend_from_slice(&cell[..len]);
+            }
+
+            if data.len() < size {
+                return Err(Error::InvalidCell(vk.data_offset));
+            }
+            return Ok(Cow::Owned(data));
+        }
+
+        self.cell(vk.data_offset)?
+            .get(..vk.data_size as usize)
+            .map(Cow::Borrowed)
+            .ok_or(Error::InvalidCell(vk.data_offset))
+    }
+
+    /// Whether the data of a value is split into segments behind a big data record.
+    pub(crate) fn is_big_data(&self, vk: &ValueKey<'_>) -> bool {
+        self.base.minor_version >= 4
+            && vk.data_size & DATA_IN_OFFSET == 0
+            && vk.data_size as usize > MAX_DATA_SEGMENT_LEN
+            && self
+                .cell(vk.data_offset)
+                .map(|x| x.starts_with(b"db"))
+                .unwrap_or(false)
+    }
+
+    /// The offsets of the segments listed by the big data record at `offset`.
+    pub(crate) fn data_segments(&self, offset: u32) -> Result<Vec<u32>, Error> {
+        let db = BigData::parse(offset, self.cell(offset)?)?;
+        let list = self
+            .cell(db.segment_list)?
+            .get(..db.segment_count as usize * 4)
+            .ok_or(Error::InvalidCell(db.segment_list))?;
+        Ok(list.chunks_exact(4).map(|x| le_u32(x, 0)).collect())
+    }
+}
@@ -0,0 +1,751 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Display,
+};
+
+use super::base::{checksum, BASE_BLOCK_SIZE};
+use super::cell::{
+    le_u32, name_hash, name_hint, BigData, KeyNode, SubkeyList, ValueKey, DATA_IN_OFFSET,
+    MAX_DATA_SEGMENT_LEN, NO_CELL,
+};
+use super::OfflineHive;
+use crate::util::cmp_ignore_case;
+
+const HBIN_HEADER_LEN: usize = 32;
+
+/// The size that hive bins are allocated in multiples of.
+const HBIN_ALIGN: u32 = 4096;
+
+/// A problem found by [`OfflineHive::verify`](struct.OfflineHive.html#method.verify).
+///
+/// Offsets are relative to the start of the hive bins, as they are in the hive itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Finding {
+    /// The checksum stored in the base block does not match its contents.
+    BadChecksum { stored: u32, computed: u32 },
+    /// The sequence numbers in the base block differ, so the last write did not complete.
+    Dirty { primary: u32, secondary: u32 },
+    /// The base block claims more hive bins than the file holds.
+    Truncated { hive_bins_size: u32, actual: u32 },
+    /// A hive bin does not start with `hbin`. Nothing after it can be checked.
+    BadBinSignature { offset: u32 },
+    /// A hive bin records an offset other than its own.
+    BadBinOffset { offset: u32, stored: u32 },
+    /// A hive bin has a size that is not a multiple of 4096 or runs past the end of the hive.
+    BadBinSize { offset: u32, size: u32 },
+    /// A cell is too small, misaligned, or runs past the end of its hive bin. The rest of the
+    /// hive bin is skipped.
+    BadCellSize { offset: u32, size: i32 },
+    /// A record refers to something that is not the start of a cell.
+    DanglingReference { offset: u32, from: u32 },
+    /// A record refers to a cell that has been freed.
+    FreeCellReferenced { offset: u32, from: u32 },
+    /// A cell does not hold the kind of record it is referenced as.
+    BadRecord { offset: u32, expected: &'static str },
+    /// An allocated cell that nothing refers to.
+    LeakedCell { offset: u32 },
+    /// A key is reachable through more than one subkey list.
+    DuplicateKey { offset: u32 },
+    /// A key's parent field does not point at the key it is listed under.
+    BadParent {
+        offset: u32,
+        stored: u32,
+        expected: u32,
+    },
+    /// A key's subkey count differs from the number of entries in its subkey lists.
+    SubkeyCountMismatch {
+        offset: u32,
+        stored: u32,
+        actual: u32,
+    },
+    /// The entries of a subkey list are not in ascending order by name.
+    UnsortedSubkeys { list: u32, index: u32 },
+    /// The hint or hash stored for an entry of a subkey list does not match the key's name.
+    BadNameHash { list: u32, index: u32 },
+    /// A key's value list cell is too small to hold its value count.
+    ValueListOutOfBounds { offset: u32, count: u32 },
+    /// A value's data cell is smaller than its data size.
+    DataOutOfBounds { offset: u32, size: u32 },
+    /// The reference count of a security cell differs from the number of keys using it.
+    SecurityRefCountMismatch {
+        offset: u32,
+        stored: u32,
+        actual: u32,
+    },
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Finding::BadChecksum { stored, computed } => write!(
+                f,
+                "Base block checksum is {:#x}, expected {:#x}",
+                stored, computed
+            ),
+            Finding::Dirty { primary, secondary } => write!(
+                f,
+                "Hive is dirty: sequence numbers {} and {}",
+                primary, secondary
+            ),
+            Finding::Truncated {
+                hive_bins_size,
+                actual,
+            } => write!(
+                f,
+                "Hive bins are {} bytes, but only {} are present",
+                hive_bins_size, actual
+            ),
+            Finding::BadBinSignature { offset } => {
+                write!(f, "Invalid hive bin signature at {:#x}", offset)
+            }
+            Finding::BadBinOffset { offset, stored } => write!(
+                f,
+                "Hive bin at {:#x} records its offset as {:#x}",
+                offset, stored
+            ),
+            Finding::BadBinSize { offset, size } => {
+                write!(f, "Hive bin at {:#x} has invalid size {}", offset, size)
+            }
+            Finding::BadCellSize { offset, size } => {
+                write!(f, "Cell at {:#x} has invalid size {}", offset, size)
+            }
+            Finding::DanglingReference { offset, from } => write!(
+                f,
+                "Cell at {:#x} refers to {:#x}, which is not a cell",
+                from, offset
+            ),
+            Finding::FreeCellReferenced { offset, from } => {
+                write!(f, "Cell at {:#x} refers to free cell {:#x}", from, offset)
+            }
+            Finding::BadRecord { offset, expected } => {
+                write!(
+                    f,
+                    "Cell at {:#x} is not a valid {} record",
+                    offset, expected
+                )
+            }
+            Finding::LeakedCell { offset } => {
+                write!(f, "Cell at {:#x} is allocated but unused", offset)
+            }
+            Finding::DuplicateKey { offset } => {
+                write!(f, "Key at {:#x} is listed more than once", offset)
+            }
+            Finding::BadParent {
+                offset,
+                stored,
+                expected,
+            } => write!(
+                f,
+                "Key at {:#x} has parent {:#x}, expected {:#x}",
+                offset, stored, expected
+            ),
+            Finding::SubkeyCountMismatch {
+                offset,
+                stored,
+                actual,
+            } => write!(
+                f,
+                "Key at {:#x} records {} subkeys, but lists {}",
+                offset, stored, actual
+            ),
+            Finding::UnsortedSubkeys { list, index } => write!(
+                f,
+                "Subkey list at {:#x} is out of order at entry {}",
+                list, index
+            ),
+            Finding::BadNameHash { list, index } => write!(
+                f,
+                "Subkey list at {:#x} has the wrong hash for entry {}",
+                list, index
+            ),
+            Finding::ValueListOutOfBounds { offset, count } => write!(
+                f,
+                "Value list of key at {:#x} cannot hold {} values",
+                offset, count
+            ),
+            Finding::DataOutOfBounds { offset, size } => write!(
+                f,
+                "Data of value at {:#x} is shorter than {} bytes",
+                offset, size
+            ),
+            Finding::SecurityRefCountMismatch {
+                offset,
+                stored,
+                actual,
+            } => write!(
+                f,
+                "Security cell at {:#x} has reference count {}, but is used by {} keys",
+                offset, stored, actual
+            ),
+        }
+    }
+}
+
+impl OfflineHive {
+    /// Checks the structure of the hive, returning everything that is wrong with it rather than
+    /// stopping at the first problem. An empty list means the hive is consistent.
+    pub fn verify(&self) -> Vec<Finding> {
+        let mut verifier = Verifier {
+            hive: self,
+            cells: BTreeMap::new(),
+            used: HashSet::new(),
+            security: HashMap::new(),
+            findings: vec![],
+        };
+
+        verifier.check_base_block();
+        verifier.check_bins();
+        verifier.check_key(self.base.root_cell, NO_CELL, NO_CELL);
+        verifier.check_security();
+        verifier.check_leaks();
+        verifier.findings
+    }
+}
+
+struct Verifier<'a> {
+    hive: &'a OfflineHive,
+    /// Every cell, with whether it is allocated.
+    cells: BTreeMap<u32, bool>,
+    /// The cells that are referred to by something.
+    used: HashSet<u32>,
+    /// The number of keys using each security cell.
+    security: HashMap<u32, u32>,
+    findings: Vec<Finding>,
+}
+
+impl Verifier<'_> {
+    fn check_base_block(&mut self) {
+        let base = &self.hive.base;
+        let computed = checksum(&self.hive.data);
+        if computed != base.checksum {
+            self.findings.push(Finding::BadChecksum {
+                stored: base.checksum,
+                computed,
+            });
+        }
+
+        if base.is_dirty() {
+            self.findings.push(Finding::Dirty {
+                primary: base.primary_sequence,
+                secondary: base.secondary_sequence,
+            });
+        }
+
+        let actual = (self.hive.data.len() - BASE_BLOCK_SIZE) as u32;
+        if actual < base.hive_bins_size {
+            self.findings.push(Finding::Truncated {
+                hive_bins_size: base.hive_bins_size,
+                actual,
+            });
+        }
+    }
+
+    fn check_bins(&mut self) {
+        let bins = self.hive.bins();
+        let mut bin = 0;
+
+        while bin + HBIN_HEADER_LEN <= bins.len() {
+            let offset = bin as u32;
+            if &bins[bin..bin + 4] != b"hbin" {
+                self.findings.push(Finding::BadBinSignature { offset });
+                return;
+            }
+
+            let stored = le_u32(bins, bin + 4);
+            if stored != offset {
+                self.findings.push(Finding::BadBinOffset { offset, stored });
+            }
+
+            let size = le_u32(bins, bin + 8);
+            if size == 0 || !size.is_multiple_of(HBIN_ALIGN) || bin + size as usize > bins.len() {
+                self.findings.push(Finding::BadBinSize { offset, size });
+                return;
+            }
+
+            let bin_end = bin + size as usize;
+            let mut cell = bin + HBIN_HEADER_LEN;
+            while cell < bin_end {
+                let size = if cell + 4 <= bin_end {
+                    le_u32(bins, cell) as i32
+                } else {
+                    0
+                };
+                let len = size.unsigned_abs() as usize;
+                if len < 8 || !len.is_multiple_of(8) || cell + len > bin_end {
+                    self.findings.push(Finding::BadCellSize {
+                        offset: cell as u32,
+                        size,
+                    });
+                    break;
+                }
+
+                self.cells.insert(cell as u32, size < 0);
+                cell += len;
+            }
+
+            bin = bin_end;
+        }
+    }
+
+    /// Marks a reference from the record at `from` to the cell at `offset`, returning the
+    /// cell's contents if it is allocated.
+    fn reference(&mut self, offset: u32, from: u32) -> Option<&[u8]> {
+        match self.cells.get(&offset) {
+            Some(true) => {
+                self.used.insert(offset);
+                self.hive.cell(offset).ok()
+            }
+            Some(false) => {
+                self.findings
+                    .push(Finding::FreeCellReferenced { offset, from });
+                None
+            }
+            None => {
+                self.findings
+                    .push(Finding::DanglingReference { offset, from });
+                None
+            }
+        }
+    }
+
+    fn check_key(&mut self, offset: u32, parent: u32, from: u32) {
+        if self.used.contains(&offset) {
+            self.findings.push(Finding::DuplicateKey { offset });
+            return;
+        }
+
+        let hive = self.hive;
+        let node = match self
+            .reference(offset, from)
+            .map(|_| hive.cell(offset).and_then(|x| KeyNode::parse(offset, x)))
+        {
+            Some(Ok(v)) => v,
+            Some(Err(_)) => {
+                self.findings.push(Finding::BadRecord {
+                    offset,
+                    expected: "key node",
+                });
+                return;
+            }
+            None => return,
+        };
+
+        if parent != NO_CELL && node.parent != parent {
+            self.findings.push(Finding::BadParent {
+                offset,
+                stored: node.parent,
+                expected: parent,
+            });
+        }
+
+        if node.class_name != NO_CELL {
+            self.reference(node.class_name, offset);
+        }
+
+        if node.security != NO_CELL && self.reference(node.security, offset).is_some() {
+            *self.security.entry(node.security).or_insert(0) += 1;
+        }
+
+        if node.value_count > 0 {
+            self.check_values(offset, node.value_list, node.value_count);
+        }
+
+        let mut subkeys = vec![];
+        if node.subkey_list != NO_CELL {
+            self.check_subkey_list(node.subkey_list, offset, true, &mut subkeys);
+        }
+        if subkeys.len() as u32 != node.subkey_count {
+            self.findings.push(Finding::SubkeyCountMismatch {
+                offset,
+                stored: node.subkey_count,
+                actual: subkeys.len() as u32,
+            });
+        }
+
+        for subkey in subkeys {
+            self.check_key(subkey, offset, node.subkey_list);
+        }
+    }
+
+    /// Checks a subkey list and collects the offsets of the keys in it.
+    fn check_subkey_list(&mut self, list: u32, key: u32, is_top: bool, subkeys: &mut Vec<u32>) {
+        let hive = self.hive;
+        let parsed = match self
+            .reference(list, key)
+            .map(|_| hive.cell(list).and_then(|x| SubkeyList::parse(list, x)))
+        {
+            Some(Ok(v)) if is_top || !v.is_root => v,
+            Some(_) => {
+                self.findings.push(Finding::BadRecord {
+                    offset: list,
+                    expected: "subkey list",
+                });
+                return;
+            }
+            None => return,
+        };
+
+        if parsed.is_root {
+            for i in 0..parsed.len() {
+                self.check_subkey_list(parsed.get(i), list, false, subkeys);
+            }
+            return;
+        }
+
+        let mut previous: Option<Vec<u16>> = None;
+        for i in 0..parsed.len() {
+            let offset = parsed.get(i);
+            let name = match hive.cell(offset).and_then(|x| KeyNode::parse(offset, x)) {
+                Ok(v) => v.name(),
+                Err(_) => {
+                    subkeys.push(offset);
+                    continue;
+                }
+            };
+
+            let is_hash_valid = match (&parsed.signature, parsed.hash(i)) {
+                (b"lf", Some(hash)) => hash == u32::from_le_bytes(name_hint(&name)),
+                (b"lh", Some(hash)) => hash == name_hash(&name),
+                _ => true,
+            };
+            if !is_hash_valid {
+                self.findings.push(Finding::BadNameHash {
+                    list,
+                    index: i as u32,
+                });
+            }
+
+            if let Some(previous) = &previous {
+                if cmp_ignore_case(previous, &name) != Ordering::Less {
+                    self.findings.push(Finding::UnsortedSubkeys {
+                        list,
+                        index: i as u32,
+                    });
+                }
+            }
+            previous = Some(name);
+            subkeys.push(offset);
+        }
+    }
+
+    fn check_values(&mut self, key: u32, list: u32, count: u32) {
+        let offsets = match self.reference(list, key) {
+            Some(v) if v.len() >= count as usize * 4 => v[..count as usize * 4]
+                .chunks_exact(4)
+                .map(|x| le_u32(x, 0))
+                .collect::<Vec<_>>(),
+            Some(_) => {
+                self.findings
+                    .push(Finding::ValueListOutOfBounds { offset: key, count });
+                return;
+            }
+            None => return,
+        };
+
+        let hive = self.hive;
+        for offset in offsets {
+            let vk = match self
+                .reference(offset, list)
+                .map(|_| hive.cell(offset).and_then(|x| ValueKey::parse(offset, x)))
+            {
+                Some(Ok(v)) => v,
+                Some(Err(_)) => {
+                    self.findings.push(Finding::BadRecord {
+                        offset,
+                        expected: "value key",
+                    });
+                    continue;
+                }
+                None => continue,
+            };
+
+            if vk.data_size & DATA_IN_OFFSET != 0 || vk.data_size == 0 {
+                continue;
+            }
+            if hive.is_big_data(&vk) {
+                self.check_big_data(offset, vk.data_offset, vk.data_size);
+                continue;
+            }
+            if let Some(data) = self.reference(vk.data_offset, offset) {
+                if data.len() < vk.data_size as usize {
+                    self.findings.push(Finding::DataOutOfBounds {
+                        offset,
+                        size: vk.data_size,
+                    });
+                }
+            }
+        }
+    }
+
+    fn check_big_data(&mut self, value: u32, offset: u32, size: u32) {
+        let hive = self.hive;
+        let db = match self
+            .reference(offset, value)
+            .map(|x| BigData::parse(offset, x))
+        {
+            Some(Ok(v)) => v,
+            Some(Err(_)) => {
+                self.findings.push(Finding::BadRecord {
+                    offset,
+                    expected: "big data",
+                });
+                return;
+            }
+            None => return,
+        };
+
+        let count = db.segment_count as usize;
+        let segments = match self.reference(db.segment_list, offset) {
+            Some(v) if v.len() >= count * 4 => v[..count * 4]
+                .chunks_exact(4)
+                .map(|x| le_u32(x, 0))
+                .collect::<Vec<_>>(),
+            Some(_) => {
+                self.findings.push(Finding::BadRecord {
+                    offset: db.segment_list,
+                    expected: "data segment list",
+                });
+                return;
+            }
+            None => return,
+        };
+
+        let mut len = 0;
+        for segment in segments {
+            if self.reference(segment, db.segment_list).is_some() {
+                let cell = hive.cell(segment).map(|x| x.len()).unwrap_or(0);
+                len += cell.min(MAX_DATA_SEGMENT_LEN);
+            }
+        }
+        if len < size as usize {
+            self.findings.push(Finding::DataOutOfBounds {
+                offset: value,
+                size,
+            });
+        }
+    }
+
+    /// Compares the reference counts of security cells with the keys that use them, and follows
+    /// the list that links them together so that unused ones are not reported as leaks.
+    fn check_security(&mut self) {
+        let mut security = self.security.drain().collect::<Vec<_>>();
+        security.sort_unstable();
+
+        for (offset, actual) in &security {
+            let cell = self.hive.cell(*offset).ok().filter(|x| &x[0..2] == b"sk");
+            let stored = match cell {
+                Some(v) if v.len() >= 20 => le_u32(v, 12),
+                _ => {
+                    self.findings.push(Finding::BadRecord {
+                        offset: *offset,
+                        expected: "security key",
+                    });
+                    continue;
+                }
+            };
+
+            if stored != *actual {
+                self.findings.push(Finding::SecurityRefCountMismatch {
+                    offset: *offset,
+                    stored,
+                    actual: *actual,
+                });
+            }
+        }
+
+        let mut next = match security.first() {
+            Some((offset, _)) => *offset,
+            None => return,
+        };
+        loop {
+            let from = next;
+            next = match self.hive.cell(from) {
+                Ok(v) if v.len() >= 20 && &v[0..2] == b"sk" => le_u32(v, 4),
+                _ => return,
+            };
+            if self.used.contains(&next) {
+                return;
+            }
+            if self.reference(next, from).is_none() {
+                return;
+            }
+        }
+    }
+
+    fn check_leaks(&mut self) {
+        let leaked = self
+            .cells
+            .iter()
+            .filter(|(offset, is_allocated)| **is_allocated && !self.used.contains(offset))
+            .map(|(offset, _)| Finding::LeakedCell { offset: *offset })
+            .collect::<Vec<_>>();
+        self.findings.extend(leaked);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    use crate::regf::base::BASE_BLOCK_SIZE;
+    use crate::regf::tests::sample_hive;
+    use crate::regf::{HiveEditor, HiveWriter};
+    use crate::tree::KeyTree;
+    use crate::value::Data;
+
+    fn s(x: &str) -> utfx::U16CString {
+        x.try_into().unwrap()
+    }
+
+    fn written_hive() -> Vec<u8> {
+        let mut root = KeyTree::new(s("ROOT"));
+        for name in &["b", "c", "a"] {
+            let key = root.create(&s(&format!(r"Software\{}", name)));
+            key.set_value(s("Data"), Data::Binary(vec![1; 100]));
+            key.class_name = Some(s("Class"));
+        }
+        HiveWriter::new().to_bytes(&root).unwrap()
+    }
+
+    /// The offset of the subkey list of `Software`, in the file.
+    fn software_list(bytes: &[u8]) -> usize {
+        let hive = OfflineHive::from_bytes(bytes.to_vec()).unwrap();
+        let list = hive
+            .open_key("Software")
+            .unwrap()
+            .node()
+            .unwrap()
+            .subkey_list;
+        BASE_BLOCK_SIZE + list as usize + 4
+    }
+
+    #[test]
+    fn consistent_hives() {
+        let hive = OfflineHive::from_bytes(written_hive()).unwrap();
+        assert_eq!(hive.verify(), vec![]);
+
+        let mut editor = HiveEditor::from_bytes(written_hive()).unwrap();
+        editor.delete_key(r"Software\b", false).unwrap();
+        editor.create_key(r"Software\d\e").unwrap();
+        editor
+            .rename_value(r"Software\a", "Data", "Renamed")
+            .unwrap();
+        assert_eq!(editor.into_hive().unwrap().verify(), vec![]);
+    }
+
+    #[test]
+    fn hand_built_hive() {
+        // The hand-built hive leaves hashes and parents empty, and lists "Ключ" before "Vëndor".
+        let hive = OfflineHive::from_bytes(sample_hive()).unwrap();
+        let findings = hive.verify();
+        assert_eq!(findings.len(), 8);
+        assert!(findings.iter().all(|x| matches!(
+            x,
+            Finding::BadNameHash { .. }
+                | Finding::BadParent { stored: 0, .. }
+                | Finding::UnsortedSubkeys { index: 1, .. }
+        )));
+    }
+
+    #[test]
+    fn base_block_problems() {
+        let mut bytes = written_hive();
+        bytes[4] += 1;
+        let findings = OfflineHive::from_bytes(bytes).unwrap().verify();
+        assert!(matches!(findings[0], Finding::BadChecksum { .. }));
+        assert!(matches!(
+            findings[1],
+            Finding::Dirty {
+                primary: 2,
+                secondary: 1
+            }
+        ));
+    }
+
+    #[test]
+    fn unsorted_and_bad_hashes() {
+        let mut bytes = written_hive();
+        let list = software_list(&bytes);
+        assert_eq!(&bytes[list..list + 2], b"lh");
+
+        // Swap the first two entries, hashes and all.
+        let (first, second) = (list + 4, list + 12);
+        let entry = bytes[first..first + 8].to_vec();
+        bytes.copy_within(second..second + 8, first);
+        bytes[second..second + 8].copy_from_slice(&entry);
+        // And spoil the hash of the third.
+        bytes[list + 24] ^= 1;
+
+        let findings = OfflineHive::from_bytes(bytes).unwrap().verify();
+        assert!(findings.contains(&Finding::UnsortedSubkeys {
+            list: (list - BASE_BLOCK_SIZE - 4) as u32,
+            index: 1
+        }));
+        assert!(findings.contains(&Finding::BadNameHash {
+            list: (list - BASE_BLOCK_SIZE - 4) as u32,
+            index: 2
+        }));
+    }
+
+    #[test]
+    fn reference_problems() {
+        let mut bytes = written_hive();
+        let hive = OfflineHive::from_bytes(bytes.clone()).unwrap();
+        let software = hive.open_key("Software").unwrap();
+        let node = software.node().unwrap();
+        let nk = BASE_BLOCK_SIZE + software.offset as usize + 4;
+        let sk = BASE_BLOCK_SIZE + node.security as usize + 4;
+        let class_name = hive
+            .open_key(r"Software\a")
+            .unwrap()
+            .node()
+            .unwrap()
+            .class_name;
+
+        // Claim an extra subkey, miscount the security cell, and free a class name cell.
+        bytes[nk + 20] += 1;
+        bytes[sk + 12] += 1;
+        let cell = BASE_BLOCK_SIZE + class_name as usize;
+        let size = -i32::from_le_bytes(bytes[cell..cell + 4].try_into().unwrap());
+        bytes[cell..cell + 4].copy_from_slice(&size.to_le_bytes());
+
+        let findings = OfflineHive::from_bytes(bytes).unwrap().verify();
+        assert!(findings.contains(&Finding::SubkeyCountMismatch {
+            offset: software.offset,
+            stored: 4,
+            actual: 3
+        }));
+        assert!(findings.iter().any(|x| matches!(
+            x,
+            Finding::SecurityRefCountMismatch { offset, .. } if *offset == node.security
+        )));
+        assert!(findings.iter().any(|x| matches!(
+            x,
+            Finding::FreeCellReferenced { offset, .. } if *offset == class_name
+        )));
+    }
+
+    #[test]
+    fn bin_and_cell_problems() {
+        let mut bytes = written_hive();
+        bytes[BASE_BLOCK_SIZE + 4] = 8;
+        // A cell size that is not a multiple of 8.
+        bytes[BASE_BLOCK_SIZE + 32] = bytes[BASE_BLOCK_SIZE + 32].wrapping_sub(4);
+        let findings = OfflineHive::from_bytes(bytes).unwrap().verify();
+        assert!(findings.contains(&Finding::BadBinOffset {
+            offset: 0,
+            stored: 8
+        }));
+        assert!(findings
+            .iter()
+            .any(|x| matches!(x, Finding::BadCellSize { offset: 32, .. })));
+
+        let mut bytes = written_hive();
+        bytes[BASE_BLOCK_SIZE] = b'x';
+        let findings = OfflineHive::from_bytes(bytes).unwrap().verify();
+        assert!(findings.contains(&Finding::BadBinSignature { offset: 0 }));
+    }
+}
@@ -0,0 +1,799 @@
+use std::{io::Write, path::Path};
+
+use utfx::U16String;
+
+use super::base::{checksum, BASE_BLOCK_SIZE};
+use super::cell::{
+    name_hash, name_hint, DATA_IN_OFFSET, KEY_COMP_NAME, KEY_HIVE_ENTRY, KEY_NO_DELETE,
+    MAX_DATA_SEGMENT_LEN, NO_CELL, VALUE_COMP_NAME,
+};
+use super::Error;
+use crate::descriptor::SecurityDescriptor;
+use crate::tree::KeyTree;
+use crate::util::{cmp_ignore_case, system_time_to_filetime};
+use crate::value::Data;
+
+/// The size that hive bins are allocated in multiples of.
+const HBIN_ALIGN: usize = 4096;
+
+const HBIN_HEADER_LEN: usize = 32;
+
+/// The longest a key name may be, in characters.
+const MAX_KEY_NAME_LEN: usize = 255;
+
+/// Leaf lists longer than this are split up underneath an index root.
+const MAX_LEAF_ENTRIES: usize = 1012;
+
+/// Serializes a [`KeyTree`](../tree/struct.KeyTree.html) into a regf hive file that Windows can load
+/// with `RegLoadKey` or `reg load`.
+#[derive(Debug, Clone)]
+pub struct HiveWriter {
+    /// The minor version of the format to write, from 3 to 6. Defaults to 5, as written by
+    /// Windows 10.
+    pub minor_version: u32,
+    /// The file name recorded in the base block. Only the last 31 characters are kept.
+    pub file_name: U16String,
+}
+
+impl Default for HiveWriter {
+    fn default() -> Self {
+        HiveWriter {
+            minor_version: 5,
+            file_name: U16String::new(),
+        }
+    }
+}
+
+impl HiveWriter {
+    #[inline]
+    pub fn new() -> HiveWriter {
+        HiveWriter::default()
+    }
+
+    /// Writes a hive with `root` as its root key to the file at `file_path`.
+    pub fn write<P: AsRef<Path>>(&self, root: &KeyTree, file_path: P) -> Result<(), Error> {
+        let bytes = self.to_bytes(root)?;
+        std::fs::File::create(file_path)?.write_all(&bytes)?;
+        Ok(())
+    }
+
+    /// Serializes a hive with `root` as its root key.
+    pub fn to_bytes(&self, root: &KeyTree) -> Result<Vec<u8>, Error> {
+        if !(3..=6).contains(&self.minor_version) {
+            return Err(Error::UnsupportedVersion(1, self.minor_version));
+        }
+
+        let last_written = system_time_to_filetime(root.last_written);
+        let mut bins = Bins::new(self.minor_version, last_written);
+        let root_cell = bins.write_key(root, NO_CELL)?;
+        let bins = bins.finish();
+
+        let mut data = vec![0u8; BASE_BLOCK_SIZE];
+        data[0..4].copy_from_slice(b"regf");
+        put_u32(&mut data, 4, 1);
+        put_u32(&mut data, 8, 1);
+        data[12..20].copy_from_slice(&last_written.to_le_bytes());
+        put_u32(&mut data, 20, 1);
+        put_u32(&mut data, 24, self.minor_version);
+        put_u32(&mut data, 32, 1);
+        put_u32(&mut data, 36, root_cell);
+        put_u32(&mut data, 40, bins.len() as u32);
+        put_u32(&mut data, 44, 1);
+
+        let file_name = self.file_name.as_slice();
+        let file_name = &file_name[file_name.len().saturating_sub(31)..];
+        for (i, c) in file_name.iter().enumerate() {
+            data[48 + i * 2..50 + i * 2].copy_from_slice(&c.to_le_bytes());
+        }
+
+        let checksum = checksum(&data);
+        put_u32(&mut data, 508, checksum);
+
+        data.extend_from_slice(&bins);
+        Ok(data)
+    }
+}
+
+/// Hive bins being laid out, with cells allocated one after another.
+struct Bins {
+    buf: Vec<u8>,
+    bin_end: usize,
+    minor_version: u32,
+    last_written: u64,
+    /// Each distinct security descriptor, with the offset of its cell and its reference count.
+    security: Vec<(Vec<u8>, u32, u32)>,
+}
+
+impl Bins {
+    fn new(minor_version: u32, last_written: u64) -> Bins {
+        let mut bins = Bins {
+            buf: vec![],
+            bin_end: 0,
+            minor_version,
+            last_written,
+            security: vec![],
+        };
+        bins.new_bin(0);
+        bins
+    }
+
+    /// Starts a new hive bin large enough to hold a cell of `cell_size` bytes.
+    fn new_bin(&mut self, cell_size: usize) {
+        self.free_rest_of_bin();
+
+        let start = self.buf.len();
+        let size = (cell_size + HBIN_HEADER_LEN).div_ceil(HBIN_ALIGN) * HBIN_ALIGN;
+        self.buf.resize(start + size, 0);
+        self.bin_end = start + size;
+
+        let header = &mut self.buf[start..start + HBIN_HEADER_LEN];
+        header[0..4].copy_from_slice(b"hbin");
+        put_u32(header, 4, start as u32);
+        put_u32(header, 8, size as u32);
+        if start == 0 {
+            header[20..28].copy_from_slice(&self.last_written.to_le_bytes());
+        }
+        self.buf.truncate(start + HBIN_HEADER_LEN);
+    }
+
+    /// Marks the unused space at the end of the current hive bin as a free cell.
+    fn free_rest_of_bin(&mut self) {
+        let remaining = self.bin_end - self.buf.len();
+        if remaining > 0 {
+            let start = self.buf.len();
+            self.buf.resize(self.bin_end, 0);
+            put_u32(&mut self.buf, start, remaining as u32);
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        for (_, offset, references) in std::mem::take(&mut self.security) {
+            put_u32(self.cell_mut(offset), 12, references);
+        }
+
+        self.free_rest_of_bin();
+        self.buf
+    }
+
+    fn write_key(&mut self, key: &KeyTree, parent: u32) -> Result<u32, Error> {
+        let mut flags = 0;
+        if parent == NO_CELL {
+            flags |= KEY_HIVE_ENTRY | KEY_NO_DELETE;
+        }
+
+        let record = key_node_record(
+            key.name.as_slice(),
+            flags,
+            system_time_to_filetime(key.last_written),
+            if parent == NO_CELL { 0 } else { parent },
+        )?;
+        let offset = self.alloc(&record);
+
+        let mut subkeys = key.subkeys.iter().collect::<Vec<_>>();
+        subkeys.sort_by(|a, b| cmp_ignore_case(a.name.as_slice(), b.name.as_slice()));
+        let entries = subkeys
+            .iter()
+            .map(|subkey| Ok((subkey.name.as_slice(), self.write_key(subkey, offset)?)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let subkey_list = write_subkey_list(self, &entries);
+
+        let value_offsets = key
+            .values
+            .iter()
+            .map(|(name, data)| write_value(self, name.as_slice(), data))
+            .collect::<Result<Vec<_>, _>>()?;
+        let value_list = match value_offsets.is_empty() {
+            true => NO_CELL,
+            false => self.alloc(&u32_bytes(&value_offsets)),
+        };
+
+        let class_name = key
+            .class_name
+            .as_ref()
+            .map(|x| utf16_bytes(x.as_slice()))
+            .unwrap_or_default();
+        let class_cell = match class_name.is_empty() {
+            true => NO_CELL,
+            false => self.alloc(&class_name),
+        };
+
+        let security = self.security(key.security.as_deref());
+
+        let record = self.cell_mut(offset);
+        put_u32(record, 20, subkeys.len() as u32);
+        put_u32(record, 28, subkey_list);
+        put_u32(record, 36, key.values.len() as u32);
+        put_u32(record, 40, value_list);
+        put_u32(record, 44, security);
+        put_u32(record, 48, class_cell);
+        put_u32(record, 52, max(subkeys.iter().map(|x| x.name.len() * 2)));
+        put_u32(
+            record,
+            56,
+            max(subkeys
+                .iter()
+                .map(|x| x.class_name.as_ref().map(|c| c.len() * 2).unwrap_or(0))),
+        );
+        put_u32(record, 60, max(key.values.iter().map(|(x, _)| x.len() * 2)));
+        put_u32(
+            record,
+            64,
+            max(key.values.iter().map(|(_, x)| x.to_bytes().len())),
+        );
+        record[74..76].copy_from_slice(&(class_name.len() as u16).to_le_bytes());
+
+        Ok(offset)
+    }
+
+    /// Returns the offset of the security cell for `descriptor`, sharing cells between keys with
+    /// identical descriptors.
+    fn security(&mut self, descriptor: Option<&[u8]>) -> u32 {
+        let descriptor = match descriptor {
+            Some(v) => v.to_vec(),
+            None => default_security_descriptor(),
+        };
+
+        if let Some(entry) = self.security.iter_mut().find(|x| x.0 == descriptor) {
+            entry.2 += 1;
+            return entry.1;
+        }
+
+        let offset = self.alloc(&security_key_record(&descriptor));
+
+        // Security cells form a circular doubly linked list.
+        let first = self.security.first().map(|x| x.1).unwrap_or(offset);
+        let last = self.security.last().map(|x| x.1).unwrap_or(offset);
+        put_u32(self.cell_mut(offset), 4, first);
+        put_u32(self.cell_mut(offset), 8, last);
+        put_u32(self.cell_mut(last), 4, offset);
+        put_u32(self.cell_mut(first), 8, offset);
+
+        self.security.push((descriptor, offset, 1));
+        offset
+    }
+}
+
+impl CellAlloc for Bins {
+    #[inline]
+    fn minor_version(&self) -> u32 {
+        self.minor_version
+    }
+
+    fn alloc(&mut self, payload: &[u8]) -> u32 {
+        let size = cell_size(payload.len());
+        if self.buf.len() + size > self.bin_end {
+            self.new_bin(size);
+        }
+
+        let offset = self.buf.len();
+        self.buf.extend_from_slice(&(-(size as i32)).to_le_bytes());
+        self.buf.extend_from_slice(payload);
+        self.buf.resize(offset + size, 0);
+        offset as u32
+    }
+
+    #[inline]
+    fn cell_mut(&mut self, offset: u32) -> &mut [u8] {
+        &mut self.buf[offset as usize + 4..]
+    }
+}
+
+/// Somewhere that cells can be allocated, either a hive being written from scratch or one being
+/// edited in place.
+pub(crate) trait CellAlloc {
+    /// The minor version of the hive format, which decides how some records are laid out.
+    fn minor_version(&self) -> u32;
+
+    /// Allocates a cell and fills it with `payload`, returning its offset.
+    fn alloc(&mut self, payload: &[u8]) -> u32;
+
+    /// The contents of an allocated cell, without its size header.
+    fn cell_mut(&mut self, offset: u32) -> &mut [u8];
+}
+
+/// The size of the cell needed to hold `payload_len` bytes.
+#[inline]
+pub(crate) fn cell_size(payload_len: usize) -> usize {
+    (payload_len + 4 + 7) & !7
+}
+
+/// Encodes a key node with the given name. Its lists, security and class name are left empty.
+pub(crate) fn key_node_record(
+    name: &[u16],
+    flags: u16,
+    last_written: u64,
+    parent: u32,
+) -> Result<Vec<u8>, Error> {
+    if name.len() > MAX_KEY_NAME_LEN {
+        return Err(Error::NameTooLong(String::from_utf16_lossy(name)));
+    }
+
+    let (is_compressed, encoded_name) = encode_name(name);
+    let flags = match is_compressed {
+        true => flags | KEY_COMP_NAME,
+        false => flags & !KEY_COMP_NAME,
+    };
+
+    let mut record = vec![0u8; 76];
+    record[0..2].copy_from_slice(b"nk");
+    record[2..4].copy_from_slice(&flags.to_le_bytes());
+    record[4..12].copy_from_slice(&last_written.to_le_bytes());
+    put_u32(&mut record, 16, parent);
+    put_u32(&mut record, 28, NO_CELL);
+    put_u32(&mut record, 32, NO_CELL);
+    put_u32(&mut record, 40, NO_CELL);
+    put_u32(&mut record, 44, NO_CELL);
+    put_u32(&mut record, 48, NO_CELL);
+    record[72..74].copy_from_slice(&(encoded_name.len() as u16).to_le_bytes());
+    record.extend_from_slice(&encoded_name);
+    Ok(record)
+}
+
+/// Encodes a security key holding `descriptor`, with its list links and reference count unset.
+pub(crate) fn security_key_record(descriptor: &[u8]) -> Vec<u8> {
+    let mut record = vec![0u8; 20];
+    record[0..2].copy_from_slice(b"sk");
+    put_u32(&mut record, 16, descriptor.len() as u32);
+    record.extend_from_slice(descriptor);
+    record
+}
+
+/// Writes a subkey list for `entries`, which must already be sorted by name, returning its offset.
+pub(crate) fn write_subkey_list<A: CellAlloc>(cells: &mut A, entries: &[(&[u16], u32)]) -> u32 {
+    if entries.is_empty() {
+        return NO_CELL;
+    }
+
+    let leaves = entries
+        .chunks(MAX_LEAF_ENTRIES)
+        .map(|entries| write_leaf(cells, entries))
+        .collect::<Vec<_>>();
+
+    if leaves.len() == 1 {
+        return leaves[0];
+    }
+
+    let mut list = b"ri".to_vec();
+    list.extend_from_slice(&(leaves.len() as u16).to_le_bytes());
+    list.extend_from_slice(&u32_bytes(&leaves));
+    cells.alloc(&list)
+}
+
+fn write_leaf<A: CellAlloc>(cells: &mut A, entries: &[(&[u16], u32)]) -> u32 {
+    let signature = match cells.minor_version() {
+        0..=2 => b"li",
+        3..=4 => b"lf",
+        _ => b"lh",
+    };
+
+    let mut list = signature.to_vec();
+    list.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    for (name, offset) in entries {
+        list.extend_from_slice(&offset.to_le_bytes());
+        match signature {
+            b"lf" => list.extend_from_slice(&name_hint(name)),
+            b"lh" => list.extend_from_slice(&name_hash(name).to_le_bytes()),
+            _ => {}
+        }
+    }
+    cells.alloc(&list)
+}
+
+/// Writes a value key and its data, returning the offset of the value key.
+pub(crate) fn write_value<A: CellAlloc>(
+    cells: &mut A,
+    name: &[u16],
+    data: &Data,
+) -> Result<u32, Error> {
+    if name.len() > u16::MAX as usize / 2 {
+        return Err(Error::NameTooLong(String::from_utf16_lossy(name)));
+    }
+
+    let bytes = &data.to_bytes()[..];
+    let (data_size, data_offset) = if bytes.len() <= 4 {
+        let mut inline = [0u8; 4];
+        inline[..bytes.len()].copy_from_slice(bytes);
+        (
+            bytes.len() as u32 | DATA_IN_OFFSET,
+            u32::from_le_bytes(inline),
+        )
+    } else if cells.minor_version() >= 4 && bytes.len() > MAX_DATA_SEGMENT_LEN {
+        (bytes.len() as u32, write_big_data(cells, bytes))
+    } else {
+        (bytes.len() as u32, cells.alloc(bytes))
+    };
+
+    let record = value_key_record(name, data_size, data_offset, data.raw_type());
+    Ok(cells.alloc(&record))
+}
+
+/// Splits data into segments behind a big data record, returning the offset of the record.
+fn write_big_data<A: CellAlloc>(cells: &mut A, bytes: &[u8]) -> u32 {
+    let segments = bytes
+        .chunks(MAX_DATA_SEGMENT_LEN)
+        .map(|x| cells.alloc(x))
+        .collect::<Vec<_>>();
+    let list = cells.alloc(&u32_bytes(&segments));
+
+    let mut record = b"db".to_vec();
+    record.extend_from_slice(&(segments.len() as u16).to_le_bytes());
+    record.extend_from_slice(&list.to_le_bytes());
+    cells.alloc(&record)
+}
+
+/// Encodes a value key pointing at data that has already been written.
+pub(crate) fn value_key_record(
+    name: &[u16],
+    data_size: u32,
+    data_offset: u32,
+    data_type: u32,
+) -> Vec<u8> {
+    let (is_compressed, encoded_name) = encode_name(name);
+    let mut record = vec![0u8; 20];
+    record[0..2].copy_from_slice(b"vk");
+    record[2..4].copy_from_slice(&(encoded_name.len() as u16).to_le_bytes());
+    put_u32(&mut record, 4, data_size);
+    put_u32(&mut record, 8, data_offset);
+    put_u32(&mut record, 12, data_type);
+    if is_compressed {
+        record[16..18].copy_from_slice(&VALUE_COMP_NAME.to_le_bytes());
+    }
+    record.extend_from_slice(&encoded_name);
+    record
+}
+
+/// A descriptor owned by the Administrators group that grants full control to SYSTEM and
+/// Administrators and read access to everyone, inherited by subkeys.
+pub(crate) fn default_security_descriptor() -> Vec<u8> {
+    SecurityDescriptor::from_sddl("O:BAG:SYD:(A;CI;KA;;;SY)(A;CI;KA;;;BA)(A;CI;KR;;;WD)")
+        .unwrap()
+        .to_bytes()
+}
+
+/// Encodes a name as Latin-1 if every character fits, or as UTF-16LE otherwise.
+fn encode_name(name: &[u16]) -> (bool, Vec<u8>) {
+    if name.iter().all(|x| *x < 0x100) {
+        (true, name.iter().map(|x| *x as u8).collect())
+    } else {
+        (false, utf16_bytes(name))
+    }
+}
+
+#[inline]
+fn utf16_bytes(s: &[u16]) -> Vec<u8> {
+    s.iter().flat_map(|x| x.to_le_bytes().to_vec()).collect()
+}
+
+#[inline]
+pub(crate) fn u32_bytes(values: &[u32]) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|x| x.to_le_bytes().to_vec())
+        .collect()
+}
+
+#[inline]
+fn max(lengths: impl Iterator<Item = usize>) -> u32 {
+    lengths.max().unwrap_or(0) as u32
+}
+
+#[inline]
+pub(crate) fn put_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::regf::cell::{le_u32, ValueKey};
+    use crate::regf::OfflineHive;
+    use crate::value::Data;
+
+    fn s(x: &str) -> utfx::U16CString {
+        x.try_into().unwrap()
+    }
+
+    fn sample_tree() -> KeyTree {
+        let mut root = KeyTree::new(s("ROOT"));
+        root.last_written = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+
+        let vendor = root.create(&s(r"Software\Vëndor"));
+        vendor.class_name = Some(s("Class"));
+        vendor.set_value(s(""), Data::String(s("default")));
+        vendor.set_value(s("Version"), Data::U32(42));
+        vendor.set_value(s("Big"), Data::U32BE(7));
+        vendor.set_value(s("Nothing"), Data::None);
+        vendor.set_value(s("Quad"), Data::U64(0x1234_5678_9abc_def0));
+        vendor.set_value(s("Binary"), Data::Binary((0..=255).collect()));
+        vendor.set_value(s("Path"), Data::ExpandString(s(r"%SystemRoot%\system32")));
+        vendor.set_value(s("Multi"), Data::MultiString(vec![s("a"), s("bc")]));
+        vendor.set_value(s("Ключ"), Data::U32(1));
+        root.create(&s("Ключ")).security = Some(vec![
+            1, 0, 0, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]);
+        root.create(&s("System"));
+
+        // FILETIMEs only have a resolution of 100ns.
+        truncate_timestamps(&mut root);
+        root
+    }
+
+    fn truncate_timestamps(key: &mut KeyTree) {
+        let since_epoch = key.last_written.duration_since(UNIX_EPOCH).unwrap();
+        key.last_written = UNIX_EPOCH + Duration::from_secs(since_epoch.as_secs());
+        key.subkeys.iter_mut().for_each(truncate_timestamps);
+    }
+
+    fn without_default_security(mut tree: KeyTree) -> KeyTree {
+        if tree.security.as_deref() == Some(&default_security_descriptor()[..]) {
+            tree.security = None;
+        }
+        tree.subkeys = tree
+            .subkeys
+            .into_iter()
+            .map(without_default_security)
+            .collect();
+        tree
+    }
+
+    #[test]
+    fn round_trip() {
+        let tree = sample_tree();
+        let bytes = HiveWriter::new().to_bytes(&tree).unwrap();
+
+        let hive = OfflineHive::from_bytes(bytes).unwrap();
+        assert!(hive.is_checksum_valid());
+        assert!(!hive.base_block().is_dirty());
+        assert_eq!(hive.base_block().last_written, tree.last_written);
+
+        let mut expected = tree;
+        expected.sort();
+        let actual = without_default_security(hive.root().unwrap().to_tree().unwrap());
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn every_minor_version_is_readable() {
+        let tree = sample_tree();
+        for minor_version in 3..=6 {
+            let writer = HiveWriter {
+                minor_version,
+                ..HiveWriter::default()
+            };
+            let hive = OfflineHive::from_bytes(writer.to_bytes(&tree).unwrap()).unwrap();
+            assert_eq!(hive.base_block().minor_version, minor_version);
+            assert_eq!(
+                hive.open_key(r"software\vëndor")
+                    .unwrap()
+                    .value("version")
+                    .unwrap(),
+                Data::U32(42)
+            );
+        }
+
+        let writer = HiveWriter {
+            minor_version: 2,
+            ..HiveWriter::default()
+        };
+        assert!(matches!(
+            writer.to_bytes(&tree),
+            Err(Error::UnsupportedVersion(1, 2))
+        ));
+    }
+
+    #[test]
+    fn hive_bins_are_well_formed() {
+        let mut tree = sample_tree();
+        // Larger than a single hive bin.
+        tree.set_value(s("Large"), Data::Binary(vec![0xAB; 10_000]));
+        let bytes = HiveWriter::new().to_bytes(&tree).unwrap();
+        let bins = &bytes[BASE_BLOCK_SIZE..];
+        assert_eq!(bins.len() % HBIN_ALIGN, 0);
+
+        let mut offset = 0;
+        while offset < bins.len() {
+            assert_eq!(&bins[offset..offset + 4], b"hbin");
+            assert_eq!(
+                u32::from_le_bytes(bins[offset + 4..offset + 8].try_into().unwrap()),
+                offset as u32
+            );
+            let size = u32::from_le_bytes(bins[offset + 8..offset + 12].try_into().unwrap());
+            assert_eq!(size as usize % HBIN_ALIGN, 0);
+
+            // Cells exactly fill each bin.
+            let mut cell = offset + HBIN_HEADER_LEN;
+            while cell < offset + size as usize {
+                let cell_size =
+                    i32::from_le_bytes(bins[cell..cell + 4].try_into().unwrap()).unsigned_abs();
+                assert_eq!(cell_size % 8, 0);
+                cell += cell_size as usize;
+            }
+            assert_eq!(cell, offset + size as usize);
+            offset += size as usize;
+        }
+
+        let hive = OfflineHive::from_bytes(bytes).unwrap();
+        assert_eq!(
+            hive.root().unwrap().value("Large").unwrap(),
+            Data::Binary(vec![0xAB; 10_000])
+        );
+    }
+
+    #[test]
+    fn many_subkeys_use_an_index_root() {
+        let mut root = KeyTree::new(s("ROOT"));
+        for i in 0..1100 {
+            root.create(&s(&format!("Key{:04}", i)));
+        }
+        let hive = OfflineHive::from_bytes(HiveWriter::new().to_bytes(&root).unwrap()).unwrap();
+        let names = hive
+            .root()
+            .unwrap()
+            .keys()
+            .map(|x| x.unwrap().name().to_string_lossy())
+            .collect::<Vec<_>>();
+        assert_eq!(names.len(), 1100);
+        assert_eq!(names[0], "Key0000");
+        assert_eq!(names[1099], "Key1099");
+        assert!(hive.open_key("key1034").is_ok());
+    }
+
+    #[test]
+    fn shared_security_cells() {
+        let tree = sample_tree();
+        let hive = OfflineHive::from_bytes(HiveWriter::new().to_bytes(&tree).unwrap()).unwrap();
+        let bins = hive.bins();
+
+        let mut cells = vec![];
+        let mut offset = HBIN_HEADER_LEN;
+        while offset < bins.len() {
+            let size = i32::from_le_bytes(bins[offset..offset + 4].try_into().unwrap());
+            if size < 0 && &bins[offset + 4..offset + 6] == b"sk" {
+                let field = |x: usize| {
+                    u32::from_le_bytes(bins[offset + x..offset + x + 4].try_into().unwrap())
+                };
+                cells.push((offset as u32, field(8), field(12), field(16)));
+            }
+            offset += size.unsigned_abs() as usize;
+        }
+
+        // The root, Software, Vëndor and System share the default descriptor.
+        assert_eq!(cells.len(), 2);
+        let (a, b) = (cells[0], cells[1]);
+        assert_eq!((a.1, a.2), (b.0, b.0));
+        assert_eq!((b.1, b.2), (a.0, a.0));
+        assert_eq!(a.3 + b.3, 5);
+    }
+
+    #[test]
+    fn read_key_info() {
+        let tree = sample_tree();
+        let hive = OfflineHive::from_bytes(HiveWriter::new().to_bytes(&tree).unwrap()).unwrap();
+
+        let info = hive.open_key("Software").unwrap().info().unwrap();
+        assert_eq!(info.class_name, None);
+        assert_eq!(info.last_written, tree.subkeys[0].last_written);
+        assert_eq!((info.subkey_count, info.value_count), (1, 0));
+        assert_eq!(info.max_subkey_name_len, 6);
+        assert_eq!(info.max_class_name_len, 5);
+
+        let info = hive.open_key(r"Software\Vëndor").unwrap().info().unwrap();
+        assert_eq!(info.class_name, Some(s("Class")));
+        assert_eq!((info.subkey_count, info.value_count), (0, 9));
+        assert_eq!(info.max_value_name_len, 7);
+        assert_eq!(info.max_value_data_len, 256);
+    }
+
+    #[test]
+    fn read_security_descriptors() {
+        let hive =
+            OfflineHive::from_bytes(HiveWriter::new().to_bytes(&sample_tree()).unwrap()).unwrap();
+
+        let sd = hive.root().unwrap().security_descriptor().unwrap().unwrap();
+        assert_eq!(sd.owner, Some("S-1-5-32-544".parse().unwrap()));
+        assert_eq!(sd.dacl.unwrap().aces.len(), 3);
+
+        let sd = hive
+            .open_key("Ключ")
+            .unwrap()
+            .security_descriptor()
+            .unwrap();
+        assert_eq!(sd, Some(SecurityDescriptor::default()));
+    }
+
+    #[test]
+    fn name_too_long() {
+        let root = KeyTree::new(s(&"a".repeat(256)));
+        assert!(matches!(
+            HiveWriter::new().to_bytes(&root),
+            Err(Error::NameTooLong(_))
+        ));
+    }
+
+    #[test]
+    fn big_data_round_trip() {
+        let mut root = KeyTree::new(s("ROOT"));
+        let binary = Data::Binary((0..2_000_000).map(|x| (x % 251) as u8).collect());
+        let multi = Data::MultiString((0..2000).map(|x| s(&format!("string {}", x))).collect());
+        let exact = Data::Binary(vec![9; MAX_DATA_SEGMENT_LEN * 2]);
+        root.set_value(s("Binary"), binary.clone());
+        root.set_value(s("Multi"), multi.clone());
+        root.set_value(s("Exact"), exact.clone());
+
+        for minor_version in 3..=6 {
+            let writer = HiveWriter {
+                minor_version,
+                ..HiveWriter::default()
+            };
+            let hive = OfflineHive::from_bytes(writer.to_bytes(&root).unwrap()).unwrap();
+            let key = hive.root().unwrap();
+            assert_eq!(key.value("Binary").unwrap(), binary);
+            assert_eq!(key.value("Multi").unwrap(), multi);
+            assert_eq!(key.value("Exact").unwrap(), exact);
+            assert_eq!(hive.verify(), vec![]);
+
+            // Only version 1.4 and later split data into segments.
+            let offset = le_u32(hive.cell(key.node().unwrap().value_list).unwrap(), 0);
+            let vk = ValueKey::parse(offset, hive.cell(offset).unwrap()).unwrap();
+            assert_eq!(hive.is_big_data(&vk), minor_version >= 4);
+        }
+    }
+
+    #[test]
+    fn undecodable_data_round_trip() {
+        let mut root = KeyTree::new(s("ROOT"));
+        root.last_written = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let custom = Data::Other {
+            ty: 0x100000,
+            bytes: vec![1, 2, 3, 4, 5, 6],
+        };
+        let short = Data::Other {
+            ty: 4,
+            bytes: vec![1, 2],
+        };
+        let unterminated = Data::Other {
+            ty: 1,
+            bytes: vec![b'a', 0, b'b'],
+        };
+        root.set_value(s("custom"), custom.clone());
+        root.set_value(s("short"), short.clone());
+        root.set_value(s("unterminated"), unterminated);
+        root.sort();
+        let bytes = HiveWriter::new().to_bytes(&root).unwrap();
+
+        let hive = OfflineHive::from_bytes(bytes.clone()).unwrap();
+        assert_eq!(hive.verify(), vec![]);
+        let key = hive.root().unwrap();
+        assert_eq!(key.value("custom").unwrap(), custom);
+        assert_eq!(key.value("short").unwrap(), short);
+
+        let tree = without_default_security(key.to_tree().unwrap());
+        assert_eq!(tree, root);
+        assert_eq!(HiveWriter::new().to_bytes(&tree).unwrap(), bytes);
+    }
+
+    #[test]
+    fn big_data_larger_than_its_segments() {
+        let mut root = KeyTree::new(s("ROOT"));
+        root.set_value(s("Binary"), Data::Binary(vec![1; MAX_DATA_SEGMENT_LEN * 2]));
+        let mut bytes = HiveWriter::new().to_bytes(&root).unwrap();
+
+        let hive = OfflineHive::from_bytes(bytes.clone()).unwrap();
+        let offset = le_u32(
+            hive.cell(hive.root().unwrap().node().unwrap().value_list)
+                .unwrap(),
+            0,
+        );
+        let size = BASE_BLOCK_SIZE + offset as usize + 8;
+        bytes[size..size + 4].copy_from_slice(&0x7FFF_0000u32.to_le_bytes());
+
+        let hive = OfflineHive::from_bytes(bytes).unwrap();
+        assert!(matches!(
+            hive.root().unwrap().value("Binary"),
+            Err(Error::InvalidCell(_))
+        ));
+    }
+}
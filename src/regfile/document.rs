@@ -0,0 +1,427 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use utfx::{U16CStr, U16CString};
+
+use super::parse::{decode, logical_lines, parse_entry, parse_section};
+use super::write::{encode, format_entry};
+use super::{Encoding, Entry, EntryValue, Error, RegFile, Section, Version};
+use crate::util::eq_ignore_case;
+
+/// A `.reg` file that keeps every line as it was read, including comments, blank lines and the
+/// way hex data is wrapped.
+///
+/// Writing a document back out reproduces the original text exactly, apart from the entries that
+/// were changed through [`set_entry`](#method.set_entry) or
+/// [`remove_entry`](#method.remove_entry), so files kept under version control get minimal diffs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Document {
+    version: Version,
+    /// The encoding used by [`to_bytes`](#method.to_bytes).
+    pub encoding: Encoding,
+    lines: Vec<Line>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Line {
+    /// The original text, including continuation lines and the final line break, if any.
+    raw: String,
+    kind: LineKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum LineKind {
+    /// The header, a comment or a blank line.
+    Other,
+    Section {
+        path: U16CString,
+        is_delete: bool,
+    },
+    Entry(Entry),
+}
+
+impl Document {
+    /// An empty document, holding only the header line.
+    pub fn new(version: Version) -> Document {
+        Document {
+            version,
+            encoding: version.encoding(),
+            lines: vec![Line {
+                raw: format!("{}\r\n", version.header()),
+                kind: LineKind::Other,
+            }],
+        }
+    }
+
+    /// Parses a document from the raw contents of a `.reg` file, remembering its encoding so that
+    /// [`to_bytes`](#method.to_bytes) writes it back the same way.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Document, Error> {
+        let (text, encoding) = decode(bytes);
+        let mut document = Document::parse(&text)?;
+        document.encoding = encoding;
+        Ok(document)
+    }
+
+    /// Parses a document from the text of a `.reg` file. Its encoding is the one `regedit` uses
+    /// for its version.
+    pub fn parse(text: &str) -> Result<Document, Error> {
+        let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+        let mut version = None;
+        let mut has_section = false;
+        let mut lines = vec![];
+
+        for line in logical_lines(text) {
+            let trimmed = line.text.trim();
+            let kind = if trimmed.is_empty() || trimmed.starts_with(';') {
+                LineKind::Other
+            } else if let Some(version) = version {
+                if trimmed.starts_with('[') {
+                    let section = parse_section(line.line_no, trimmed)?;
+                    has_section = true;
+                    LineKind::Section {
+                        path: section.path,
+                        is_delete: section.is_delete,
+                    }
+                } else if has_section {
+                    LineKind::Entry(parse_entry(line.line_no, trimmed, version)?)
+                } else {
+                    return Err(Error::Parse(
+                        line.line_no,
+                        "value outside of a key section".into(),
+                    ));
+                }
+            } else {
+                version = Some(match trimmed {
+                    x if x == Version::Regedit4.header() => Version::Regedit4,
+                    x if x == Version::Regedit5.header() => Version::Regedit5,
+                    _ => return Err(Error::MissingHeader),
+                });
+                LineKind::Other
+            };
+
+            lines.push(Line {
+                raw: line.raw.to_string(),
+                kind,
+            });
+        }
+
+        let version = version.ok_or(Error::MissingHeader)?;
+        Ok(Document {
+            version,
+            encoding: version.encoding(),
+            lines,
+        })
+    }
+
+    #[inline]
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Encodes the document in its [`encoding`](#structfield.encoding).
+    #[inline]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        encode(&self.to_string(), self.encoding)
+    }
+
+    /// The sections and entries of the document, without its formatting.
+    pub fn to_reg_file(&self) -> RegFile {
+        let mut file = RegFile::new(self.version);
+        for line in &self.lines {
+            match &line.kind {
+                LineKind::Other => {}
+                LineKind::Section { path, is_delete } => file.sections.push(Section {
+                    path: path.clone(),
+                    is_delete: *is_delete,
+                    entries: vec![],
+                }),
+                LineKind::Entry(entry) => {
+                    if let Some(section) = file.sections.last_mut() {
+                        section.entries.push(entry.clone());
+                    }
+                }
+            }
+        }
+        file
+    }
+
+    /// The value given to an entry in the sections for the key at `path`, or `None` if there is
+    /// no such entry. Later entries win over earlier ones, as they do when the file is imported.
+    pub fn entry(&self, path: &U16CStr, name: Option<&U16CStr>) -> Option<&EntryValue> {
+        let index = self.entry_indices(path, name).last()?;
+        match &self.lines[index].kind {
+            LineKind::Entry(entry) => Some(&entry.value),
+            _ => None,
+        }
+    }
+
+    /// Sets an entry in the section for the key at `path`. An existing entry is rewritten in
+    /// place; otherwise the entry is added after the last entry of the section, and a section is
+    /// added at the end of the document if there is none for the key.
+    pub fn set_entry(&mut self, path: &U16CStr, name: Option<&U16CStr>, value: EntryValue) {
+        if let Some(index) = self.entry_indices(path, name).last() {
+            // Keep the name as it is written in the file, in case it differs in case.
+            let name = match &self.lines[index].kind {
+                LineKind::Entry(entry) => entry.name.clone(),
+                _ => unreachable!(),
+            };
+            let entry = Entry { name, value };
+            let raw = self.format(&entry) + line_break(&self.lines[index].raw);
+            self.lines[index] = Line {
+                raw,
+                kind: LineKind::Entry(entry),
+            };
+            return;
+        }
+
+        let entry = Entry {
+            name: name.map(U16CStr::to_ucstring),
+            value,
+        };
+
+        let newline = self.newline();
+        let index = match self.section_index(path) {
+            Some(index) => {
+                let end = self.lines[index + 1..]
+                    .iter()
+                    .position(|x| matches!(x.kind, LineKind::Section { .. }))
+                    .map_or(self.lines.len(), |x| index + 1 + x);
+                self.lines[index + 1..end]
+                    .iter()
+                    .rposition(|x| matches!(x.kind, LineKind::Entry(_)))
+                    .map_or(index + 1, |x| index + 2 + x)
+            }
+            None => {
+                let is_blank_at_end = self.lines.last().is_some_and(|x| x.raw.trim().is_empty());
+                if !is_blank_at_end {
+                    self.push_line(newline.to_string(), LineKind::Other);
+                }
+                self.push_line(
+                    format!("[{}]{}", path.to_string_lossy(), newline),
+                    LineKind::Section {
+                        path: path.to_ucstring(),
+                        is_delete: false,
+                    },
+                );
+                if is_blank_at_end {
+                    self.push_line(newline.to_string(), LineKind::Other);
+                    self.lines.len() - 1
+                } else {
+                    self.lines.len()
+                }
+            }
+        };
+
+        self.end_line(index);
+        let raw = self.format(&entry) + newline;
+        self.lines.insert(
+            index,
+            Line {
+                raw,
+                kind: LineKind::Entry(entry),
+            },
+        );
+    }
+
+    /// Removes every entry for a value from the sections for the key at `path`, returning whether
+    /// there were any.
+    pub fn remove_entry(&mut self, path: &U16CStr, name: Option<&U16CStr>) -> bool {
+        let indices = self.entry_indices(path, name).collect::<Vec<_>>();
+        for index in indices.iter().rev() {
+            self.lines.remove(*index);
+        }
+        !indices.is_empty()
+    }
+
+    /// The indices of the lines holding entries for a value in any section for the key at `path`.
+    fn entry_indices<'a>(
+        &'a self,
+        path: &'a U16CStr,
+        name: Option<&'a U16CStr>,
+    ) -> impl Iterator<Item = usize> + 'a {
+        let mut is_in_section = false;
+        self.lines
+            .iter()
+            .enumerate()
+            .filter_map(move |(i, line)| match &line.kind {
+                LineKind::Other => None,
+                LineKind::Section {
+                    path: section_path,
+                    is_delete,
+                } => {
+                    is_in_section =
+                        !is_delete && eq_ignore_case(section_path.as_slice(), path.as_slice());
+                    None
+                }
+                LineKind::Entry(entry) if is_in_section && names_eq(&entry.name, name) => Some(i),
+                LineKind::Entry(_) => None,
+            })
+    }
+
+    /// The index of the header of the last section that sets values on the key at `path`.
+    fn section_index(&self, path: &U16CStr) -> Option<usize> {
+        self.lines.iter().rposition(|line| match &line.kind {
+            LineKind::Section {
+                path: section_path,
+                is_delete,
+            } => !is_delete && eq_ignore_case(section_path.as_slice(), path.as_slice()),
+            _ => false,
+        })
+    }
+
+    fn format(&self, entry: &Entry) -> String {
+        let text = format_entry(entry, self.version);
+        match self.newline() {
+            "\r\n" => text,
+            newline => text.replace("\r\n", newline),
+        }
+    }
+
+    /// The line break used by the header line, which new lines are given.
+    fn newline(&self) -> &'static str {
+        match self.lines.first().map(|x| line_break(&x.raw)) {
+            Some("\n") => "\n",
+            _ => "\r\n",
+        }
+    }
+
+    fn push_line(&mut self, raw: String, kind: LineKind) {
+        let index = self.lines.len();
+        self.end_line(index);
+        self.lines.push(Line { raw, kind });
+    }
+
+    /// Makes sure that the line before `index` ends with a line break, so that a line can be
+    /// inserted at `index`.
+    fn end_line(&mut self, index: usize) {
+        let newline = self.newline();
+        if let Some(line) = index.checked_sub(1).and_then(|x| self.lines.get_mut(x)) {
+            if line_break(&line.raw).is_empty() {
+                line.raw.push_str(newline);
+            }
+        }
+    }
+}
+
+impl Display for Document {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.lines.iter().try_for_each(|x| f.write_str(&x.raw))
+    }
+}
+
+impl FromStr for Document {
+    type Err = Error;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Document::parse(s)
+    }
+}
+
+fn names_eq(a: &Option<U16CString>, b: Option<&U16CStr>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => eq_ignore_case(a.as_slice(), b.as_slice()),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+fn line_break(raw: &str) -> &str {
+    if raw.ends_with("\r\n") {
+        "\r\n"
+    } else if raw.ends_with('\n') {
+        "\n"
+    } else {
+        ""
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    use crate::value::Data;
+
+    fn s(x: &str) -> U16CString {
+        x.try_into().unwrap()
+    }
+
+    const TEXT: &str = "Windows Registry Editor Version 5.00\n\
+        \n\
+        ; Settings for the vendor's app.\n\
+        [HKEY_CURRENT_USER\\Software\\Vendor]\n\
+        \"Version\"=dword:00000001\n\
+        ; Keep this in sync with the installer.\n\
+        \"Blob\"=hex:01,02,\\\n    03,04\n\
+        \n\
+        [HKEY_CURRENT_USER\\Software\\Other]\n\
+        @=\"x\"\n";
+
+    #[test]
+    fn round_trip_is_exact() {
+        let document = Document::parse(TEXT).unwrap();
+        assert_eq!(document.to_string(), TEXT);
+        assert_eq!(document.to_reg_file().sections.len(), 2);
+
+        let bytes = RegFile::parse(TEXT).unwrap().to_bytes();
+        let document = Document::from_bytes(&bytes).unwrap();
+        assert_eq!(document.encoding, Encoding::Utf16);
+        assert_eq!(document.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn set_entry_in_place() {
+        let mut document = Document::parse(TEXT).unwrap();
+        let vendor = s(r"hkey_current_user\software\vendor");
+        document.set_entry(&vendor, Some(&s("VERSION")), EntryValue::Set(Data::U32(2)));
+
+        assert_eq!(
+            document.to_string(),
+            TEXT.replace("dword:00000001", "dword:00000002")
+        );
+        assert_eq!(
+            document.entry(&vendor, Some(&s("Version"))),
+            Some(&EntryValue::Set(Data::U32(2)))
+        );
+    }
+
+    #[test]
+    fn add_entries_and_sections() {
+        let mut document = Document::parse(TEXT).unwrap();
+        document.set_entry(
+            &s(r"HKEY_CURRENT_USER\Software\Vendor"),
+            Some(&s("Path")),
+            EntryValue::Delete,
+        );
+        document.set_entry(
+            &s(r"HKEY_CURRENT_USER\Software\New"),
+            None,
+            EntryValue::Set(Data::String(s("y"))),
+        );
+
+        let expected = TEXT.replace("03,04\n", "03,04\n\"Path\"=-\n")
+            + "\n[HKEY_CURRENT_USER\\Software\\New]\n@=\"y\"\n";
+        assert_eq!(document.to_string(), expected);
+
+        let mut document = Document::new(Version::Regedit5);
+        document.set_entry(&s(r"HKEY_USERS\A"), Some(&s("a")), EntryValue::Delete);
+        assert_eq!(
+            document.to_string(),
+            "Windows Registry Editor Version 5.00\r\n\r\n[HKEY_USERS\\A]\r\n\"a\"=-\r\n"
+        );
+    }
+
+    #[test]
+    fn remove_entries() {
+        let mut document = Document::parse(TEXT).unwrap();
+        let vendor = s(r"HKEY_CURRENT_USER\Software\Vendor");
+        assert!(document.remove_entry(&vendor, Some(&s("Blob"))));
+        assert!(!document.remove_entry(&vendor, Some(&s("Blob"))));
+        assert_eq!(document.entry(&vendor, Some(&s("Blob"))), None);
+        assert_eq!(
+            document.to_string(),
+            TEXT.replace("\"Blob\"=hex:01,02,\\\n    03,04\n", "")
+        );
+    }
+}
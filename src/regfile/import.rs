@@ -0,0 +1,418 @@
+use utfx::{U16CStr, U16CString};
+
+use super::{EntryValue, Error, RegFile};
+use crate::backend::Backend;
+use crate::key;
+use crate::patch::{Operation, Patch};
+use crate::sec::Security;
+use crate::util::eq_ignore_case;
+use crate::value::{self, Data};
+
+const SEPARATOR: u16 = b'\\' as u16;
+
+impl RegFile {
+    /// Works out what importing the file onto `base` would change, without changing anything.
+    ///
+    /// `root` is the full path, as written in the file, of the key that `base` stands for, such as
+    /// `HKEY_CURRENT_USER\Software\Vendor`. Every section must be at or below it. The returned
+    /// patch only contains the operations that make a difference: keys that already exist are not
+    /// created, and values that already hold the same data are not set. Deleting a key or value
+    /// that does not exist is not an error, as with `regedit`.
+    pub fn plan_import<B: Backend>(
+        &self,
+        backend: &B,
+        base: &B::Key,
+        root: &U16CStr,
+    ) -> Result<Patch, Error> {
+        let mut planner = Planner {
+            backend,
+            base,
+            created: vec![],
+            deleted: vec![],
+            values: vec![],
+            operations: vec![],
+        };
+
+        for section in &self.sections {
+            let path = relative_path(root.as_slice(), section.path.as_slice())
+                .ok_or_else(|| Error::OutsideRoot(section.path.to_string_lossy()))?;
+
+            if section.is_delete {
+                if path.is_empty() {
+                    return Err(Error::DeleteRoot(section.path.to_string_lossy()));
+                }
+                // `regedit` ignores any values listed under a deleted key.
+                planner.delete_key(path)?;
+                continue;
+            }
+
+            planner.create_key(path)?;
+            for entry in &section.entries {
+                let name = entry.name.clone().unwrap_or_default();
+                match &entry.value {
+                    EntryValue::Set(data) => planner.set_value(path, name, data)?,
+                    EntryValue::Delete => planner.delete_value(path, name)?,
+                }
+            }
+        }
+
+        Ok(Patch {
+            operations: planner.operations,
+        })
+    }
+
+    /// Imports the file onto `base`, returning the operations that were performed. See
+    /// [`plan_import`](#method.plan_import) for how paths are resolved.
+    ///
+    /// Every section is resolved and checked before anything is changed, but the registry has no
+    /// transactions, so an error from the registry part way through leaves the earlier operations
+    /// in place.
+    pub fn import<B: Backend>(
+        &self,
+        backend: &B,
+        base: &B::Key,
+        root: &U16CStr,
+    ) -> Result<Patch, Error> {
+        let patch = self.plan_import(backend, base, root)?;
+        patch.apply_unchecked::<B, Error>(backend, base)?;
+        Ok(patch)
+    }
+}
+
+#[cfg(windows)]
+impl crate::RegKey {
+    /// Imports `file` onto this key, where `root` is the path in the file that stands for this key.
+    #[inline]
+    pub fn import_reg_file(&self, file: &RegFile, root: &U16CStr) -> Result<Patch, Error> {
+        file.import(&crate::backend::Win32Registry, self, root)
+    }
+
+    /// Reports what [`import_reg_file`](#method.import_reg_file) would change, without changing
+    /// anything.
+    #[inline]
+    pub fn plan_reg_file_import(&self, file: &RegFile, root: &U16CStr) -> Result<Patch, Error> {
+        file.plan_import(&crate::backend::Win32Registry, self, root)
+    }
+}
+
+/// Strips `root` from the start of `path`, comparing without regard to case.
+fn relative_path<'a>(root: &[u16], path: &'a [u16]) -> Option<&'a [u16]> {
+    let path = trim_separators(path);
+    let root = trim_separators(root);
+    if root.is_empty() {
+        return Some(path);
+    }
+
+    match path.get(root.len()) {
+        _ if path.len() < root.len() || !eq_ignore_case(&path[..root.len()], root) => None,
+        None => Some(&[]),
+        Some(&SEPARATOR) => Some(&path[root.len() + 1..]),
+        Some(_) => None,
+    }
+}
+
+fn trim_separators(path: &[u16]) -> &[u16] {
+    let start = path
+        .iter()
+        .position(|x| *x != SEPARATOR)
+        .unwrap_or(path.len());
+    let end = path
+        .iter()
+        .rposition(|x| *x != SEPARATOR)
+        .map_or(start, |x| x + 1);
+    &path[start..end]
+}
+
+/// Whether `path` is `ancestor` or somewhere below it.
+fn is_within(path: &[u16], ancestor: &[u16]) -> bool {
+    relative_path(ancestor, path).is_some()
+}
+
+fn to_ucstring(path: &[u16]) -> U16CString {
+    // Safety: paths come from section headers, which cannot contain nuls.
+    unsafe { U16CString::from_vec_unchecked(path.to_vec()) }
+}
+
+/// Tracks the state of the registry as the operations planned so far would leave it.
+struct Planner<'a, B: Backend> {
+    backend: &'a B,
+    base: &'a B::Key,
+    /// Keys created by the plan, which exist along with their parents.
+    created: Vec<Vec<u16>>,
+    /// Keys deleted by the plan, which do not exist along with everything below them, unless
+    /// created again.
+    deleted: Vec<Vec<u16>>,
+    /// Values set or deleted by the plan.
+    values: Vec<(Vec<u16>, U16CString, Option<Data>)>,
+    operations: Vec<Operation>,
+}
+
+impl<B: Backend> Planner<'_, B> {
+    fn key_exists(&self, path: &[u16]) -> Result<bool, Error> {
+        if path.is_empty() || self.created.iter().any(|x| is_within(x, path)) {
+            return Ok(true);
+        }
+        if self.deleted.iter().any(|x| is_within(path, x)) {
+            return Ok(false);
+        }
+
+        match self
+            .backend
+            .open_key(self.base, to_ucstring(path), Security::Read)
+        {
+            Ok(_) => Ok(true),
+            Err(key::Error::NotFound(..)) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn value(&self, path: &[u16], name: &U16CString) -> Result<Option<Data>, Error> {
+        let planned = self.values.iter().rev().find(|(p, n, _)| {
+            eq_ignore_case(p, path) && eq_ignore_case(n.as_slice(), name.as_slice())
+        });
+        if let Some((_, _, data)) = planned {
+            return Ok(data.clone());
+        }
+        if !self.key_exists(path)? || self.deleted.iter().any(|x| is_within(path, x)) {
+            return Ok(None);
+        }
+
+        // Keys created by the plan, and their new parents, are not in the registry yet.
+        let key = match self
+            .backend
+            .open_key(self.base, to_ucstring(path), Security::Read)
+        {
+            Ok(v) => v,
+            Err(key::Error::NotFound(..)) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        match self.backend.query_value(&key, name) {
+            Ok(v) => Ok(Some(v)),
+            Err(value::Error::NotFound(..)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn create_key(&mut self, path: &[u16]) -> Result<(), Error> {
+        if !self.key_exists(path)? {
+            self.operations.push(Operation::CreateKey {
+                path: to_ucstring(path),
+            });
+            self.created.push(path.to_vec());
+        }
+        Ok(())
+    }
+
+    fn delete_key(&mut self, path: &[u16]) -> Result<(), Error> {
+        if self.key_exists(path)? {
+            self.operations.push(Operation::DeleteKey {
+                path: to_ucstring(path),
+            });
+            self.created.retain(|x| !is_within(x, path));
+            self.values.retain(|(x, _, _)| !is_within(x, path));
+            self.deleted.push(path.to_vec());
+        }
+        Ok(())
+    }
+
+    fn set_value(&mut self, path: &[u16], name: U16CString, data: &Data) -> Result<(), Error> {
+        let old = self.value(path, &name)?;
+        if old.as_ref() == Some(data) {
+            return Ok(());
+        }
+
+        self.operations.push(Operation::SetValue {
+            path: to_ucstring(path),
+            name: name.clone(),
+            old,
+            new: data.clone(),
+        });
+        self.values.push((path.to_vec(), name, Some(data.clone())));
+        Ok(())
+    }
+
+    fn delete_value(&mut self, path: &[u16], name: U16CString) -> Result<(), Error> {
+        if let Some(old) = self.value(path, &name)? {
+            self.operations.push(Operation::DeleteValue {
+                path: to_ucstring(path),
+                name: name.clone(),
+                old,
+            });
+            self.values.push((path.to_vec(), name, None));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    use crate::backend::{MemoryKey, MemoryRegistry};
+    use crate::Hive;
+
+    fn s(x: &str) -> U16CString {
+        x.try_into().unwrap()
+    }
+
+    const ROOT: &str = r"HKEY_CURRENT_USER\Software";
+
+    fn sample_registry() -> (MemoryRegistry, MemoryKey) {
+        let registry = MemoryRegistry::new();
+        let hive = registry
+            .open_hive(Hive::CurrentUser, Security::AllAccess)
+            .unwrap();
+        let root = registry
+            .create_key(&hive, "Software", Security::AllAccess)
+            .unwrap();
+        let vendor = registry
+            .create_key(&root, "Vendor", Security::AllAccess)
+            .unwrap();
+        registry
+            .set_value(&vendor, "Version", &Data::U32(1))
+            .unwrap();
+        registry
+            .set_value(&vendor, "Path", &Data::String(s(r"C:\Vendor")))
+            .unwrap();
+        registry
+            .create_key(&root, r"Old\Nested", Security::AllAccess)
+            .unwrap();
+        (registry, root)
+    }
+
+    fn sample_file() -> RegFile {
+        r#"Windows Registry Editor Version 5.00
+
+[hkey_current_user\software\Vendor]
+"Version"=dword:00000001
+"Path"=-
+"Missing"=-
+@="default"
+
+[HKEY_CURRENT_USER\Software\Vendor\App]
+"Enabled"=dword:00000001
+
+[-HKEY_CURRENT_USER\Software\Old]
+
+[-HKEY_CURRENT_USER\Software\Gone]
+"#
+        .parse()
+        .unwrap()
+    }
+
+    #[test]
+    fn plan_is_a_dry_run() {
+        let (registry, root) = sample_registry();
+        let patch = sample_file()
+            .plan_import(&registry, &root, &s(ROOT))
+            .unwrap();
+
+        assert_eq!(
+            patch.operations,
+            vec![
+                Operation::DeleteValue {
+                    path: s("Vendor"),
+                    name: s("Path"),
+                    old: Data::String(s(r"C:\Vendor")),
+                },
+                Operation::SetValue {
+                    path: s("Vendor"),
+                    name: s(""),
+                    old: None,
+                    new: Data::String(s("default")),
+                },
+                Operation::CreateKey {
+                    path: s(r"Vendor\App"),
+                },
+                Operation::SetValue {
+                    path: s(r"Vendor\App"),
+                    name: s("Enabled"),
+                    old: None,
+                    new: Data::U32(1),
+                },
+                Operation::DeleteKey { path: s("Old") },
+            ]
+        );
+        assert!(registry
+            .open_key(&root, r"Old\Nested", Security::Read)
+            .is_ok());
+        assert!(registry
+            .open_key(&root, r"Vendor\App", Security::Read)
+            .is_err());
+    }
+
+    #[test]
+    fn import_applies_changes() {
+        let (registry, root) = sample_registry();
+        let file = sample_file();
+        file.import(&registry, &root, &s(ROOT)).unwrap();
+
+        let vendor = registry.open_key(&root, "Vendor", Security::Read).unwrap();
+        assert!(registry.query_value(&vendor, "Path").is_err());
+        assert_eq!(
+            registry.query_value(&vendor, "").unwrap(),
+            Data::String(s("default"))
+        );
+        let app = registry
+            .open_key(&root, r"Vendor\App", Security::Read)
+            .unwrap();
+        assert_eq!(registry.query_value(&app, "Enabled").unwrap(), Data::U32(1));
+        assert!(registry.open_key(&root, "Old", Security::Read).is_err());
+
+        let patch = file.plan_import(&registry, &root, &s(ROOT)).unwrap();
+        assert_eq!(patch.operations, vec![]);
+    }
+
+    #[test]
+    fn plan_follows_earlier_sections() {
+        let (registry, root) = sample_registry();
+        let file: RegFile = r#"Windows Registry Editor Version 5.00
+
+[-HKEY_CURRENT_USER\Software\Vendor]
+
+[HKEY_CURRENT_USER\Software\Vendor]
+"Version"=dword:00000001
+"Path"=-
+"#
+        .parse()
+        .unwrap();
+
+        let patch = file.import(&registry, &root, &s(ROOT)).unwrap();
+        assert_eq!(
+            patch.operations,
+            vec![
+                Operation::DeleteKey { path: s("Vendor") },
+                Operation::CreateKey { path: s("Vendor") },
+                Operation::SetValue {
+                    path: s("Vendor"),
+                    name: s("Version"),
+                    old: None,
+                    new: Data::U32(1),
+                },
+            ]
+        );
+        let vendor = registry.open_key(&root, "Vendor", Security::Read).unwrap();
+        assert!(registry.query_value(&vendor, "Path").is_err());
+    }
+
+    #[test]
+    fn sections_outside_root() {
+        let (registry, root) = sample_registry();
+        let file: RegFile = "REGEDIT4\n\n[HKEY_CURRENT_USER\\SoftwareX]\n"
+            .parse()
+            .unwrap();
+        assert!(matches!(
+            file.plan_import(&registry, &root, &s(ROOT)),
+            Err(Error::OutsideRoot(_))
+        ));
+
+        let file: RegFile = "REGEDIT4\n\n[-HKEY_CURRENT_USER\\Software\\]\n"
+            .parse()
+            .unwrap();
+        assert!(matches!(
+            file.plan_import(&registry, &root, &s(ROOT)),
+            Err(Error::DeleteRoot(_))
+        ));
+    }
+}
@@ -0,0 +1,144 @@
+//! Reading and writing `.reg` files, as exported and imported by `regedit`.
+//!
+//! Both the `REGEDIT4` format, which stores text as ANSI, and the `Windows Registry Editor
+//! Version 5.00` format, which stores it as UTF-16LE, are supported.
+//!
+//! A file can also be used as a change set and imported onto a key with
+//! [`RegFile::import`](struct.RegFile.html#method.import), which honours deletions as well.
+//!
+//! Files that are edited by hand are better changed through a [`Document`](struct.Document.html),
+//! which keeps comments, blank lines and formatting so that writing it back only touches the lines
+//! that were changed.
+
+use utfx::U16CString;
+
+use crate::{key, value::Data};
+
+mod document;
+mod import;
+mod parse;
+mod write;
+
+pub use document::Document;
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Missing .reg file header")]
+    MissingHeader,
+
+    #[error("Invalid .reg file at line {0}: {1}")]
+    Parse(usize, String),
+
+    #[error("Value at line {0} cannot be decoded")]
+    Data(usize, #[source] crate::value::Error),
+
+    #[error("Invalid null found in name at line {0}")]
+    InvalidNul(usize, #[source] utfx::NulError<u16>),
+
+    #[error("Key '{0}' is outside of the key being imported into")]
+    OutsideRoot(String),
+
+    #[error("Key '{0}' is the key being imported into and cannot be deleted")]
+    DeleteRoot(String),
+
+    #[error("Error accessing key")]
+    Key(#[from] key::Error),
+
+    #[error("Error accessing value")]
+    Value(#[from] crate::value::Error),
+}
+
+/// The format of a `.reg` file, given by its header line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Version {
+    /// `REGEDIT4`, where text is stored in the ANSI code page.
+    Regedit4,
+    /// `Windows Registry Editor Version 5.00`, where text is stored as UTF-16LE.
+    Regedit5,
+}
+
+impl Version {
+    pub(crate) fn header(self) -> &'static str {
+        match self {
+            Version::Regedit4 => "REGEDIT4",
+            Version::Regedit5 => "Windows Registry Editor Version 5.00",
+        }
+    }
+
+    /// The encoding that `regedit` writes files of this version in.
+    #[inline]
+    pub fn encoding(self) -> Encoding {
+        match self {
+            Version::Regedit4 => Encoding::Latin1,
+            Version::Regedit5 => Encoding::Utf16,
+        }
+    }
+}
+
+/// How the text of a `.reg` file is stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    /// UTF-16LE with a byte order mark, as `regedit` writes version 5.00 files.
+    Utf16,
+    /// UTF-8 without a byte order mark.
+    Utf8,
+    /// UTF-8 with a byte order mark.
+    Utf8WithBom,
+    /// Latin-1, standing in for the ANSI code page that `regedit` writes `REGEDIT4` files in.
+    Latin1,
+}
+
+/// The contents of a `.reg` file: a list of key sections, in the order they appear.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegFile {
+    pub version: Version,
+    pub sections: Vec<Section>,
+}
+
+/// A `[HKEY_...\Path]` section, which creates the key and sets its values, or a
+/// `[-HKEY_...\Path]` section, which deletes the key and everything below it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    /// The full path of the key, starting with the name of its hive.
+    pub path: U16CString,
+    pub is_delete: bool,
+    pub entries: Vec<Entry>,
+}
+
+/// A `"Name"=...` line within a section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// The name of the value, or `None` for the default value, written as `@`.
+    pub name: Option<U16CString>,
+    pub value: EntryValue,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryValue {
+    /// Sets the value to this data.
+    Set(Data),
+    /// Deletes the value, written as `"Name"=-`.
+    Delete,
+}
+
+impl RegFile {
+    #[inline]
+    pub fn new(version: Version) -> RegFile {
+        RegFile {
+            version,
+            sections: vec![],
+        }
+    }
+}
+
+impl Section {
+    #[inline]
+    pub fn new(path: U16CString) -> Section {
+        Section {
+            path,
+            is_delete: false,
+            entries: vec![],
+        }
+    }
+}
@@ -0,0 +1,394 @@
+use std::str::FromStr;
+
+use utfx::U16CString;
+
+use super::{Document, Encoding, Entry, EntryValue, Error, RegFile, Section, Version};
+use crate::value::{parse_value_type_data_with, Data, ParseMode, Type};
+
+impl RegFile {
+    /// Parses a `.reg` file from its raw contents. UTF-16LE is detected by its byte order mark;
+    /// anything else is read as UTF-8 if it is valid, and as Latin-1 otherwise.
+    pub fn from_bytes(bytes: &[u8]) -> Result<RegFile, Error> {
+        RegFile::parse(&decode(bytes).0)
+    }
+
+    /// Parses the text of a `.reg` file.
+    #[inline]
+    pub fn parse(text: &str) -> Result<RegFile, Error> {
+        Ok(Document::parse(text)?.to_reg_file())
+    }
+}
+
+impl FromStr for RegFile {
+    type Err = Error;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RegFile::parse(s)
+    }
+}
+
+pub(crate) fn decode(bytes: &[u8]) -> (String, Encoding) {
+    if let Some(bytes) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+        let units = bytes
+            .chunks_exact(2)
+            .map(|x| u16::from_le_bytes([x[0], x[1]]))
+            .collect::<Vec<_>>();
+        return (String::from_utf16_lossy(&units), Encoding::Utf16);
+    }
+
+    let (bytes, encoding) = match bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        Some(bytes) => (bytes, Encoding::Utf8WithBom),
+        None => (bytes, Encoding::Utf8),
+    };
+    match std::str::from_utf8(bytes) {
+        Ok(v) => (v.to_string(), encoding),
+        Err(_) => (
+            bytes.iter().map(|x| char::from(*x)).collect(),
+            Encoding::Latin1,
+        ),
+    }
+}
+
+/// A line of a `.reg` file after joining any continuation lines onto it.
+pub(crate) struct LogicalLine<'a> {
+    /// The number of the physical line it starts on, counting from 1.
+    pub line_no: usize,
+    /// The joined text, without line breaks or continuation backslashes.
+    pub text: String,
+    /// The physical lines it was joined from, including their line breaks.
+    pub raw: &'a str,
+}
+
+/// Splits text into lines, joining lines that end with a backslash onto the next one.
+pub(crate) fn logical_lines(text: &str) -> Vec<LogicalLine<'_>> {
+    let mut lines = vec![];
+    let mut current: Option<(usize, usize, String)> = None;
+    let mut offset = 0;
+
+    for (i, physical) in text.split_inclusive('\n').enumerate() {
+        let line = physical.strip_suffix('\n').unwrap_or(physical);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let line = match &current {
+            Some(_) => line.trim_start(),
+            None => line,
+        };
+        let (line_no, start, mut joined) = current.take().unwrap_or((i + 1, offset, String::new()));
+        joined.push_str(line);
+        offset += physical.len();
+
+        let trimmed = joined.trim_end();
+        if trimmed.ends_with('\\') && !trimmed.trim_start().starts_with(';') {
+            let len = trimmed.len() - 1;
+            joined.truncate(len);
+            current = Some((line_no, start, joined));
+        } else {
+            lines.push(LogicalLine {
+                line_no,
+                text: joined,
+                raw: &text[start..offset],
+            });
+        }
+    }
+
+    lines.extend(current.map(|(line_no, start, joined)| LogicalLine {
+        line_no,
+        text: joined,
+        raw: &text[start..],
+    }));
+    lines
+}
+
+fn name(line_no: usize, s: &str) -> Result<U16CString, Error> {
+    U16CString::from_str(s).map_err(|e| Error::InvalidNul(line_no, e))
+}
+
+pub(crate) fn parse_section(line_no: usize, line: &str) -> Result<Section, Error> {
+    let path = line
+        .strip_prefix('[')
+        .and_then(|x| x.strip_suffix(']'))
+        .ok_or_else(|| Error::Parse(line_no, "unterminated key section".into()))?;
+    let (path, is_delete) = match path.strip_prefix('-') {
+        Some(path) => (path, true),
+        None => (path, false),
+    };
+    if path.is_empty() {
+        return Err(Error::Parse(line_no, "empty key path".into()));
+    }
+
+    Ok(Section {
+        path: name(line_no, path)?,
+        is_delete,
+        entries: vec![],
+    })
+}
+
+/// Reads a quoted string from the start of `s`, returning it and whatever follows the closing
+/// quote.
+pub(crate) fn parse_quoted(line_no: usize, s: &str) -> Result<(String, &str), Error> {
+    let mut chars = s
+        .strip_prefix('"')
+        .ok_or_else(|| Error::Parse(line_no, "expected a quoted string".into()))?
+        .char_indices();
+    let mut value = String::new();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((value, &s[i + 2..])),
+            '\\' => match chars.next() {
+                Some((_, c)) => value.push(c),
+                None => break,
+            },
+            c => value.push(c),
+        }
+    }
+
+    Err(Error::Parse(line_no, "unterminated string".into()))
+}
+
+pub(crate) fn parse_entry(line_no: usize, line: &str, version: Version) -> Result<Entry, Error> {
+    let (entry_name, rest) = match line.strip_prefix('@') {
+        Some(rest) => (None, rest),
+        None => {
+            let (entry_name, rest) = parse_quoted(line_no, line)?;
+            (Some(name(line_no, &entry_name)?), rest)
+        }
+    };
+
+    let value = rest
+        .trim_start()
+        .strip_prefix('=')
+        .ok_or_else(|| Error::Parse(line_no, "expected '='".into()))?
+        .trim();
+
+    Ok(Entry {
+        name: entry_name,
+        value: parse_value(line_no, value, version)?,
+    })
+}
+
+pub(crate) fn parse_value(
+    line_no: usize,
+    value: &str,
+    version: Version,
+) -> Result<EntryValue, Error> {
+    let invalid = |reason: &str| Error::Parse(line_no, reason.to_string());
+
+    if value == "-" {
+        return Ok(EntryValue::Delete);
+    }
+
+    if value.starts_with('"') {
+        let (s, rest) = parse_quoted(line_no, value)?;
+        if !rest.trim().is_empty() {
+            return Err(invalid("unexpected text after string"));
+        }
+        return Ok(EntryValue::Set(Data::String(name(line_no, &s)?)));
+    }
+
+    if let Some(hex) = value.strip_prefix("dword:") {
+        if hex.is_empty() || hex.len() > 8 {
+            return Err(invalid("invalid dword"));
+        }
+        let x = u32::from_str_radix(hex, 16).map_err(|_| invalid("invalid dword"))?;
+        return Ok(EntryValue::Set(Data::U32(x)));
+    }
+
+    let (ty, bytes) = match value.strip_prefix("hex:") {
+        Some(bytes) => (Type::Binary as u32, bytes),
+        None => {
+            let rest = value
+                .strip_prefix("hex(")
+                .ok_or_else(|| invalid("unknown value type"))?;
+            let end = rest
+                .find("):")
+                .ok_or_else(|| invalid("unknown value type"))?;
+            let ty =
+                u32::from_str_radix(&rest[..end], 16).map_err(|_| invalid("invalid value type"))?;
+            (ty, &rest[end + 2..])
+        }
+    };
+    let bytes = parse_hex_bytes(bytes).ok_or_else(|| invalid("invalid hex data"))?;
+
+    decode_data(ty, bytes, version)
+        .map(EntryValue::Set)
+        .map_err(|e| match e {
+            Some(e) => Error::Data(line_no, e),
+            None => invalid("data has the wrong length for its type"),
+        })
+}
+
+pub(crate) fn parse_hex_bytes(s: &str) -> Option<Vec<u8>> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .map(|x| match x.len() {
+            1 | 2 => u8::from_str_radix(x, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Decodes the bytes of a `hex(...)` value. `REGEDIT4` files store strings in the ANSI code
+/// page, which is read as Latin-1. String-typed bytes that do not widen into a valid string were
+/// written raw, and are kept as they are.
+fn decode_data(
+    ty: u32,
+    bytes: Vec<u8>,
+    version: Version,
+) -> Result<Data, Option<crate::value::Error>> {
+    let is_string = ty == Type::String as u32
+        || ty == Type::ExpandString as u32
+        || ty == Type::MultiString as u32;
+    if version == Version::Regedit4 && is_string {
+        let wide = bytes
+            .iter()
+            .flat_map(|x| u16::from(*x).to_le_bytes().to_vec())
+            .collect();
+        return Ok(match Data::from_raw(ty, wide) {
+            Data::Other { .. } => Data::Other { ty, bytes },
+            data => data,
+        });
+    }
+
+    parse_value_type_data_with(ty, &bytes, ParseMode::PreserveRaw).map_err(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    fn s(x: &str) -> U16CString {
+        x.try_into().unwrap()
+    }
+
+    fn set(name: Option<&str>, data: Data) -> Entry {
+        Entry {
+            name: name.map(s),
+            value: EntryValue::Set(data),
+        }
+    }
+
+    #[test]
+    fn parse_version_5() {
+        let text = "Windows Registry Editor Version 5.00\r\n\
+            \r\n\
+            ; A comment\r\n\
+            [HKEY_CURRENT_USER\\Software\\Vendor]\r\n\
+            @=\"Default \\\"quoted\\\" C:\\\\\"\r\n\
+            \"Version\"=dword:0000002a\r\n\
+            \"Blob\"=hex:01,02,\\\r\n  03,ff\r\n\
+            \"Path\"=hex(2):25,00,41,00,25,00,00,00\r\n\
+            \"Multi\"=hex(7):61,00,00,00,62,00,00,00,00,00\r\n\
+            \"Quad\"=hex(b):01,00,00,00,00,00,00,00\r\n\
+            \"Old\"=-\r\n\
+            \r\n\
+            [-HKEY_CURRENT_USER\\Software\\Old]\r\n";
+
+        let file = RegFile::parse(text).unwrap();
+        assert_eq!(file.version, Version::Regedit5);
+        assert_eq!(file.sections.len(), 2);
+
+        let vendor = &file.sections[0];
+        assert_eq!(vendor.path, s(r"HKEY_CURRENT_USER\Software\Vendor"));
+        assert!(!vendor.is_delete);
+        assert_eq!(
+            vendor.entries,
+            vec![
+                set(None, Data::String(s(r#"Default "quoted" C:\"#))),
+                set(Some("Version"), Data::U32(42)),
+                set(Some("Blob"), Data::Binary(vec![1, 2, 3, 0xff])),
+                set(Some("Path"), Data::ExpandString(s("%A%"))),
+                set(Some("Multi"), Data::MultiString(vec![s("a"), s("b")])),
+                set(Some("Quad"), Data::U64(1)),
+                Entry {
+                    name: Some(s("Old")),
+                    value: EntryValue::Delete,
+                },
+            ]
+        );
+
+        assert!(file.sections[1].is_delete);
+        assert!(file.sections[1].entries.is_empty());
+    }
+
+    #[test]
+    fn parse_regedit4() {
+        let text = "REGEDIT4\n\n[HKEY_LOCAL_MACHINE\\SOFTWARE\\Vendor]\n\
+            \"Path\"=hex(2):25,41,25,00\n\
+            \"Multi\"=hex(7):e9,00,00\n";
+        let file = RegFile::parse(text).unwrap();
+        assert_eq!(file.version, Version::Regedit4);
+        assert_eq!(
+            file.sections[0].entries,
+            vec![
+                set(Some("Path"), Data::ExpandString(s("%A%"))),
+                set(Some("Multi"), Data::MultiString(vec![s("é")])),
+            ]
+        );
+    }
+
+    #[test]
+    fn detect_encodings() {
+        let text = "Windows Registry Editor Version 5.00\r\n\r\n[HKEY_USERS\\Ключ]\r\n";
+
+        let utf16 = [0xFF, 0xFE]
+            .iter()
+            .copied()
+            .chain(text.encode_utf16().flat_map(|x| x.to_le_bytes().to_vec()))
+            .collect::<Vec<_>>();
+        let file = RegFile::from_bytes(&utf16).unwrap();
+        assert_eq!(file.sections[0].path, s("HKEY_USERS\\Ключ"));
+
+        let latin1 = b"REGEDIT4\r\n\r\n[HKEY_USERS\\Caf\xe9]\r\n";
+        let file = RegFile::from_bytes(latin1).unwrap();
+        assert_eq!(file.sections[0].path, s("HKEY_USERS\\Café"));
+    }
+
+    #[test]
+    fn invalid_files() {
+        assert!(matches!(
+            RegFile::parse("[HKEY_USERS\\A]"),
+            Err(Error::MissingHeader)
+        ));
+
+        let header = "Windows Registry Editor Version 5.00\n";
+        for (body, line) in &[
+            ("\"a\"=dword:1", 2),
+            ("[HKEY_USERS\\A\n", 2),
+            ("[HKEY_USERS\\A]\n\"a\"=dword:123456789", 3),
+            ("[HKEY_USERS\\A]\n\"a\"=hex:1,2,zz", 3),
+            ("[HKEY_USERS\\A]\n\"a\"=\"b", 3),
+            ("[HKEY_USERS\\A]\n\"a\"=\"b\" c", 3),
+            ("[HKEY_USERS\\A]\n\n\"a\" dword:1", 4),
+            ("[HKEY_USERS\\A]\n\"a\"=qword:1", 3),
+        ] {
+            let result = RegFile::parse(&format!("{}{}", header, body));
+            let actual = match result {
+                Err(Error::Parse(x, _)) | Err(Error::Data(x, _)) => x,
+                other => panic!("{:?}: {:?}", body, other),
+            };
+            assert_eq!(actual, *line, "{:?}", body);
+        }
+    }
+
+    #[test]
+    fn preserve_undecodable_data() {
+        let header = "Windows Registry Editor Version 5.00\n\n[HKEY_USERS\\A]\n";
+        for (line, ty, bytes) in &[
+            ("\"a\"=hex(4):01,02", 4, vec![1, 2]),
+            ("\"a\"=hex(2):41,00", 2, vec![0x41, 0]),
+            ("\"a\"=hex(100000):ff", 0x100000, vec![0xff]),
+        ] {
+            let text = format!("{}{}\n", header, line);
+            let file = RegFile::parse(&text).unwrap();
+            let expected = Data::Other {
+                ty: *ty,
+                bytes: bytes.clone(),
+            };
+            assert_eq!(file.sections[0].entries[0].value, EntryValue::Set(expected));
+            assert_eq!(RegFile::parse(&file.to_string()).unwrap(), file);
+        }
+    }
+}
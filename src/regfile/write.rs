@@ -0,0 +1,251 @@
+use std::convert::TryFrom;
+use std::fmt::{Display, Write};
+
+use utfx::U16CStr;
+
+use super::{Encoding, Entry, EntryValue, RegFile, Version};
+use crate::value::Data;
+
+/// Hex data is wrapped once a line reaches this many columns, as `regedit` does.
+const HEX_WRAP_COLUMN: usize = 76;
+
+impl RegFile {
+    /// Encodes the file the way `regedit` exports it: UTF-16LE with a byte order mark for version
+    /// 5.00, and ANSI for `REGEDIT4`, where characters outside of Latin-1 become `?`.
+    #[inline]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        encode(&self.to_string(), self.version.encoding())
+    }
+}
+
+pub(crate) fn encode(text: &str, encoding: Encoding) -> Vec<u8> {
+    match encoding {
+        Encoding::Utf16 => [0xFF, 0xFE]
+            .iter()
+            .copied()
+            .chain(text.encode_utf16().flat_map(|x| x.to_le_bytes().to_vec()))
+            .collect(),
+        Encoding::Utf8 => text.as_bytes().to_vec(),
+        Encoding::Utf8WithBom => [0xEF, 0xBB, 0xBF]
+            .iter()
+            .chain(text.as_bytes())
+            .copied()
+            .collect(),
+        Encoding::Latin1 => text
+            .chars()
+            .map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?'))
+            .collect(),
+    }
+}
+
+impl Display for RegFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}\r\n\r\n", self.version.header())?;
+
+        for section in &self.sections {
+            let prefix = if section.is_delete { "-" } else { "" };
+            write!(f, "[{}{}]\r\n", prefix, section.path.to_string_lossy())?;
+            for entry in &section.entries {
+                f.write_str(&format_entry(entry, self.version))?;
+                f.write_str("\r\n")?;
+            }
+            f.write_str("\r\n")?;
+        }
+
+        Ok(())
+    }
+}
+
+pub(crate) fn format_entry(entry: &Entry, version: Version) -> String {
+    let mut line = match &entry.name {
+        Some(name) => quote(name),
+        None => "@".to_string(),
+    };
+    line.push('=');
+
+    match &entry.value {
+        EntryValue::Delete => line.push('-'),
+        EntryValue::Set(data) => format_data(&mut line, data, version),
+    }
+    line
+}
+
+pub(crate) fn format_data(line: &mut String, data: &Data, version: Version) {
+    match data {
+        Data::String(s) if !s.as_slice().iter().any(|x| *x == 0x0A || *x == 0x0D) => {
+            line.push_str(&quote(s))
+        }
+        Data::U32(x) => {
+            let _ = write!(line, "dword:{:08x}", x);
+        }
+        Data::Binary(bytes) => {
+            line.push_str("hex:");
+            format_hex(line, bytes);
+        }
+        data => {
+            let _ = write!(line, "hex({:x}):", data.raw_type());
+            format_hex(line, &encode_data(data, version));
+        }
+    }
+}
+
+fn quote(s: &U16CStr) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.to_string_lossy().chars() {
+        if c == '\\' || c == '"' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The bytes of a value as written to a `.reg` file, where `REGEDIT4` stores strings as ANSI.
+/// [`Data::Other`] bytes are written unchanged, since they may not be valid UTF-16.
+fn encode_data(data: &Data, version: Version) -> Vec<u8> {
+    let bytes = data.to_bytes();
+    let is_string = matches!(
+        data,
+        Data::String(_) | Data::ExpandString(_) | Data::MultiString(_)
+    );
+
+    match version {
+        Version::Regedit4 if is_string => bytes
+            .chunks_exact(2)
+            .map(|x| u8::try_from(u16::from_le_bytes([x[0], x[1]])).unwrap_or(b'?'))
+            .collect(),
+        _ => bytes,
+    }
+}
+
+/// Appends comma-separated hex bytes, wrapping lines with a trailing backslash.
+pub(crate) fn format_hex(line: &mut String, bytes: &[u8]) {
+    let mut column = line.rsplit('\n').next().map_or(0, str::len);
+
+    for (i, byte) in bytes.iter().enumerate() {
+        let _ = write!(line, "{:02x}", byte);
+        column += 2;
+        if i + 1 == bytes.len() {
+            break;
+        }
+
+        line.push(',');
+        column += 1;
+        if column >= HEX_WRAP_COLUMN {
+            line.push_str("\\\r\n  ");
+            column = 2;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    use utfx::U16CString;
+
+    use crate::regfile::Section;
+    use crate::value::Type;
+
+    fn s(x: &str) -> U16CString {
+        x.try_into().unwrap()
+    }
+
+    fn set(name: &str, data: Data) -> Entry {
+        Entry {
+            name: Some(s(name)),
+            value: EntryValue::Set(data),
+        }
+    }
+
+    fn sample_file(version: Version) -> RegFile {
+        let mut vendor = Section::new(s(r"HKEY_CURRENT_USER\Software\Vendor"));
+        vendor.entries = vec![
+            Entry {
+                name: None,
+                value: EntryValue::Set(Data::String(s(r#"say "hi" C:\"#))),
+            },
+            set("Version", Data::U32(42)),
+            set("Path", Data::ExpandString(s("%A%"))),
+            set("Multi", Data::MultiString(vec![s("a"), s("é")])),
+            set("Big", Data::U32BE(1)),
+            set("Quad", Data::U64(1)),
+            set("Lines", Data::String(s("a\r\nb"))),
+            set("Blob", Data::Binary((0..40).collect())),
+            Entry {
+                name: Some(s("Old")),
+                value: EntryValue::Delete,
+            },
+        ];
+        let mut old = Section::new(s(r"HKEY_CURRENT_USER\Software\Old"));
+        old.is_delete = true;
+
+        RegFile {
+            version,
+            sections: vec![vendor, old],
+        }
+    }
+
+    #[test]
+    fn write_version_5() {
+        let text = sample_file(Version::Regedit5).to_string();
+        let expected = "Windows Registry Editor Version 5.00\r\n\
+            \r\n\
+            [HKEY_CURRENT_USER\\Software\\Vendor]\r\n\
+            @=\"say \\\"hi\\\" C:\\\\\"\r\n\
+            \"Version\"=dword:0000002a\r\n\
+            \"Path\"=hex(2):25,00,41,00,25,00,00,00\r\n\
+            \"Multi\"=hex(7):61,00,00,00,e9,00,00,00,00,00\r\n\
+            \"Big\"=hex(5):00,00,00,01\r\n\
+            \"Quad\"=hex(b):01,00,00,00,00,00,00,00\r\n\
+            \"Lines\"=hex(1):61,00,0d,00,0a,00,62,00,00,00\r\n\
+            \"Blob\"=hex:00,01,02,03,04,05,06,07,08,09,0a,0b,0c,0d,0e,0f,10,11,12,13,14,15,\\\r\n  \
+            16,17,18,19,1a,1b,1c,1d,1e,1f,20,21,22,23,24,25,26,27\r\n\
+            \"Old\"=-\r\n\
+            \r\n\
+            [-HKEY_CURRENT_USER\\Software\\Old]\r\n\
+            \r\n";
+        assert_eq!(text, expected);
+        assert!(text.lines().all(|x| x.len() <= 80));
+    }
+
+    #[test]
+    fn round_trip() {
+        for version in &[Version::Regedit5, Version::Regedit4] {
+            let file = sample_file(*version);
+            assert_eq!(RegFile::from_bytes(&file.to_bytes()).unwrap(), file);
+        }
+    }
+
+    #[test]
+    fn regedit4_is_ansi() {
+        let mut file = RegFile::new(Version::Regedit4);
+        let mut section = Section::new(s(r"HKEY_USERS\Ключ"));
+        section
+            .entries
+            .push(set("Path", Data::ExpandString(s("é"))));
+        file.sections.push(section);
+
+        let bytes = file.to_bytes();
+        assert!(bytes.starts_with(b"REGEDIT4\r\n\r\n[HKEY_USERS\\????]\r\n"));
+        assert!(bytes.ends_with(b"\"Path\"=hex(2):e9,00\r\n\r\n"));
+    }
+
+    #[test]
+    fn regedit4_keeps_raw_strings() {
+        let mut file = RegFile::new(Version::Regedit4);
+        let mut section = Section::new(s(r"HKEY_USERS\A"));
+        let odd = Data::Other {
+            ty: Type::String as u32,
+            bytes: vec![0x41, 0, 0x42],
+        };
+        section.entries.push(set("Odd", odd));
+        file.sections.push(section);
+
+        let bytes = file.to_bytes();
+        assert!(bytes.ends_with(b"\"Odd\"=hex(1):41,00,42\r\n\r\n"));
+        assert_eq!(RegFile::from_bytes(&bytes).unwrap(), file);
+    }
+}
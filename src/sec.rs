@@ -15,6 +15,26 @@ bitflags::bitflags! {
         const Read = 0x20019;
         const Execute = 0x20019;
         const AllAccess = 0xf003f;
+        const Delete = 0x10000;
+        const ReadControl = 0x20000;
+        const WriteDac = 0x40000;
+        const WriteOwner = 0x80000;
+        const Synchronize = 0x100000;
+        const AccessSystemSecurity = 0x1000000;
+        const MaximumAllowed = 0x2000000;
+        const GenericAll = 0x10000000;
+        const GenericExecute = 0x20000000;
+        const GenericWrite = 0x40000000;
+        const GenericRead = 0x80000000;
+    }
+}
+
+impl Security {
+    /// Keeps every bit of `bits`, including those without a named flag, so that access masks read
+    /// from a security descriptor are written back unchanged.
+    #[inline]
+    pub(crate) fn from_bits_retain(bits: u32) -> Security {
+        Security { bits }
     }
 }
 
@@ -0,0 +1,156 @@
+//! An owned, in-memory snapshot of a registry key and everything below it.
+
+use std::time::SystemTime;
+
+use utfx::{U16CStr, U16CString};
+
+use crate::util::{cmp_ignore_case, eq_ignore_case};
+use crate::value::Data;
+
+/// A registry key with its metadata, values and subkeys, detached from any backing store.
+///
+/// Key and value names are looked up case-insensitively, as they are by Windows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyTree {
+    pub name: U16CString,
+    pub class_name: Option<U16CString>,
+    pub last_written: SystemTime,
+    /// A self-relative security descriptor. Keys without one are given a default descriptor when
+    /// they are written to a hive file.
+    pub security: Option<Vec<u8>>,
+    pub values: Vec<(U16CString, Data)>,
+    pub subkeys: Vec<KeyTree>,
+}
+
+impl KeyTree {
+    pub fn new(name: U16CString) -> KeyTree {
+        KeyTree {
+            name,
+            class_name: None,
+            last_written: SystemTime::now(),
+            security: None,
+            values: vec![],
+            subkeys: vec![],
+        }
+    }
+
+    #[inline]
+    pub fn subkey(&self, name: &U16CStr) -> Option<&KeyTree> {
+        self.subkeys
+            .iter()
+            .find(|x| eq_ignore_case(x.name.as_slice(), name.as_slice()))
+    }
+
+    #[inline]
+    pub fn subkey_mut(&mut self, name: &U16CStr) -> Option<&mut KeyTree> {
+        self.subkeys
+            .iter_mut()
+            .find(|x| eq_ignore_case(x.name.as_slice(), name.as_slice()))
+    }
+
+    /// Returns the subkey at a backslash-separated `path`, creating any keys that do not exist.
+    pub fn create(&mut self, path: &U16CStr) -> &mut KeyTree {
+        let mut key = self;
+
+        for segment in path
+            .as_slice()
+            .split(|x| *x == u16::from(b'\\'))
+            .filter(|x| !x.is_empty())
+        {
+            // Safety: the segment was split out of a string without interior nuls.
+            let name = unsafe { U16CString::from_vec_unchecked(segment.to_vec()) };
+            let index = match key
+                .subkeys
+                .iter()
+                .position(|x| eq_ignore_case(x.name.as_slice(), segment))
+            {
+                Some(i) => i,
+                None => {
+                    key.subkeys.push(KeyTree::new(name));
+                    key.subkeys.len() - 1
+                }
+            };
+            key = &mut key.subkeys[index];
+        }
+
+        key
+    }
+
+    #[inline]
+    pub fn value(&self, name: &U16CStr) -> Option<&Data> {
+        self.values
+            .iter()
+            .find(|(x, _)| eq_ignore_case(x.as_slice(), name.as_slice()))
+            .map(|(_, data)| data)
+    }
+
+    /// Sets a value, replacing the data of any existing value with the same name.
+    pub fn set_value(&mut self, name: U16CString, data: Data) {
+        match self
+            .values
+            .iter_mut()
+            .find(|(x, _)| eq_ignore_case(x.as_slice(), name.as_slice()))
+        {
+            Some((_, existing)) => *existing = data,
+            None => self.values.push((name, data)),
+        }
+    }
+
+    /// Sorts subkeys, recursively, into the order the registry enumerates them in.
+    pub fn sort(&mut self) {
+        self.subkeys
+            .sort_by(|a, b| cmp_ignore_case(a.name.as_slice(), b.name.as_slice()));
+        for subkey in &mut self.subkeys {
+            subkey.sort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    fn s(x: &str) -> U16CString {
+        x.try_into().unwrap()
+    }
+
+    #[test]
+    fn create_nested_keys() {
+        let mut root = KeyTree::new(s("ROOT"));
+        root.create(&s(r"Software\Vendor"))
+            .set_value(s("Version"), Data::U32(1));
+        root.create(&s(r"SOFTWARE\vendor\App"));
+
+        let vendor = root
+            .subkey(&s("software"))
+            .and_then(|x| x.subkey(&s("VENDOR")))
+            .unwrap();
+        assert_eq!(vendor.value(&s("version")), Some(&Data::U32(1)));
+        assert_eq!(vendor.subkeys.len(), 1);
+        assert_eq!(root.subkeys.len(), 1);
+    }
+
+    #[test]
+    fn set_value_replaces() {
+        let mut root = KeyTree::new(s("ROOT"));
+        root.set_value(s("a"), Data::U32(1));
+        root.set_value(s("A"), Data::U32(2));
+        assert_eq!(root.values, vec![(s("a"), Data::U32(2))]);
+    }
+
+    #[test]
+    fn sort_subkeys() {
+        let mut root = KeyTree::new(s("ROOT"));
+        for name in &["b", "C", "a"] {
+            root.create(&s(name));
+        }
+        root.sort();
+        let names = root
+            .subkeys
+            .iter()
+            .map(|x| x.name.to_string_lossy())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["a", "b", "C"]);
+    }
+}
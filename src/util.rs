@@ -1,9 +1,13 @@
+#[cfg(windows)]
 use std::ops::{Deref, DerefMut};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+#[cfg(windows)]
 #[repr(transparent)]
 #[derive(Debug, Clone)]
 pub(crate) struct U16AlignedU8Vec(pub Vec<u8>);
 
+#[cfg(windows)]
 impl U16AlignedU8Vec {
     #[inline(always)]
     pub fn new(size: usize) -> U16AlignedU8Vec {
@@ -17,26 +21,9 @@ impl U16AlignedU8Vec {
         buf.truncate(size);
         U16AlignedU8Vec(buf)
     }
-
-    pub fn into_u16_vec(mut self) -> Vec<u16> {
-        let remainder = self.len() % 2;
-
-        if remainder > 0 {
-            self.0.push(0);
-        }
-        self.shrink_to_fit();
-
-        let (ptr, len, capacity) = (self.as_mut_ptr(), self.len(), self.capacity());
-        std::mem::forget(self);
-
-        // Safety: this is guaranteed to be aligned.
-        #[allow(clippy::cast_ptr_alignment)]
-        unsafe {
-            Vec::from_raw_parts(ptr as *mut u16, len / 2, capacity / 2)
-        }
-    }
 }
 
+#[cfg(windows)]
 impl Deref for U16AlignedU8Vec {
     type Target = Vec<u8>;
 
@@ -45,8 +32,71 @@ impl Deref for U16AlignedU8Vec {
     }
 }
 
+#[cfg(windows)]
 impl DerefMut for U16AlignedU8Vec {
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.0
     }
 }
+
+/// Compares two UTF-16 names the way the registry does, ignoring case.
+pub(crate) fn eq_ignore_case(a: &[u16], b: &[u16]) -> bool {
+    upper_case(a.iter().copied()).eq(upper_case(b.iter().copied()))
+}
+
+/// Compares a name that is decoded on the fly with a UTF-16 name, ignoring case.
+pub(crate) fn units_eq_ignore_case<I: IntoIterator<Item = u16>>(a: I, b: &[u16]) -> bool {
+    upper_case(a).eq(upper_case(b.iter().copied()))
+}
+
+/// Orders two UTF-16 names the way the registry sorts subkeys, ignoring case.
+pub(crate) fn cmp_ignore_case(a: &[u16], b: &[u16]) -> std::cmp::Ordering {
+    upper_case(a.iter().copied()).cmp(upper_case(b.iter().copied()))
+}
+
+#[inline]
+fn upper_case<I: IntoIterator<Item = u16>>(s: I) -> impl Iterator<Item = char> {
+    std::char::decode_utf16(s)
+        .map(|c| c.unwrap_or(std::char::REPLACEMENT_CHARACTER))
+        .flat_map(char::to_uppercase)
+}
+
+/// The number of 100-nanosecond intervals between 1601-01-01 and 1970-01-01.
+const FILETIME_UNIX_EPOCH: u64 = 116_444_736_000_000_000;
+
+/// Converts a Windows `FILETIME`, in 100-nanosecond intervals since 1601, to a `SystemTime`.
+///
+/// Times are often read from untrusted data, so they are clamped to `i64::MAX` intervals, the
+/// latest time a `SystemTime` can hold on Windows.
+pub(crate) fn filetime_to_system_time(filetime: u64) -> SystemTime {
+    let filetime = filetime.min(i64::MAX as u64);
+    let time = if filetime >= FILETIME_UNIX_EPOCH {
+        UNIX_EPOCH.checked_add(filetime_duration(filetime - FILETIME_UNIX_EPOCH))
+    } else {
+        UNIX_EPOCH.checked_sub(filetime_duration(FILETIME_UNIX_EPOCH - filetime))
+    };
+    time.unwrap_or(UNIX_EPOCH)
+}
+
+/// Converts a `SystemTime` to a Windows `FILETIME`, saturating at the bounds of the format.
+pub(crate) fn system_time_to_filetime(time: SystemTime) -> u64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => FILETIME_UNIX_EPOCH.saturating_add(duration_filetime(d)),
+        Err(e) => FILETIME_UNIX_EPOCH.saturating_sub(duration_filetime(e.duration())),
+    }
+}
+
+#[inline]
+fn filetime_duration(intervals: u64) -> Duration {
+    Duration::new(
+        intervals / 10_000_000,
+        (intervals % 10_000_000) as u32 * 100,
+    )
+}
+
+#[inline]
+fn duration_filetime(d: Duration) -> u64 {
+    d.as_secs()
+        .saturating_mul(10_000_000)
+        .saturating_add(u64::from(d.subsec_nanos() / 100))
+}
@@ -0,0 +1,268 @@
+use std::{convert::TryFrom, ffi::OsString, path::PathBuf, time::SystemTime};
+
+use utfx::{U16CStr, U16CString};
+
+use super::{Data, Error, Type};
+use crate::util::{filetime_to_system_time, system_time_to_filetime};
+
+#[inline]
+fn mismatch(expected: Type, data: &Data) -> Error {
+    Error::TypeMismatch(expected, data.raw_type())
+}
+
+/// The text of a `REG_SZ` or `REG_EXPAND_SZ` value. Environment variables are not expanded.
+#[inline]
+fn string(data: &Data) -> Result<&U16CStr, Error> {
+    match data {
+        Data::String(s) | Data::ExpandString(s) => Ok(s),
+        data => Err(mismatch(Type::String, data)),
+    }
+}
+
+impl TryFrom<Data> for u32 {
+    type Error = Error;
+
+    fn try_from(data: Data) -> Result<Self, Self::Error> {
+        match data {
+            Data::U32(x) | Data::U32BE(x) => Ok(x),
+            data => Err(mismatch(Type::U32, &data)),
+        }
+    }
+}
+
+impl TryFrom<Data> for u64 {
+    type Error = Error;
+
+    fn try_from(data: Data) -> Result<Self, Self::Error> {
+        match data {
+            Data::U64(x) => Ok(x),
+            data => Err(mismatch(Type::U64, &data)),
+        }
+    }
+}
+
+/// Any non-zero `REG_DWORD` is `true`.
+impl TryFrom<Data> for bool {
+    type Error = Error;
+
+    #[inline]
+    fn try_from(data: Data) -> Result<Self, Self::Error> {
+        u32::try_from(data).map(|x| x != 0)
+    }
+}
+
+impl TryFrom<Data> for String {
+    type Error = Error;
+
+    #[inline]
+    fn try_from(data: Data) -> Result<Self, Self::Error> {
+        Ok(string(&data)?.to_string()?)
+    }
+}
+
+impl TryFrom<Data> for OsString {
+    type Error = Error;
+
+    #[inline]
+    fn try_from(data: Data) -> Result<Self, Self::Error> {
+        Ok(string(&data)?.to_os_string())
+    }
+}
+
+impl TryFrom<Data> for PathBuf {
+    type Error = Error;
+
+    #[inline]
+    fn try_from(data: Data) -> Result<Self, Self::Error> {
+        OsString::try_from(data).map(PathBuf::from)
+    }
+}
+
+impl TryFrom<Data> for Vec<String> {
+    type Error = Error;
+
+    fn try_from(data: Data) -> Result<Self, Self::Error> {
+        match data {
+            Data::MultiString(x) => x
+                .iter()
+                .map(|s| s.to_string().map_err(Error::from))
+                .collect(),
+            data => Err(mismatch(Type::MultiString, &data)),
+        }
+    }
+}
+
+impl TryFrom<Data> for Vec<u8> {
+    type Error = Error;
+
+    fn try_from(data: Data) -> Result<Self, Self::Error> {
+        match data {
+            Data::Binary(x) => Ok(x),
+            data => Err(mismatch(Type::Binary, &data)),
+        }
+    }
+}
+
+/// A `REG_QWORD` holding a `FILETIME`.
+impl TryFrom<Data> for SystemTime {
+    type Error = Error;
+
+    #[inline]
+    fn try_from(data: Data) -> Result<Self, Self::Error> {
+        u64::try_from(data).map(filetime_to_system_time)
+    }
+}
+
+impl From<u32> for Data {
+    #[inline]
+    fn from(x: u32) -> Self {
+        Data::U32(x)
+    }
+}
+
+impl From<u64> for Data {
+    #[inline]
+    fn from(x: u64) -> Self {
+        Data::U64(x)
+    }
+}
+
+/// Stored as a `REG_DWORD` of 0 or 1.
+impl From<bool> for Data {
+    #[inline]
+    fn from(x: bool) -> Self {
+        Data::U32(u32::from(x))
+    }
+}
+
+impl From<Vec<u8>> for Data {
+    #[inline]
+    fn from(x: Vec<u8>) -> Self {
+        Data::Binary(x)
+    }
+}
+
+/// Stored as a `REG_QWORD` holding a `FILETIME`.
+impl From<SystemTime> for Data {
+    #[inline]
+    fn from(x: SystemTime) -> Self {
+        Data::U64(system_time_to_filetime(x))
+    }
+}
+
+impl TryFrom<&str> for Data {
+    type Error = Error;
+
+    #[inline]
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        Ok(Data::String(U16CString::from_str(s)?))
+    }
+}
+
+impl TryFrom<String> for Data {
+    type Error = Error;
+
+    #[inline]
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Data::try_from(s.as_str())
+    }
+}
+
+impl TryFrom<OsString> for Data {
+    type Error = Error;
+
+    #[inline]
+    fn try_from(s: OsString) -> Result<Self, Self::Error> {
+        Ok(Data::String(U16CString::from_os_str(s)?))
+    }
+}
+
+impl TryFrom<PathBuf> for Data {
+    type Error = Error;
+
+    #[inline]
+    fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
+        Data::try_from(path.into_os_string())
+    }
+}
+
+impl TryFrom<Vec<String>> for Data {
+    type Error = Error;
+
+    fn try_from(x: Vec<String>) -> Result<Self, Self::Error> {
+        x.iter()
+            .map(|s| U16CString::from_str(s).map_err(Error::from))
+            .collect::<Result<_, _>>()
+            .map(Data::MultiString)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::TryInto,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use super::*;
+
+    fn round_trip<T>(value: T, ty: Type)
+    where
+        T: TryFrom<Data, Error = Error> + TryInto<Data> + Clone + PartialEq + std::fmt::Debug,
+        <T as TryInto<Data>>::Error: std::fmt::Debug,
+    {
+        let data = value.clone().try_into().unwrap();
+        assert_eq!(data.as_type(), Some(ty));
+        assert_eq!(T::try_from(data).unwrap(), value);
+    }
+
+    #[test]
+    fn round_trip_values() {
+        round_trip(7u32, Type::U32);
+        round_trip(7u64, Type::U64);
+        round_trip(true, Type::U32);
+        round_trip(false, Type::U32);
+        round_trip("a b".to_string(), Type::String);
+        round_trip(OsString::from("a b"), Type::String);
+        round_trip(PathBuf::from(r"C:\Windows"), Type::String);
+        round_trip(vec!["a".to_string(), "b".to_string()], Type::MultiString);
+        round_trip(vec![1u8, 2, 3], Type::Binary);
+        round_trip(UNIX_EPOCH + Duration::from_secs(1_600_000_000), Type::U64);
+    }
+
+    #[test]
+    fn conversions_from_stored_types() {
+        assert_eq!(u32::try_from(Data::U32BE(3)).unwrap(), 3);
+        assert!(bool::try_from(Data::U32(2)).unwrap());
+        let s = U16CString::from_str("%TEMP%").unwrap();
+        assert_eq!(String::try_from(Data::ExpandString(s)).unwrap(), "%TEMP%");
+        assert_eq!(
+            SystemTime::try_from(Data::U64(116_444_736_000_000_000)).unwrap(),
+            UNIX_EPOCH
+        );
+        assert_eq!(
+            SystemTime::try_from(Data::U64(u64::MAX)).unwrap(),
+            SystemTime::try_from(Data::U64(i64::MAX as u64)).unwrap()
+        );
+    }
+
+    #[test]
+    fn type_mismatch() {
+        assert!(matches!(
+            u32::try_from(Data::U64(1)),
+            Err(Error::TypeMismatch(Type::U32, 11))
+        ));
+        assert!(matches!(
+            String::try_from(Data::Binary(vec![])),
+            Err(Error::TypeMismatch(Type::String, 3))
+        ));
+        assert!(matches!(
+            Vec::<u8>::try_from(Data::Other {
+                ty: 0x100000,
+                bytes: vec![]
+            }),
+            Err(Error::TypeMismatch(Type::Binary, 0x100000))
+        ));
+        assert!(matches!(Data::try_from("a\0b"), Err(Error::InvalidNul(_))));
+    }
+}
@@ -0,0 +1,705 @@
+#[cfg(windows)]
+use std::{convert::TryInto, ptr::null_mut};
+use std::{
+    convert::{Infallible, TryFrom},
+    fmt::Display,
+};
+
+use utfx::{U16CString, U16String};
+#[cfg(windows)]
+use winapi::shared::minwindef::HKEY;
+#[cfg(windows)]
+use winapi::um::winreg::{RegDeleteValueW, RegQueryValueExW, RegSetValueExW};
+
+#[cfg(windows)]
+use crate::util::U16AlignedU8Vec;
+
+mod convert;
+mod resource;
+
+pub use resource::{
+    FullResourceDescriptor, IoResource, IoResourceDescriptor, IoResourceList, Layout,
+    PartialResourceDescriptor, Resource, ResourceList, ResourceRequirementsList,
+};
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Error determining required buffer size for value '{0}'")]
+    BufferSize(String, #[source] std::io::Error),
+
+    #[error("Data not found for value with name '{0}'")]
+    NotFound(String, #[source] std::io::Error),
+
+    #[error("Permission denied for given value name: '{0}'")]
+    PermissionDenied(String, #[source] std::io::Error),
+
+    #[error("Unhandled type: 0x{0:x}")]
+    UnhandledType(u32),
+
+    #[error("Invalid buffer size for UTF-16 string: {0}")]
+    InvalidBufferSize(usize),
+
+    #[error("Invalid null found in string")]
+    InvalidNul(#[from] utfx::NulError<u16>),
+
+    #[error("Missing null terminator in string")]
+    MissingNul(#[from] utfx::MissingNulError<u16>),
+
+    #[error("Missing null terminator in multi string")]
+    MissingMultiNul,
+
+    #[error("Invalid UTF-16")]
+    InvalidUtf16(#[from] std::string::FromUtf16Error),
+
+    #[error("Invalid resource data: {0}")]
+    InvalidResourceData(&'static str),
+
+    #[error("Invalid data length for type 0x{0:x}: {1} bytes")]
+    InvalidDataLength(u32, usize),
+
+    #[error("Expected data of type {0:?}, found type 0x{1:x}")]
+    TypeMismatch(Type, u32),
+
+    #[error("An unknown IO error occurred for given value name: '{0}'")]
+    Unknown(String, #[source] std::io::Error),
+}
+
+impl From<Infallible> for Error {
+    fn from(_: Infallible) -> Self {
+        unsafe { std::hint::unreachable_unchecked() }
+    }
+}
+
+/// The raw type of a registry value, as stored alongside its data.
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Type {
+    None = 0,
+    String = 1,
+    ExpandString = 2,
+    Binary = 3,
+    U32 = 4,
+    U32BE = 5,
+    Link = 6,
+    MultiString = 7,
+    ResourceList = 8,
+    FullResourceDescriptor = 9,
+    ResourceRequirementsList = 10,
+    U64 = 11,
+}
+
+impl Type {
+    const MAX: u32 = 11;
+}
+
+/// A type-safe wrapper around Windows Registry value data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Data {
+    None,
+    String(U16CString),
+    ExpandString(U16CString),
+    Binary(Vec<u8>),
+    U32(u32),
+    U32BE(u32),
+    /// The target of a symbolic link key, as a native path such as
+    /// `\Registry\Machine\Software\Classes`. It is stored without a terminating null.
+    Link(U16String),
+    MultiString(Vec<U16CString>),
+    ResourceList(ResourceList),
+    FullResourceDescriptor(FullResourceDescriptor),
+    ResourceRequirementsList(ResourceRequirementsList),
+    U64(u64),
+    /// Data of a type this crate does not know, or which could not be decoded without losing
+    /// bytes, kept exactly as it was stored.
+    Other {
+        ty: u32,
+        bytes: Vec<u8>,
+    },
+}
+
+/// How raw value bytes are turned into [`Data`](enum.Data.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ParseMode {
+    /// Decode the data, returning an error for unknown types and data that is malformed for its
+    /// type.
+    Strict,
+    /// Decode the data only when encoding it again gives back the same bytes, and keep anything
+    /// else as [`Data::Other`](enum.Data.html#variant.Other). Strings with extra trailing nulls
+    /// are kept this way too.
+    PreserveRaw,
+}
+
+/// Reading values keeps their bytes by default, so that anything read can be written back.
+impl Default for ParseMode {
+    #[inline]
+    fn default() -> Self {
+        ParseMode::PreserveRaw
+    }
+}
+
+impl Display for Data {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Data::None => f.write_str("<None>"),
+            Data::String(s) => f.write_str(&s.to_string_lossy()),
+            Data::ExpandString(s) => f.write_str(&s.to_string_lossy()),
+            Data::Binary(s) => write!(
+                f,
+                "<{}>",
+                s.iter()
+                    .map(|x| format!("{:02x}", x))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+            Data::U32(x) => write!(f, "0x{:016x}", x),
+            Data::U32BE(x) => write!(f, "0x{:016x}", x),
+            Data::Link(x) => f.write_str(&x.to_string_lossy()),
+            Data::MultiString(x) => f
+                .debug_list()
+                .entries(x.iter().map(|x| x.to_string_lossy()))
+                .finish(),
+            Data::ResourceList(_) => f.write_str("<Resource List>"),
+            Data::FullResourceDescriptor(_) => f.write_str("<Full Resource Descriptor>"),
+            Data::ResourceRequirementsList(_) => f.write_str("<Resource Requirements List>"),
+            Data::U64(x) => write!(f, "0x{:032x}", x),
+            Data::Other { ty, bytes } => write!(
+                f,
+                "<0x{:x}: {}>",
+                ty,
+                bytes
+                    .iter()
+                    .map(|x| format!("{:02x}", x))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+        }
+    }
+}
+
+impl Data {
+    /// The registry type this data is stored as, or `None` for [`Data::Other`](#variant.Other)
+    /// with a type this crate does not know. [`raw_type`](#method.raw_type) gives the type of any
+    /// data.
+    #[inline]
+    pub fn as_type(&self) -> Option<Type> {
+        Type::try_from(self.raw_type()).ok()
+    }
+
+    /// The numeric registry type this data is stored as, including types this crate does not know.
+    pub fn raw_type(&self) -> u32 {
+        let ty = match self {
+            Data::None => Type::None,
+            Data::String(_) => Type::String,
+            Data::ExpandString(_) => Type::ExpandString,
+            Data::Binary(_) => Type::Binary,
+            Data::U32(_) => Type::U32,
+            Data::U32BE(_) => Type::U32BE,
+            Data::Link(_) => Type::Link,
+            Data::MultiString(_) => Type::MultiString,
+            Data::ResourceList(_) => Type::ResourceList,
+            Data::FullResourceDescriptor(_) => Type::FullResourceDescriptor,
+            Data::ResourceRequirementsList(_) => Type::ResourceRequirementsList,
+            Data::U64(_) => Type::U64,
+            Data::Other { ty, .. } => return *ty,
+        };
+        ty as u32
+    }
+
+    /// Encodes the data into the byte representation stored in the registry.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Data::None => vec![],
+            Data::String(s) => string_to_utf16_byte_vec(s),
+            Data::ExpandString(s) => string_to_utf16_byte_vec(s),
+            Data::Binary(x) => x.to_vec(),
+            Data::U32(x) => x.to_le_bytes().to_vec(),
+            Data::U32BE(x) => x.to_be_bytes().to_vec(),
+            Data::Link(x) => x
+                .as_slice()
+                .iter()
+                .flat_map(|x| x.to_le_bytes().to_vec())
+                .collect(),
+            Data::MultiString(x) => multi_string_bytes(x),
+            Data::ResourceList(x) => x.to_bytes(),
+            Data::FullResourceDescriptor(x) => x.to_bytes(),
+            Data::ResourceRequirementsList(x) => x.to_bytes(),
+            Data::U64(x) => x.to_le_bytes().to_vec(),
+            Data::Other { bytes, .. } => bytes.clone(),
+        }
+    }
+
+    /// Decodes a raw type and bytes, such as those returned by
+    /// [`RegKey::raw_value`](../struct.RegKey.html#method.raw_value). Anything that would not
+    /// encode back to the same bytes is kept as [`Data::Other`](#variant.Other).
+    pub fn from_raw(ty: u32, bytes: Vec<u8>) -> Data {
+        match parse_strict(ty, &bytes) {
+            Ok(data) if data.to_bytes() == bytes => data,
+            _ => Data::Other { ty, bytes },
+        }
+    }
+
+    /// The raw type and bytes this data is stored as, such as for
+    /// [`RegKey::set_raw_value`](../struct.RegKey.html#method.set_raw_value).
+    pub fn into_raw(self) -> (u32, Vec<u8>) {
+        match self {
+            Data::Other { ty, bytes } => (ty, bytes),
+            data => (data.raw_type(), data.to_bytes()),
+        }
+    }
+}
+
+impl From<(u32, Vec<u8>)> for Data {
+    #[inline]
+    fn from((ty, bytes): (u32, Vec<u8>)) -> Self {
+        Data::from_raw(ty, bytes)
+    }
+}
+
+impl From<Data> for (u32, Vec<u8>) {
+    #[inline]
+    fn from(data: Data) -> Self {
+        data.into_raw()
+    }
+}
+
+#[inline(always)]
+fn multi_string_bytes(s: &[U16CString]) -> Vec<u8> {
+    let mut vec = s
+        .iter()
+        .flat_map(string_to_utf16_byte_vec)
+        .collect::<Vec<u8>>();
+    vec.push(0);
+    vec.push(0);
+    vec
+}
+
+#[inline(always)]
+fn string_to_utf16_byte_vec(s: &U16CString) -> Vec<u8> {
+    s.to_owned()
+        .into_vec_with_nul()
+        .into_iter()
+        .flat_map(|x| x.to_le_bytes().to_vec())
+        .collect()
+}
+
+fn parse_wide_string_nul(vec: Vec<u16>) -> Result<U16CString, Error> {
+    Ok(U16CString::from_vec_with_nul(vec)?)
+}
+
+#[inline(always)]
+fn bytes_to_u16_vec(buf: &[u8]) -> Vec<u16> {
+    buf.chunks(2)
+        .map(|x| u16::from_le_bytes([x[0], *x.get(1).unwrap_or(&0)]))
+        .collect()
+}
+
+fn parse_wide_multi_string(vec: Vec<u16>) -> Result<Vec<U16CString>, Error> {
+    let len = vec.len();
+    if len <= 2 && vec.iter().all(|x| *x == 0) {
+        return Ok(vec![]);
+    }
+
+    if len < 2 || vec[len - 1] != 0 || vec[len - 2] != 0 {
+        return Err(Error::MissingMultiNul);
+    }
+
+    vec[0..len - 2]
+        .split(|x| *x == 0)
+        .map(U16CString::new)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(Error::InvalidNul)
+}
+
+#[cfg(windows)]
+#[inline]
+pub(crate) fn set_value<S>(base: HKEY, value_name: S, data: &Data) -> Result<(), Error>
+where
+    S: TryInto<U16CString>,
+    S::Error: Into<Error>,
+{
+    set_raw_value(base, value_name, data.raw_type(), &data.to_bytes())
+}
+
+#[cfg(windows)]
+pub(crate) fn set_raw_value<S>(
+    base: HKEY,
+    value_name: S,
+    ty: u32,
+    bytes: &[u8],
+) -> Result<(), Error>
+where
+    S: TryInto<U16CString>,
+    S::Error: Into<Error>,
+{
+    let value_name = value_name.try_into().map_err(Into::into)?;
+    let result = unsafe {
+        RegSetValueExW(
+            base,
+            value_name.as_ptr(),
+            0,
+            ty,
+            bytes.as_ptr(),
+            bytes.len() as u32,
+        )
+    };
+
+    if result != 0 {
+        let io_error = std::io::Error::from_raw_os_error(result);
+        let value_name = value_name
+            .to_string()
+            .unwrap_or_else(|_| "<unknown>".into());
+        return match io_error.kind() {
+            std::io::ErrorKind::NotFound => Err(Error::NotFound(value_name, io_error)),
+            std::io::ErrorKind::PermissionDenied => {
+                Err(Error::PermissionDenied(value_name, io_error))
+            }
+            _ => Err(Error::Unknown(value_name, io_error)),
+        };
+    }
+
+    Ok(())
+}
+
+#[cfg(windows)]
+#[inline]
+pub(crate) fn delete_value<S>(base: HKEY, value_name: S) -> Result<(), Error>
+where
+    S: TryInto<U16CString>,
+    S::Error: Into<Error>,
+{
+    let value_name = value_name.try_into().map_err(Into::into)?;
+    let result = unsafe { RegDeleteValueW(base, value_name.as_ptr()) };
+
+    if result != 0 {
+        let io_error = std::io::Error::from_raw_os_error(result);
+        let value_name = value_name
+            .to_string()
+            .unwrap_or_else(|_| "<unknown>".into());
+        return match io_error.kind() {
+            std::io::ErrorKind::NotFound => Err(Error::NotFound(value_name, io_error)),
+            std::io::ErrorKind::PermissionDenied => {
+                Err(Error::PermissionDenied(value_name, io_error))
+            }
+            _ => Err(Error::Unknown(value_name, io_error)),
+        };
+    }
+
+    Ok(())
+}
+
+#[cfg(windows)]
+#[inline]
+pub(crate) fn query_value<S>(base: HKEY, value_name: S, mode: ParseMode) -> Result<Data, Error>
+where
+    S: TryInto<U16CString>,
+    S::Error: Into<Error>,
+{
+    let (ty, bytes) = query_raw_value(base, value_name)?;
+    parse_value_type_data_with(ty, &bytes, mode)
+}
+
+#[cfg(windows)]
+pub(crate) fn query_raw_value<S>(base: HKEY, value_name: S) -> Result<(u32, Vec<u8>), Error>
+where
+    S: TryInto<U16CString>,
+    S::Error: Into<Error>,
+{
+    let value_name = value_name.try_into().map_err(Into::into)?;
+    let mut sz: u32 = 0;
+
+    // Get the required buffer size first
+    let result = unsafe {
+        RegQueryValueExW(
+            base,
+            value_name.as_ptr(),
+            null_mut(),
+            null_mut(),
+            null_mut(),
+            &mut sz,
+        )
+    };
+
+    if result != 0 {
+        return Err(Error::BufferSize(
+            value_name
+                .to_string()
+                .unwrap_or_else(|_| "<unknown>".into()),
+            std::io::Error::from_raw_os_error(result),
+        ));
+    }
+
+    let mut buf = U16AlignedU8Vec::new(sz as usize);
+    let mut ty = 0u32;
+
+    // Get the actual value
+    let result = unsafe {
+        RegQueryValueExW(
+            base,
+            value_name.as_ptr(),
+            null_mut(),
+            &mut ty,
+            buf.as_mut_ptr(),
+            &mut sz,
+        )
+    };
+
+    if result != 0 {
+        let io_error = std::io::Error::from_raw_os_error(result);
+        let value_name = value_name
+            .to_string()
+            .unwrap_or_else(|_| "<unknown>".into());
+        return match io_error.kind() {
+            std::io::ErrorKind::NotFound => Err(Error::NotFound(value_name, io_error)),
+            std::io::ErrorKind::PermissionDenied => {
+                Err(Error::PermissionDenied(value_name, io_error))
+            }
+            _ => Err(Error::Unknown(value_name, io_error)),
+        };
+    }
+
+    Ok((ty, buf[..sz as usize].to_vec()))
+}
+
+/// Decodes raw value bytes of the given registry type into [`Data`](enum.Data.html), in
+/// [`ParseMode::Strict`](enum.ParseMode.html#variant.Strict).
+#[inline]
+pub fn parse_value_type_data(ty: u32, buf: &[u8]) -> Result<Data, Error> {
+    parse_value_type_data_with(ty, buf, ParseMode::Strict)
+}
+
+/// Decodes raw value bytes of the given registry type into [`Data`](enum.Data.html). In
+/// [`ParseMode::PreserveRaw`](enum.ParseMode.html#variant.PreserveRaw) this never fails.
+pub fn parse_value_type_data_with(ty: u32, buf: &[u8], mode: ParseMode) -> Result<Data, Error> {
+    match mode {
+        ParseMode::Strict => parse_strict(ty, buf),
+        ParseMode::PreserveRaw => Ok(Data::from_raw(ty, buf.to_vec())),
+    }
+}
+
+fn parse_strict(raw_ty: u32, buf: &[u8]) -> Result<Data, Error> {
+    let ty = Type::try_from(raw_ty).map_err(|_| Error::UnhandledType(raw_ty))?;
+    let fixed = |len: usize| match buf.len() == len {
+        true => Ok(buf),
+        false => Err(Error::InvalidDataLength(raw_ty, buf.len())),
+    };
+
+    match ty {
+        Type::None => Ok(Data::None),
+        Type::String => parse_wide_string_nul(bytes_to_u16_vec(buf)).map(Data::String),
+        Type::ExpandString => parse_wide_string_nul(bytes_to_u16_vec(buf)).map(Data::ExpandString),
+        Type::Binary => Ok(Data::Binary(buf.to_vec())),
+        Type::U32 => Ok(Data::U32(u32::from_le_bytes(
+            <[u8; 4]>::try_from(fixed(4)?).unwrap(),
+        ))),
+        Type::U32BE => Ok(Data::U32BE(u32::from_be_bytes(
+            <[u8; 4]>::try_from(fixed(4)?).unwrap(),
+        ))),
+        Type::Link => Ok(Data::Link(U16String::from_vec(bytes_to_u16_vec(buf)))),
+        Type::MultiString => parse_wide_multi_string(bytes_to_u16_vec(buf)).map(Data::MultiString),
+        Type::ResourceList => ResourceList::from_bytes(buf).map(Data::ResourceList),
+        Type::FullResourceDescriptor => {
+            FullResourceDescriptor::from_bytes(buf).map(Data::FullResourceDescriptor)
+        }
+        Type::ResourceRequirementsList => {
+            ResourceRequirementsList::from_bytes(buf).map(Data::ResourceRequirementsList)
+        }
+        Type::U64 => Ok(Data::U64(u64::from_le_bytes(
+            <[u8; 8]>::try_from(fixed(8)?).unwrap(),
+        ))),
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid or unknown type value: {0:#x}")]
+pub struct TryIntoTypeError(u32);
+
+impl TryFrom<u32> for Type {
+    type Error = TryIntoTypeError;
+    fn try_from(ty: u32) -> Result<Self, Self::Error> {
+        if ty > Type::MAX {
+            return Err(TryIntoTypeError(ty));
+        }
+
+        // SAFETY: This is safe because we check if the value will fit just
+        // above and Type has repr(u32).
+        Ok(unsafe { std::mem::transmute::<u32, Type>(ty) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    fn round_trip(data: Data) {
+        let bytes = data.to_bytes();
+        let parsed = parse_value_type_data(data.raw_type(), &bytes).unwrap();
+        assert_eq!(parsed, data);
+    }
+
+    #[test]
+    fn round_trip_data() {
+        round_trip(Data::None);
+        round_trip(Data::String("Meow meow".try_into().unwrap()));
+        round_trip(Data::ExpandString(
+            "%SystemRoot%\\system32".try_into().unwrap(),
+        ));
+        round_trip(Data::Binary(vec![1, 2, 3, 4, 255]));
+        round_trip(Data::U32(0x1234FEFE));
+        round_trip(Data::U32BE(0x1234FEFE));
+        round_trip(Data::MultiString(vec![
+            "Meow meow".try_into().unwrap(),
+            "Woop woop".try_into().unwrap(),
+        ]));
+        round_trip(Data::U64(0x1234FEFE_1234FEFE));
+        round_trip(Data::ResourceList(ResourceList {
+            descriptors: vec![FullResourceDescriptor::new(Layout::X64)],
+        }));
+        round_trip(Data::FullResourceDescriptor(FullResourceDescriptor::new(
+            Layout::X64,
+        )));
+        round_trip(Data::ResourceRequirementsList(ResourceRequirementsList {
+            interface_type: 5,
+            bus_number: 0,
+            slot_number: 0,
+            alternatives: vec![],
+        }));
+    }
+
+    #[test]
+    fn parse_string_bytes() {
+        let bytes = [b'h', 0, b'i', 0, 0, 0];
+        assert_eq!(
+            parse_value_type_data(Type::String as u32, &bytes).unwrap(),
+            Data::String("hi".try_into().unwrap())
+        );
+    }
+
+    #[test]
+    fn parse_empty_multi_string() {
+        assert_eq!(
+            parse_value_type_data(Type::MultiString as u32, &[0, 0]).unwrap(),
+            Data::MultiString(vec![])
+        );
+        round_trip(Data::MultiString(vec![]));
+    }
+
+    #[test]
+    fn parse_link_target() {
+        let target = r"\Registry\Machine\Software";
+        let bytes = target
+            .encode_utf16()
+            .flat_map(|x| x.to_le_bytes().to_vec())
+            .collect::<Vec<_>>();
+        let data = parse_value_type_data(Type::Link as u32, &bytes).unwrap();
+        assert_eq!(data, Data::Link(U16String::from_str(target)));
+        assert_eq!(data.to_bytes(), bytes);
+        assert_eq!(data.to_string(), target);
+        round_trip(Data::Link(U16String::new()));
+    }
+
+    #[test]
+    fn parse_big_endian_u32() {
+        assert_eq!(
+            parse_value_type_data(Type::U32BE as u32, &[0x12, 0x34, 0xfe, 0xfe]).unwrap(),
+            Data::U32BE(0x1234FEFE)
+        );
+    }
+
+    #[test]
+    fn unhandled_type() {
+        assert!(matches!(
+            parse_value_type_data(0x100000, &[]),
+            Err(Error::UnhandledType(0x100000))
+        ));
+    }
+
+    #[test]
+    fn strict_mode_rejects_bad_lengths() {
+        for (ty, len) in &[
+            (Type::U32, 3),
+            (Type::U32BE, 0),
+            (Type::U64, 4),
+            (Type::U64, 9),
+        ] {
+            let buf = vec![1; *len];
+            assert!(
+                matches!(
+                    parse_value_type_data(*ty as u32, &buf),
+                    Err(Error::InvalidDataLength(x, y)) if x == *ty as u32 && y == *len
+                ),
+                "{:?}",
+                ty
+            );
+        }
+        assert!(parse_value_type_data(Type::String as u32, &[b'a', 0]).is_err());
+    }
+
+    #[test]
+    fn raw_round_trip() {
+        let sz = Type::String as u32;
+        for bytes in &[
+            vec![b'a', 0],
+            vec![b'a', 0, 0, 0, b'b', 0],
+            vec![b'a', 0, b'b'],
+            vec![b'a'],
+        ] {
+            let data = Data::from_raw(sz, bytes.clone());
+            assert!(matches!(data, Data::Other { ty, .. } if ty == sz));
+            assert_eq!(data.into_raw(), (sz, bytes.clone()));
+        }
+
+        let bytes = vec![b'a', 0, 0, 0];
+        let data = Data::from((sz, bytes.clone()));
+        assert_eq!(data, Data::String(U16CString::from_str("a").unwrap()));
+        assert_eq!(<(u32, Vec<u8>)>::from(data), (sz, bytes));
+        assert_eq!(Data::U32(1).into_raw(), (4, vec![1, 0, 0, 0]));
+    }
+
+    #[test]
+    fn preserve_raw_mode() {
+        let samples: &[(u32, &[u8])] = &[
+            (0x100000, &[1, 2, 3]),
+            (Type::U32 as u32, &[1, 2, 3]),
+            (Type::U64 as u32, &[1, 2, 3, 4]),
+            (Type::String as u32, &[b'a', 0]),
+            (Type::String as u32, &[b'a', 0, 0, 0, 0, 0]),
+            (Type::MultiString as u32, &[b'a', 0, 0, 0]),
+            (Type::None as u32, &[1]),
+            (Type::ResourceList as u32, &[1]),
+        ];
+        for (ty, buf) in samples {
+            let data = parse_value_type_data_with(*ty, buf, ParseMode::PreserveRaw).unwrap();
+            assert_eq!(
+                data,
+                Data::Other {
+                    ty: *ty,
+                    bytes: buf.to_vec()
+                }
+            );
+            assert_eq!(data.raw_type(), *ty);
+            assert_eq!(data.to_bytes(), *buf);
+        }
+
+        let data =
+            parse_value_type_data_with(Type::U32 as u32, &[1, 0, 0, 0], ParseMode::PreserveRaw);
+        assert_eq!(data.unwrap(), Data::U32(1));
+        assert_eq!(
+            Data::Other {
+                ty: 0x100000,
+                bytes: vec![]
+            }
+            .as_type(),
+            None
+        );
+        assert_eq!(
+            Data::Other {
+                ty: 4,
+                bytes: vec![1]
+            }
+            .as_type(),
+            Some(Type::U32)
+        );
+    }
+}
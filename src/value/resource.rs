@@ -0,0 +1,900 @@
+//! Hardware resource structures, stored in `REG_RESOURCE_LIST`, `REG_FULL_RESOURCE_DESCRIPTOR`
+//! and `REG_RESOURCE_REQUIREMENTS_LIST` values such as those under `HKLM\HARDWARE\RESOURCEMAP`.
+//!
+//! These mirror `CM_RESOURCE_LIST`, `CM_FULL_RESOURCE_DESCRIPTOR` and
+//! `IO_RESOURCE_REQUIREMENTS_LIST` from the Windows Driver Kit. A `CM_PARTIAL_RESOURCE_DESCRIPTOR`
+//! holds a pointer-sized affinity mask, so it is 16 bytes long when written by 32-bit Windows and
+//! 20 bytes long when written by 64-bit Windows. Which one was used is worked out from the length
+//! of the data, and kept as a [`Layout`](enum.Layout.html) so that the data is written back the
+//! same way. Data without any partial descriptors reads the same either way, and is taken to be
+//! x64.
+
+use std::convert::TryFrom;
+
+use super::Error;
+
+const TYPE_NULL: u8 = 0;
+const TYPE_PORT: u8 = 1;
+const TYPE_INTERRUPT: u8 = 2;
+const TYPE_MEMORY: u8 = 3;
+const TYPE_DMA: u8 = 4;
+const TYPE_DEVICE_SPECIFIC: u8 = 5;
+const TYPE_BUS_NUMBER: u8 = 6;
+const TYPE_MEMORY_LARGE: u8 = 7;
+
+/// The length of an `IO_RESOURCE_DESCRIPTOR`, which is the same on every architecture.
+const IO_DESCRIPTOR_LEN: usize = 32;
+
+/// The architecture a `CM_PARTIAL_RESOURCE_DESCRIPTOR` was written by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Layout {
+    /// 32-bit Windows, where partial descriptors are 16 bytes long.
+    X86,
+    /// 64-bit Windows, where partial descriptors are 20 bytes long.
+    X64,
+}
+
+impl Layout {
+    fn partial_descriptor_len(self) -> usize {
+        match self {
+            Layout::X86 => 16,
+            Layout::X64 => 20,
+        }
+    }
+}
+
+/// A `CM_RESOURCE_LIST`: the resources assigned to a device, grouped by the bus they are on.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ResourceList {
+    pub descriptors: Vec<FullResourceDescriptor>,
+}
+
+/// A `CM_FULL_RESOURCE_DESCRIPTOR`: the resources assigned on a single bus.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FullResourceDescriptor {
+    pub layout: Layout,
+    /// The `INTERFACE_TYPE` of the bus, such as 1 for ISA or 5 for PCI.
+    pub interface_type: i32,
+    pub bus_number: u32,
+    pub version: u16,
+    pub revision: u16,
+    pub descriptors: Vec<PartialResourceDescriptor>,
+}
+
+/// A `CM_PARTIAL_RESOURCE_DESCRIPTOR`: a single assigned resource.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartialResourceDescriptor {
+    /// The `CM_SHARE_DISPOSITION` of the resource.
+    pub share_disposition: u8,
+    /// Flags whose meaning depends on the type of resource.
+    pub flags: u16,
+    pub resource: Resource,
+}
+
+/// An assigned resource, decoded according to its `CmResourceType`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resource {
+    Null,
+    Port {
+        start: u64,
+        length: u32,
+    },
+    Interrupt {
+        level: u16,
+        group: u16,
+        vector: u32,
+        /// The processors the interrupt can be delivered to. Only the low 32 bits are kept by
+        /// the x86 layout.
+        affinity: u64,
+    },
+    Memory {
+        start: u64,
+        length: u32,
+    },
+    Dma {
+        channel: u32,
+        port: u32,
+        reserved: u32,
+    },
+    /// Data that only the driver understands, stored right after the descriptor.
+    DeviceSpecific {
+        reserved1: u32,
+        reserved2: u32,
+        data: Vec<u8>,
+    },
+    BusNumber {
+        start: u32,
+        length: u32,
+        reserved: u32,
+    },
+    /// Memory above 4 GiB, where the flags give how far `length` is shifted.
+    MemoryLarge {
+        start: u64,
+        length: u32,
+    },
+    /// Any other type of resource, with the raw bytes of its union.
+    Other {
+        ty: u8,
+        data: Vec<u8>,
+    },
+}
+
+/// An `IO_RESOURCE_REQUIREMENTS_LIST`: the sets of resources that a device can work with, of
+/// which one is chosen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceRequirementsList {
+    /// The `INTERFACE_TYPE` of the bus, such as 1 for ISA or 5 for PCI.
+    pub interface_type: i32,
+    pub bus_number: u32,
+    pub slot_number: u32,
+    pub alternatives: Vec<IoResourceList>,
+}
+
+/// An `IO_RESOURCE_LIST`: one alternative set of resources.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IoResourceList {
+    pub version: u16,
+    pub revision: u16,
+    pub descriptors: Vec<IoResourceDescriptor>,
+}
+
+/// An `IO_RESOURCE_DESCRIPTOR`: the range a single resource may be assigned from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IoResourceDescriptor {
+    /// Whether this is a preferred, alternative or default choice, as `IO_RESOURCE_*` flags.
+    pub option: u8,
+    /// The `CM_SHARE_DISPOSITION` of the resource.
+    pub share_disposition: u8,
+    /// Flags whose meaning depends on the type of resource.
+    pub flags: u16,
+    pub resource: IoResource,
+}
+
+/// A resource requirement, decoded according to its `CmResourceType`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IoResource {
+    Null,
+    Port {
+        length: u32,
+        alignment: u32,
+        minimum_address: u64,
+        maximum_address: u64,
+    },
+    Interrupt {
+        minimum_vector: u32,
+        maximum_vector: u32,
+        affinity_policy: u16,
+        group: u16,
+        priority_policy: u32,
+        targeted_processors: u64,
+    },
+    Memory {
+        length: u32,
+        alignment: u32,
+        minimum_address: u64,
+        maximum_address: u64,
+    },
+    Dma {
+        minimum_channel: u32,
+        maximum_channel: u32,
+    },
+    BusNumber {
+        length: u32,
+        minimum_bus_number: u32,
+        maximum_bus_number: u32,
+    },
+    MemoryLarge {
+        length: u32,
+        alignment: u32,
+        minimum_address: u64,
+        maximum_address: u64,
+    },
+    /// Any other type of resource, with the raw bytes of its union.
+    Other {
+        ty: u8,
+        data: Vec<u8>,
+    },
+}
+
+impl ResourceList {
+    pub fn from_bytes(buf: &[u8]) -> Result<ResourceList, Error> {
+        parse_exact(buf, |r, layout| {
+            let count = r.u32()?;
+            let descriptors = (0..count)
+                .map(|_| FullResourceDescriptor::read(r, layout))
+                .collect::<Result<_, _>>()?;
+            Ok(ResourceList { descriptors })
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = (self.descriptors.len() as u32).to_le_bytes().to_vec();
+        for descriptor in &self.descriptors {
+            descriptor.write(&mut buf);
+        }
+        buf
+    }
+}
+
+impl FullResourceDescriptor {
+    #[inline]
+    pub fn new(layout: Layout) -> FullResourceDescriptor {
+        FullResourceDescriptor {
+            layout,
+            interface_type: 0,
+            bus_number: 0,
+            version: 1,
+            revision: 1,
+            descriptors: vec![],
+        }
+    }
+
+    pub fn from_bytes(buf: &[u8]) -> Result<FullResourceDescriptor, Error> {
+        parse_exact(buf, FullResourceDescriptor::read)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        self.write(&mut buf);
+        buf
+    }
+
+    fn read(r: &mut Reader<'_>, layout: Layout) -> Result<FullResourceDescriptor, Error> {
+        let interface_type = r.i32()?;
+        let bus_number = r.u32()?;
+        let version = r.u16()?;
+        let revision = r.u16()?;
+        let count = r.u32()?;
+        let descriptors = (0..count)
+            .map(|_| PartialResourceDescriptor::read(r, layout))
+            .collect::<Result<_, _>>()?;
+
+        Ok(FullResourceDescriptor {
+            layout,
+            interface_type,
+            bus_number,
+            version,
+            revision,
+            descriptors,
+        })
+    }
+
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.interface_type.to_le_bytes());
+        buf.extend_from_slice(&self.bus_number.to_le_bytes());
+        buf.extend_from_slice(&self.version.to_le_bytes());
+        buf.extend_from_slice(&self.revision.to_le_bytes());
+        buf.extend_from_slice(&(self.descriptors.len() as u32).to_le_bytes());
+        for descriptor in &self.descriptors {
+            descriptor.write(buf, self.layout);
+        }
+    }
+}
+
+impl PartialResourceDescriptor {
+    fn read(r: &mut Reader<'_>, layout: Layout) -> Result<PartialResourceDescriptor, Error> {
+        let ty = r.u8()?;
+        let share_disposition = r.u8()?;
+        let flags = r.u16()?;
+        let union = r.bytes(layout.partial_descriptor_len() - 4)?;
+        let u = &mut Reader::new(union);
+
+        let resource = match ty {
+            TYPE_NULL => Resource::Null,
+            TYPE_PORT => Resource::Port {
+                start: u.u64()?,
+                length: u.u32()?,
+            },
+            TYPE_INTERRUPT => Resource::Interrupt {
+                level: u.u16()?,
+                group: u.u16()?,
+                vector: u.u32()?,
+                affinity: match layout {
+                    Layout::X86 => u64::from(u.u32()?),
+                    Layout::X64 => u.u64()?,
+                },
+            },
+            TYPE_MEMORY => Resource::Memory {
+                start: u.u64()?,
+                length: u.u32()?,
+            },
+            TYPE_DMA => Resource::Dma {
+                channel: u.u32()?,
+                port: u.u32()?,
+                reserved: u.u32()?,
+            },
+            TYPE_DEVICE_SPECIFIC => {
+                let len = u.u32()? as usize;
+                Resource::DeviceSpecific {
+                    reserved1: u.u32()?,
+                    reserved2: u.u32()?,
+                    data: r.bytes(len)?.to_vec(),
+                }
+            }
+            TYPE_BUS_NUMBER => Resource::BusNumber {
+                start: u.u32()?,
+                length: u.u32()?,
+                reserved: u.u32()?,
+            },
+            TYPE_MEMORY_LARGE => Resource::MemoryLarge {
+                start: u.u64()?,
+                length: u.u32()?,
+            },
+            ty => Resource::Other {
+                ty,
+                data: union.to_vec(),
+            },
+        };
+
+        Ok(PartialResourceDescriptor {
+            share_disposition,
+            flags,
+            resource,
+        })
+    }
+
+    fn write(&self, buf: &mut Vec<u8>, layout: Layout) {
+        let mut union = vec![];
+        let mut trailing: &[u8] = &[];
+        let ty = match &self.resource {
+            Resource::Null => TYPE_NULL,
+            Resource::Port { start, length } => {
+                push(&mut union, &[&start.to_le_bytes(), &length.to_le_bytes()]);
+                TYPE_PORT
+            }
+            Resource::Interrupt {
+                level,
+                group,
+                vector,
+                affinity,
+            } => {
+                push(
+                    &mut union,
+                    &[
+                        &level.to_le_bytes(),
+                        &group.to_le_bytes(),
+                        &vector.to_le_bytes(),
+                    ],
+                );
+                match layout {
+                    Layout::X86 => union.extend_from_slice(&(*affinity as u32).to_le_bytes()),
+                    Layout::X64 => union.extend_from_slice(&affinity.to_le_bytes()),
+                }
+                TYPE_INTERRUPT
+            }
+            Resource::Memory { start, length } => {
+                push(&mut union, &[&start.to_le_bytes(), &length.to_le_bytes()]);
+                TYPE_MEMORY
+            }
+            Resource::Dma {
+                channel,
+                port,
+                reserved,
+            } => {
+                push(
+                    &mut union,
+                    &[
+                        &channel.to_le_bytes(),
+                        &port.to_le_bytes(),
+                        &reserved.to_le_bytes(),
+                    ],
+                );
+                TYPE_DMA
+            }
+            Resource::DeviceSpecific {
+                reserved1,
+                reserved2,
+                data,
+            } => {
+                push(
+                    &mut union,
+                    &[
+                        &(data.len() as u32).to_le_bytes(),
+                        &reserved1.to_le_bytes(),
+                        &reserved2.to_le_bytes(),
+                    ],
+                );
+                trailing = data;
+                TYPE_DEVICE_SPECIFIC
+            }
+            Resource::BusNumber {
+                start,
+                length,
+                reserved,
+            } => {
+                push(
+                    &mut union,
+                    &[
+                        &start.to_le_bytes(),
+                        &length.to_le_bytes(),
+                        &reserved.to_le_bytes(),
+                    ],
+                );
+                TYPE_BUS_NUMBER
+            }
+            Resource::MemoryLarge { start, length } => {
+                push(&mut union, &[&start.to_le_bytes(), &length.to_le_bytes()]);
+                TYPE_MEMORY_LARGE
+            }
+            Resource::Other { ty, data } => {
+                union.extend_from_slice(data);
+                *ty
+            }
+        };
+        union.resize(layout.partial_descriptor_len() - 4, 0);
+
+        buf.push(ty);
+        buf.push(self.share_disposition);
+        buf.extend_from_slice(&self.flags.to_le_bytes());
+        buf.extend_from_slice(&union);
+        buf.extend_from_slice(trailing);
+    }
+}
+
+impl ResourceRequirementsList {
+    pub fn from_bytes(buf: &[u8]) -> Result<ResourceRequirementsList, Error> {
+        let r = &mut Reader::new(buf);
+        let _list_size = r.u32()?;
+        let interface_type = r.i32()?;
+        let bus_number = r.u32()?;
+        let slot_number = r.u32()?;
+        r.bytes(12)?;
+        let count = r.u32()?;
+
+        let alternatives = (0..count)
+            .map(|_| {
+                let version = r.u16()?;
+                let revision = r.u16()?;
+                let count = r.u32()?;
+                let descriptors = (0..count)
+                    .map(|_| IoResourceDescriptor::read(r.bytes(IO_DESCRIPTOR_LEN)?))
+                    .collect::<Result<_, _>>()?;
+                Ok(IoResourceList {
+                    version,
+                    revision,
+                    descriptors,
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+
+        if r.offset != buf.len() {
+            return Err(Error::InvalidResourceData(
+                "unexpected data after requirements list",
+            ));
+        }
+
+        Ok(ResourceRequirementsList {
+            interface_type,
+            bus_number,
+            slot_number,
+            alternatives,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut lists = vec![];
+        for list in &self.alternatives {
+            lists.extend_from_slice(&list.version.to_le_bytes());
+            lists.extend_from_slice(&list.revision.to_le_bytes());
+            lists.extend_from_slice(&(list.descriptors.len() as u32).to_le_bytes());
+            for descriptor in &list.descriptors {
+                descriptor.write(&mut lists);
+            }
+        }
+
+        let mut buf = vec![];
+        push(
+            &mut buf,
+            &[
+                &(32 + lists.len() as u32).to_le_bytes(),
+                &self.interface_type.to_le_bytes(),
+                &self.bus_number.to_le_bytes(),
+                &self.slot_number.to_le_bytes(),
+                &[0; 12],
+                &(self.alternatives.len() as u32).to_le_bytes(),
+            ],
+        );
+        buf.extend_from_slice(&lists);
+        buf
+    }
+}
+
+impl IoResourceDescriptor {
+    fn read(buf: &[u8]) -> Result<IoResourceDescriptor, Error> {
+        let r = &mut Reader::new(buf);
+        let option = r.u8()?;
+        let ty = r.u8()?;
+        let share_disposition = r.u8()?;
+        let _spare1 = r.u8()?;
+        let flags = r.u16()?;
+        let _spare2 = r.u16()?;
+        let union = &buf[r.offset..];
+
+        let resource = match ty {
+            TYPE_NULL => IoResource::Null,
+            TYPE_PORT | TYPE_MEMORY | TYPE_MEMORY_LARGE => {
+                let (length, alignment) = (r.u32()?, r.u32()?);
+                let (minimum_address, maximum_address) = (r.u64()?, r.u64()?);
+                match ty {
+                    TYPE_PORT => IoResource::Port {
+                        length,
+                        alignment,
+                        minimum_address,
+                        maximum_address,
+                    },
+                    TYPE_MEMORY => IoResource::Memory {
+                        length,
+                        alignment,
+                        minimum_address,
+                        maximum_address,
+                    },
+                    _ => IoResource::MemoryLarge {
+                        length,
+                        alignment,
+                        minimum_address,
+                        maximum_address,
+                    },
+                }
+            }
+            TYPE_INTERRUPT => IoResource::Interrupt {
+                minimum_vector: r.u32()?,
+                maximum_vector: r.u32()?,
+                affinity_policy: r.u16()?,
+                group: r.u16()?,
+                priority_policy: r.u32()?,
+                targeted_processors: r.u64()?,
+            },
+            TYPE_DMA => IoResource::Dma {
+                minimum_channel: r.u32()?,
+                maximum_channel: r.u32()?,
+            },
+            TYPE_BUS_NUMBER => IoResource::BusNumber {
+                length: r.u32()?,
+                minimum_bus_number: r.u32()?,
+                maximum_bus_number: r.u32()?,
+            },
+            ty => IoResource::Other {
+                ty,
+                data: union.to_vec(),
+            },
+        };
+
+        Ok(IoResourceDescriptor {
+            option,
+            share_disposition,
+            flags,
+            resource,
+        })
+    }
+
+    fn write(&self, buf: &mut Vec<u8>) {
+        let mut union = vec![];
+        let ty = match &self.resource {
+            IoResource::Null => TYPE_NULL,
+            IoResource::Port {
+                length,
+                alignment,
+                minimum_address,
+                maximum_address,
+            }
+            | IoResource::Memory {
+                length,
+                alignment,
+                minimum_address,
+                maximum_address,
+            }
+            | IoResource::MemoryLarge {
+                length,
+                alignment,
+                minimum_address,
+                maximum_address,
+            } => {
+                push(
+                    &mut union,
+                    &[
+                        &length.to_le_bytes(),
+                        &alignment.to_le_bytes(),
+                        &minimum_address.to_le_bytes(),
+                        &maximum_address.to_le_bytes(),
+                    ],
+                );
+                match self.resource {
+                    IoResource::Port { .. } => TYPE_PORT,
+                    IoResource::Memory { .. } => TYPE_MEMORY,
+                    _ => TYPE_MEMORY_LARGE,
+                }
+            }
+            IoResource::Interrupt {
+                minimum_vector,
+                maximum_vector,
+                affinity_policy,
+                group,
+                priority_policy,
+                targeted_processors,
+            } => {
+                push(
+                    &mut union,
+                    &[
+                        &minimum_vector.to_le_bytes(),
+                        &maximum_vector.to_le_bytes(),
+                        &affinity_policy.to_le_bytes(),
+                        &group.to_le_bytes(),
+                        &priority_policy.to_le_bytes(),
+                        &targeted_processors.to_le_bytes(),
+                    ],
+                );
+                TYPE_INTERRUPT
+            }
+            IoResource::Dma {
+                minimum_channel,
+                maximum_channel,
+            } => {
+                push(
+                    &mut union,
+                    &[
+                        &minimum_channel.to_le_bytes(),
+                        &maximum_channel.to_le_bytes(),
+                    ],
+                );
+                TYPE_DMA
+            }
+            IoResource::BusNumber {
+                length,
+                minimum_bus_number,
+                maximum_bus_number,
+            } => {
+                push(
+                    &mut union,
+                    &[
+                        &length.to_le_bytes(),
+                        &minimum_bus_number.to_le_bytes(),
+                        &maximum_bus_number.to_le_bytes(),
+                    ],
+                );
+                TYPE_BUS_NUMBER
+            }
+            IoResource::Other { ty, data } => {
+                union.extend_from_slice(data);
+                *ty
+            }
+        };
+        union.resize(IO_DESCRIPTOR_LEN - 8, 0);
+
+        buf.extend_from_slice(&[self.option, ty, self.share_disposition, 0]);
+        buf.extend_from_slice(&self.flags.to_le_bytes());
+        buf.extend_from_slice(&[0, 0]);
+        buf.extend_from_slice(&union);
+    }
+}
+
+fn push(buf: &mut Vec<u8>, fields: &[&[u8]]) {
+    fields.iter().for_each(|x| buf.extend_from_slice(x));
+}
+
+/// Parses `buf` with whichever layout reads it exactly, trying the x64 layout first.
+fn parse_exact<T>(
+    buf: &[u8],
+    parse: impl Fn(&mut Reader<'_>, Layout) -> Result<T, Error>,
+) -> Result<T, Error> {
+    let mut error = None;
+    for layout in &[Layout::X64, Layout::X86] {
+        let r = &mut Reader::new(buf);
+        match parse(r, *layout) {
+            Ok(v) if r.offset == buf.len() => return Ok(v),
+            Ok(_) => {}
+            Err(e) => error = error.or(Some(e)),
+        }
+    }
+    Err(error.unwrap_or(Error::InvalidResourceData(
+        "length does not match either descriptor layout",
+    )))
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Reader<'a> {
+        Reader { buf, offset: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let end = self.offset.checked_add(len);
+        let bytes = end
+            .and_then(|end| self.buf.get(self.offset..end))
+            .ok_or(Error::InvalidResourceData("unexpected end of data"))?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_le_bytes(
+            <[u8; 2]>::try_from(self.bytes(2)?).unwrap(),
+        ))
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(
+            <[u8; 4]>::try_from(self.bytes(4)?).unwrap(),
+        ))
+    }
+
+    fn i32(&mut self) -> Result<i32, Error> {
+        Ok(self.u32()? as i32)
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(
+            <[u8; 8]>::try_from(self.bytes(8)?).unwrap(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses bytes written the way `regedit` exports them, such as `01,00,ff`.
+    fn hex(s: &str) -> Vec<u8> {
+        s.split(',')
+            .map(|x| u8::from_str_radix(x.trim(), 16).unwrap())
+            .collect()
+    }
+
+    /// The resources of a PS/2 keyboard: two I/O ports and an edge-triggered interrupt.
+    fn keyboard(layout: Layout) -> ResourceList {
+        let port = |start| PartialResourceDescriptor {
+            share_disposition: 1,
+            flags: 0x11,
+            resource: Resource::Port { start, length: 1 },
+        };
+        let mut descriptor = FullResourceDescriptor::new(layout);
+        descriptor.interface_type = 15;
+        descriptor.descriptors = vec![
+            port(0x60),
+            port(0x64),
+            PartialResourceDescriptor {
+                share_disposition: 1,
+                flags: 1,
+                resource: Resource::Interrupt {
+                    level: 1,
+                    group: 0,
+                    vector: 1,
+                    affinity: match layout {
+                        Layout::X86 => 0xffff_ffff,
+                        Layout::X64 => u64::MAX,
+                    },
+                },
+            },
+        ];
+        ResourceList {
+            descriptors: vec![descriptor],
+        }
+    }
+
+    #[test]
+    fn resource_list_x64() {
+        let bytes = hex(
+            "01,00,00,00,0f,00,00,00,00,00,00,00,01,00,01,00,03,00,00,00,\
+             01,01,11,00,60,00,00,00,00,00,00,00,01,00,00,00,00,00,00,00,\
+             01,01,11,00,64,00,00,00,00,00,00,00,01,00,00,00,00,00,00,00,\
+             02,01,01,00,01,00,00,00,01,00,00,00,ff,ff,ff,ff,ff,ff,ff,ff",
+        );
+        let list = ResourceList::from_bytes(&bytes).unwrap();
+        assert_eq!(list, keyboard(Layout::X64));
+        assert_eq!(list.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn resource_list_x86() {
+        let bytes = hex(
+            "01,00,00,00,0f,00,00,00,00,00,00,00,01,00,01,00,03,00,00,00,\
+             01,01,11,00,60,00,00,00,00,00,00,00,01,00,00,00,\
+             01,01,11,00,64,00,00,00,00,00,00,00,01,00,00,00,\
+             02,01,01,00,01,00,00,00,01,00,00,00,ff,ff,ff,ff",
+        );
+        let list = ResourceList::from_bytes(&bytes).unwrap();
+        assert_eq!(list, keyboard(Layout::X86));
+        assert_eq!(list.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn full_descriptor_with_device_specific_data() {
+        let bytes = hex("00,00,00,00,00,00,00,00,00,00,00,00,02,00,00,00,\
+             03,01,00,00,00,00,0e,00,00,00,00,00,00,00,02,00,\
+             05,00,00,00,03,00,00,00,00,00,00,00,00,00,00,00,aa,bb,cc");
+        let descriptor = FullResourceDescriptor::from_bytes(&bytes).unwrap();
+        assert_eq!(descriptor.layout, Layout::X86);
+        assert_eq!(
+            descriptor.descriptors[0].resource,
+            Resource::Memory {
+                start: 0xe0000,
+                length: 0x20000,
+            }
+        );
+        assert_eq!(
+            descriptor.descriptors[1].resource,
+            Resource::DeviceSpecific {
+                reserved1: 0,
+                reserved2: 0,
+                data: vec![0xaa, 0xbb, 0xcc],
+            }
+        );
+        assert_eq!(descriptor.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn requirements_list() {
+        let list = ResourceRequirementsList {
+            interface_type: 5,
+            bus_number: 0,
+            slot_number: 0x10,
+            alternatives: vec![IoResourceList {
+                version: 1,
+                revision: 1,
+                descriptors: vec![
+                    IoResourceDescriptor {
+                        option: 0,
+                        share_disposition: 1,
+                        flags: 0,
+                        resource: IoResource::Memory {
+                            length: 0x1000,
+                            alignment: 0x1000,
+                            minimum_address: 0,
+                            maximum_address: 0xffff_ffff,
+                        },
+                    },
+                    IoResourceDescriptor {
+                        option: 0,
+                        share_disposition: 3,
+                        flags: 0,
+                        resource: IoResource::Interrupt {
+                            minimum_vector: 0x10,
+                            maximum_vector: 0x10,
+                            affinity_policy: 0,
+                            group: 0,
+                            priority_policy: 0,
+                            targeted_processors: 0,
+                        },
+                    },
+                    IoResourceDescriptor {
+                        option: 8,
+                        share_disposition: 0,
+                        flags: 0,
+                        resource: IoResource::Other {
+                            ty: 0x81,
+                            data: vec![1; 24],
+                        },
+                    },
+                ],
+            }],
+        };
+
+        let bytes = list.to_bytes();
+        assert_eq!(bytes.len(), 32 + 8 + 3 * 32);
+        assert_eq!(&bytes[..4], &[136, 0, 0, 0]);
+        assert_eq!(
+            &bytes[40..56],
+            &hex("00,03,01,00,00,00,00,00,00,10,00,00,00,10,00,00")[..]
+        );
+        assert_eq!(ResourceRequirementsList::from_bytes(&bytes).unwrap(), list);
+    }
+
+    #[test]
+    fn invalid_data() {
+        let bytes = keyboard(Layout::X64).to_bytes();
+        for len in &[0, 3, 10, 40, 79] {
+            assert!(ResourceList::from_bytes(&bytes[..*len]).is_err(), "{}", len);
+        }
+        assert!(ResourceList::from_bytes(&[0xff; 8]).is_err());
+        assert!(FullResourceDescriptor::from_bytes(&[]).is_err());
+        assert!(ResourceRequirementsList::from_bytes(&[0; 31]).is_err());
+        assert!(ResourceRequirementsList::from_bytes(&[0; 33]).is_err());
+    }
+}